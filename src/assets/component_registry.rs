@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::assets::gltf::{gltf_source, load_gltf_assets};
+use crate::assets::obj::load_obj;
+use crate::components::camera::Camera;
+use crate::components::color::Color;
+use crate::components::light::{DirectionalLight, PointLight, SpotLight};
+use crate::components::material::Material;
+use crate::components::mesh::Mesh;
+use crate::components::parent::Parent;
+use crate::components::texture::Texture;
+use crate::components::transform::Transform;
+use crate::primitives::three_d::cube::{Cube, CUBE_MESH_KEY};
+
+use legion::{Entity, World};
+use legion::query::{Read, IntoQuery, Query};
+
+// A component that can be written to a scene file. `Data` is what ends up in the file, which for most
+// components is just the component itself, but things like meshes are only stored as a reference.
+pub trait SceneComponent: Sized + Send + Sync + 'static {
+    type Data: Serialize + DeserializeOwned;
+
+    fn to_data(&self, context: &SaveContext) -> Result<Self::Data, String>;
+    fn from_data(data: Self::Data, context: &mut LoadContext) -> Result<Self, String>;
+}
+
+// entities are referred to by their id in the file
+pub struct SaveContext {
+    ids: HashMap<Entity, u64>,
+}
+
+impl SaveContext {
+    pub(crate) fn new(ids: HashMap<Entity, u64>) -> Self {
+        Self { ids }
+    }
+
+    pub fn id(&self, entity: Entity) -> Result<u64, String> {
+        self.ids
+            .get(&entity)
+            .copied()
+            .ok_or_else(|| format!("{:?} isn't part of the scene", entity))
+    }
+}
+
+pub struct LoadContext {
+    entities: HashMap<u64, Entity>,
+    // so every instance of a mesh is only loaded once
+    meshes: HashMap<String, Mesh>,
+    // images out of gltf files, which have no file of their own to load from
+    images: HashMap<String, Texture>,
+}
+
+impl LoadContext {
+    pub(crate) fn new(entities: HashMap<u64, Entity>) -> Self {
+        Self {
+            entities,
+            meshes: HashMap::new(),
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn entity(&self, id: u64) -> Result<Entity, String> {
+        self.entities
+            .get(&id)
+            .copied()
+            .ok_or_else(|| format!("there's no entity {} in the scene", id))
+    }
+
+    // Meshes are stored by key, which only says where they came from for cubes, obj and gltf models.
+    // Obj models are keyed by their path and the index of the model in the file, gltf primitives by
+    // their path, mesh and primitive index.
+    pub fn mesh(&mut self, key: &str) -> Result<Mesh, String> {
+        if let Some(mesh) = self.meshes.get(key) {
+            return Ok(mesh.clone());
+        }
+
+        if key == CUBE_MESH_KEY {
+            let (_, mesh) = Cube::new();
+            self.meshes.insert(key.to_string(), mesh);
+        } else if let Some(path) = gltf_source(key) {
+            self.load_gltf(path)?;
+        } else {
+            let path = key
+                .rsplitn(2, '#')
+                .nth(1)
+                .filter(|path| path.ends_with(".obj"))
+                .ok_or_else(|| format!("don't know where to load mesh {} from, only cubes, obj and gltf models can be referenced", key))?;
+
+            for obj_mesh in load_obj(path)? {
+                self.meshes.insert(obj_mesh.mesh.key.clone(), obj_mesh.mesh);
+            }
+        }
+
+        self.meshes
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("there's no mesh {}", key))
+    }
+
+    // textures are loaded from their own file, except for images out of gltf files which are keyed by
+    // the file and the index of the image in it
+    pub fn texture(&mut self, path: &str) -> Result<Texture, String> {
+        let source = match gltf_source(path) {
+            Some(source) => source,
+            None => return Ok(Texture::new(path)),
+        };
+
+        if !self.images.contains_key(path) {
+            self.load_gltf(source)?;
+        }
+
+        self.images
+            .get(path)
+            .cloned()
+            .ok_or_else(|| format!("there's no image {}", path))
+    }
+
+    fn load_gltf(&mut self, path: &str) -> Result<(), String> {
+        let (meshes, textures) = load_gltf_assets(path)?;
+
+        for mesh in meshes {
+            self.meshes.insert(mesh.key.clone(), mesh);
+        }
+        for texture in textures {
+            self.images.insert(texture.path.clone(), texture);
+        }
+
+        Ok(())
+    }
+}
+
+struct RegisteredComponent {
+    name: String,
+    entities: Box<dyn Fn(&World) -> Vec<Entity> + Send + Sync>,
+    save: Box<dyn Fn(&World, Entity, &SaveContext) -> Option<Result<Value, String>> + Send + Sync>,
+    load: Box<dyn Fn(&mut World, Entity, Value, &mut LoadContext) -> Result<(), String> + Send + Sync>,
+}
+
+// The components scenes know about, by the name they're stored under. Anything not registered is left
+// out when saving, and is an error when loading so typos in hand written scenes get noticed.
+pub struct ComponentRegistry {
+    components: Vec<RegisteredComponent>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self {
+            components: vec![],
+        }
+    }
+
+    pub fn register<T: SceneComponent>(&mut self, name: &str) {
+        if self.components.iter().any(|component| component.name == name) {
+            log::warn!("scene component {} is already registered, keeping the first one", name);
+            return;
+        }
+
+        let component_name = name.to_string();
+
+        self.components.push(RegisteredComponent {
+            name: name.to_string(),
+            entities: Box::new(|world| {
+                <Read<T>>::query()
+                    .iter_entities(world)
+                    .map(|(entity, _)| entity)
+                    .collect()
+            }),
+            save: Box::new(|world, entity, context| {
+                world.entity_data::<T>(entity).map(|component| {
+                    component
+                        .to_data(context)
+                        .and_then(|data| serde_json::to_value(data).map_err(|e| e.to_string()))
+                })
+            }),
+            load: Box::new(move |world, entity, value, context| {
+                let data = serde_json::from_value::<T::Data>(value)
+                    .map_err(|e| format!("invalid {}: {}", component_name, e))?;
+                let component = T::from_data(data, context)?;
+
+                world.mutate_entity(entity, |entity| entity.add_component(component));
+                Ok(())
+            }),
+        });
+    }
+
+    pub fn with<T: SceneComponent>(mut self, name: &str) -> Self {
+        self.register::<T>(name);
+        self
+    }
+
+    // every entity with at least one registered component, in registration order
+    pub(crate) fn entities(&self, world: &World) -> Vec<Entity> {
+        let mut entities = Vec::new();
+
+        for component in self.components.iter() {
+            for entity in (component.entities)(world) {
+                if !entities.contains(&entity) {
+                    entities.push(entity);
+                }
+            }
+        }
+
+        entities
+    }
+
+    pub(crate) fn save(&self, world: &World, entity: Entity, context: &SaveContext) -> Result<Vec<(String, Value)>, String> {
+        self.components
+            .iter()
+            .filter_map(|component| {
+                (component.save)(world, entity, context).map(|value| {
+                    value
+                        .map(|value| (component.name.clone(), value))
+                        .map_err(|e| format!("can't save {} of {:?}: {}", component.name, entity, e))
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn load(&self, world: &mut World, entity: Entity, name: &str, value: Value, context: &mut LoadContext) -> Result<(), String> {
+        let component = self.components
+            .iter()
+            .find(|component| component.name == name)
+            .ok_or_else(|| format!("unknown component {}", name))?;
+
+        (component.load)(world, entity, value, context)
+    }
+}
+
+// everything the engine itself can put in a scene
+impl Default for ComponentRegistry {
+    fn default() -> Self {
+        Self::new()
+            .with::<Transform>("transform")
+            .with::<Parent>("parent")
+            .with::<Mesh>("mesh")
+            .with::<Texture>("texture")
+            .with::<Material>("material")
+            .with::<Color>("color")
+            .with::<Camera>("camera")
+            .with::<DirectionalLight>("directional_light")
+            .with::<PointLight>("point_light")
+            .with::<SpotLight>("spot_light")
+    }
+}
@@ -0,0 +1,306 @@
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
+use legion::{Entity, World};
+
+use crate::components::children::Children;
+use crate::components::global_transform::GlobalTransform;
+use crate::components::material::{Material, ShadingModel};
+use crate::components::mesh::Mesh;
+use crate::components::parent::Parent;
+use crate::components::texture::{ColorSpace, FilterMode, SamplerOptions, Texture, TexturePixels, WrapMode};
+use crate::components::transform::Transform;
+use crate::primitives::vertex::{generate_normals, Vertex};
+use crate::utils::data_path;
+
+// Imports the default scene (or the first one) of a .gltf or .glb file into the world below `parent`, if
+// there is one, and returns every entity it spawned. `path` is relative to the data directory like
+// texture paths are. Either the whole file makes it in or none of it does.
+//
+// Every node becomes an entity with its local Transform, a Parent pointing at the node above it and
+// Children, and every triangle primitive of a node's mesh becomes a child entity with a Mesh, a
+// Material, and a Texture for the base color map if it has one. Images are decoded up front, including
+// embedded ones.
+pub fn load_gltf(world: &mut World, path: &str, parent: Option<Entity>) -> Result<Vec<Entity>, String> {
+    let (document, buffers, images) = ::gltf::import(data_path(path))
+        .map_err(|e| format!("failed to load {}: {}", path, e))?;
+
+    let textures = images
+        .iter()
+        .enumerate()
+        .map(|(image_index, image)| image_texture(path, image_index, image))
+        .collect::<Result<Vec<Texture>, String>>()?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or(format!("{} has no scenes", path))?;
+
+    let import = Import {
+        path,
+        buffers: &buffers,
+        textures: &textures,
+    };
+
+    let parent_global = parent
+        .and_then(|parent| world.entity_data::<GlobalTransform>(parent).map(|global| global.0))
+        .unwrap_or_else(Matrix4::identity);
+
+    let mut spawned = Vec::new();
+    for node in scene.nodes() {
+        if let Err(e) = import.spawn_node(world, node, parent, parent_global, &mut spawned) {
+            for entity in spawned {
+                world.delete(entity);
+            }
+
+            return Err(e);
+        }
+    }
+
+    if let Some(parent) = parent {
+        if world.entity_data::<Children>(parent).is_none() {
+            world.mutate_entity(parent, |entity| entity.add_component(Children::default()));
+        }
+    }
+
+    Ok(spawned)
+}
+
+// The meshes and decoded images of a .gltf or .glb file under the keys `load_gltf` gives them, so a
+// scene can refer to them without spawning the whole file again.
+pub(crate) fn load_gltf_assets(path: &str) -> Result<(Vec<Mesh>, Vec<Texture>), String> {
+    let (document, buffers, images) = ::gltf::import(data_path(path))
+        .map_err(|e| format!("failed to load {}: {}", path, e))?;
+
+    let textures = images
+        .iter()
+        .enumerate()
+        .map(|(image_index, image)| image_texture(path, image_index, image))
+        .collect::<Result<Vec<Texture>, String>>()?;
+
+    let import = Import {
+        path,
+        buffers: &buffers,
+        textures: &textures,
+    };
+
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        for (primitive_index, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() == ::gltf::mesh::Mode::Triangles {
+                let material = import.material(&primitive.material());
+                meshes.push(import.primitive_mesh(&mesh, primitive_index, &primitive, &material)?);
+            }
+        }
+    }
+
+    Ok((meshes, textures))
+}
+
+// the file a mesh or image key made up by `load_gltf` points into
+pub(crate) fn gltf_source(key: &str) -> Option<&str> {
+    key.rsplitn(2, '#')
+        .nth(1)
+        .filter(|path| path.ends_with(".gltf") || path.ends_with(".glb"))
+}
+
+struct Import<'a> {
+    path: &'a str,
+    buffers: &'a [::gltf::buffer::Data],
+    textures: &'a [Texture],
+}
+
+impl<'a> Import<'a> {
+    // `parent_global` is only there so everything starts out in the right place, transform propagation
+    // takes over from the first frame on
+    fn spawn_node(&self, world: &mut World, node: ::gltf::Node, parent: Option<Entity>, parent_global: Matrix4<f32>, spawned: &mut Vec<Entity>) -> Result<Entity, String> {
+        let transform = local_transform(&node);
+        let global = GlobalTransform(parent_global * transform.matrix());
+
+        let entity = match parent {
+            Some(parent) => world.insert_from((), vec![(transform, global, Children::default(), Parent(parent))])[0],
+            None => world.insert_from((), vec![(transform, global, Children::default())])[0],
+        };
+        spawned.push(entity);
+
+        if let Some(mesh) = node.mesh() {
+            for (primitive_index, primitive) in mesh.primitives().enumerate() {
+                if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                    log::warn!("skipping primitive {} of mesh {} in {}, only triangles are supported", primitive_index, mesh.index(), self.path);
+                    continue;
+                }
+
+                let material = self.material(&primitive.material());
+                let primitive_mesh = self.primitive_mesh(&mesh, primitive_index, &primitive, &material)?;
+
+                // primitives sit exactly where their node is
+                let primitive_transform = Transform::new();
+
+                let primitive_entity = match material.base_color_texture.clone() {
+                    Some(texture) => world.insert_from((), vec![(primitive_transform, global, primitive_mesh, material, texture, Parent(entity))])[0],
+                    None => world.insert_from((), vec![(primitive_transform, global, primitive_mesh, material, Parent(entity))])[0],
+                };
+                spawned.push(primitive_entity);
+            }
+        }
+
+        for child in node.children() {
+            self.spawn_node(world, child, Some(entity), global.0, spawned)?;
+        }
+
+        Ok(entity)
+    }
+
+    // keyed by mesh rather than node so nodes that reuse a mesh get drawn instanced
+    fn primitive_mesh(&self, mesh: &::gltf::Mesh, primitive_index: usize, primitive: &::gltf::Primitive, material: &Material) -> Result<Mesh, String> {
+        let (vertices, indices) = self.primitive_geometry(primitive, material.base_color_factor)
+            .map_err(|e| format!("primitive {} of mesh {} in {} is invalid: {}", primitive_index, mesh.index(), self.path, e))?;
+
+        Ok(Mesh {
+            key: format!("{}#mesh{}/{}", self.path, mesh.index(), primitive_index),
+            vertices,
+            indices,
+            rendered: true,
+        })
+    }
+
+    fn primitive_geometry(&self, primitive: &::gltf::Primitive, color_factor: [f32; 4]) -> Result<(Vec<Vertex>, Vec<u32>), String> {
+        let reader = primitive.reader(|buffer| self.buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+        let positions = reader
+            .read_positions()
+            .ok_or("it has no positions")?
+            .collect::<Vec<[f32; 3]>>();
+
+        let vertex_count = positions.len();
+
+        // without vertex colors the material's base color is used so untextured meshes still look right
+        let colors = match reader.read_colors(0) {
+            Some(colors) => colors.into_rgb_f32().collect::<Vec<[f32; 3]>>(),
+            None => vec![[color_factor[0], color_factor[1], color_factor[2]]; vertex_count],
+        };
+
+        let tex_coords = match reader.read_tex_coords(0) {
+            Some(tex_coords) => tex_coords.into_f32().collect::<Vec<[f32; 2]>>(),
+            None => vec![[0.0, 0.0]; vertex_count],
+        };
+
+        let normals = reader
+            .read_normals()
+            .map(|normals| normals.collect::<Vec<[f32; 3]>>());
+
+        if colors.len() != vertex_count
+            || tex_coords.len() != vertex_count
+            || normals.as_ref().map_or(false, |normals| normals.len() != vertex_count) {
+            return Err("its attributes have different lengths".to_string());
+        }
+
+        let mut vertices = positions
+            .into_iter()
+            .zip(colors.into_iter())
+            .zip(tex_coords.into_iter())
+            .enumerate()
+            .map(|(i, ((position, color), tex_coord))| {
+                let normal = normals.as_ref().map_or([0.0, 0.0, 0.0], |normals| normals[i]);
+                Vertex::new(position, color, tex_coord, normal)
+            })
+            .collect::<Vec<Vertex>>();
+
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect::<Vec<u32>>(),
+            None => (0..vertex_count as u32).collect(),
+        };
+
+        if indices.iter().any(|&index| index as usize >= vertex_count) {
+            return Err("an index is out of range".to_string());
+        }
+
+        if normals.is_none() {
+            generate_normals(&mut vertices, &indices);
+        }
+
+        Ok((vertices, indices))
+    }
+
+    fn material(&self, material: &::gltf::Material) -> Material {
+        let pbr = material.pbr_metallic_roughness();
+
+        let color = |texture: ::gltf::Texture| self.texture(&texture, ColorSpace::Srgb);
+        let data = |texture: ::gltf::Texture| self.texture(&texture, ColorSpace::Linear);
+
+        Material {
+            shading_model: ShadingModel::Pbr,
+            base_color_factor: pbr.base_color_factor(),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            emissive_factor: material.emissive_factor(),
+            base_color_texture: pbr.base_color_texture().and_then(|info| color(info.texture())),
+            metallic_roughness_texture: pbr.metallic_roughness_texture().and_then(|info| data(info.texture())),
+            normal_texture: material.normal_texture().and_then(|normal| data(normal.texture())),
+            occlusion_texture: material.occlusion_texture().and_then(|occlusion| data(occlusion.texture())),
+            emissive_texture: material.emissive_texture().and_then(|info| color(info.texture())),
+        }
+    }
+
+    fn texture(&self, texture: &::gltf::Texture, color_space: ColorSpace) -> Option<Texture> {
+        use ::gltf::texture::{MagFilter, WrappingMode};
+
+        let sampler = texture.sampler();
+
+        // gltf wraps u and v separately, we only have one mode so u wins
+        let wrap = match sampler.wrap_s() {
+            WrappingMode::ClampToEdge => WrapMode::Clamp,
+            WrappingMode::MirroredRepeat => WrapMode::Mirror,
+            WrappingMode::Repeat => WrapMode::Repeat,
+        };
+
+        let filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => FilterMode::Nearest,
+            _ => FilterMode::Linear,
+        };
+
+        self.textures
+            .get(texture.source().index())
+            .map(|image| image.clone().with_sampler(SamplerOptions {
+                filter,
+                wrap,
+                color_space,
+                ..SamplerOptions::default()
+            }))
+    }
+}
+
+fn local_transform(node: &::gltf::Node) -> Transform {
+    let (translation, rotation, scale) = node.transform().decomposed();
+
+    Transform {
+        position: Vector3::from(translation),
+        // gltf stores quaternions as xyzw
+        rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+        scale: Vector3::from(scale),
+    }
+}
+
+// images don't have a path of their own once they're decoded, so they're keyed by file and index
+fn image_texture(path: &str, image_index: usize, image: &::gltf::image::Data) -> Result<Texture, String> {
+    use ::gltf::image::Format;
+
+    let pixels = &image.pixels;
+
+    let rgba = match image.format {
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::R8G8B8 => pixels.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        Format::B8G8R8A8 => pixels.chunks(4).flat_map(|p| vec![p[2], p[1], p[0], p[3]]).collect(),
+        Format::B8G8R8 => pixels.chunks(3).flat_map(|p| vec![p[2], p[1], p[0], 255]).collect(),
+        Format::R8G8 => pixels.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        Format::R8 => pixels.iter().flat_map(|&p| vec![p, p, p, 255]).collect(),
+        other => return Err(format!("image {} in {} has unsupported format {:?}", image_index, path, other)),
+    };
+
+    Ok(Texture::from_pixels(
+        &format!("{}#image{}", path, image_index),
+        TexturePixels {
+            width: image.width,
+            height: image.height,
+            data: rgba,
+        },
+    ))
+}
@@ -0,0 +1,5 @@
+pub mod obj;
+pub mod gltf;
+pub mod component_registry;
+pub mod prefab;
+pub mod scene;
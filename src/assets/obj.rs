@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::components::mesh::Mesh;
+use crate::components::texture::Texture;
+use crate::primitives::vertex::{generate_normals, Vertex};
+use crate::utils::data_path;
+
+// used when a model has no material or its material has no diffuse color
+const DEFAULT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+// One mesh of an OBJ file along with the diffuse map of its material, if it has one.
+pub struct ObjMesh {
+    pub mesh: Mesh,
+    pub texture: Option<Texture>,
+}
+
+// Loads every model in an OBJ file, `path` is relative to the data directory like texture paths are.
+// Each model becomes its own mesh keyed by the file and model, so loading the same file twice gets
+// drawn instanced.
+pub fn load_obj(path: &str) -> Result<Vec<ObjMesh>, String> {
+    let (models, materials) = tobj::load_obj(&data_path(path))
+        .map_err(|e| format!("failed to load {}: {}", path, e))?;
+
+    // mtl texture paths are relative to the obj, textures are loaded relative to the data directory
+    let model_directory = Path::new(path)
+        .parent()
+        .unwrap_or(Path::new(""));
+
+    models
+        .into_iter()
+        .enumerate()
+        .map(|(model_index, model)| {
+            let material = model.mesh.material_id.and_then(|id| materials.get(id));
+
+            let color = material.map_or(DEFAULT_COLOR, |material| material.diffuse);
+
+            let texture = material
+                .filter(|material| !material.diffuse_texture.is_empty())
+                .map(|material| {
+                    Texture::new(&model_directory.join(&material.diffuse_texture).to_string_lossy())
+                });
+
+            let (vertices, indices) = build_geometry(&model.mesh, color)
+                .map_err(|e| format!("model {} in {} is invalid: {}", model.name, path, e))?;
+
+            let mesh = Mesh {
+                key: format!("{}#{}", path, model_index),
+                vertices,
+                indices,
+                rendered: true,
+            };
+
+            Ok(ObjMesh { mesh, texture })
+        })
+        .collect()
+}
+
+// tobj gives one vertex per position/normal/uv combination of every face, identical vertices are
+// merged again here so shared corners only get stored once
+fn build_geometry(mesh: &tobj::Mesh, color: [f32; 3]) -> Result<(Vec<Vertex>, Vec<u32>), String> {
+    let has_tex_coords = !mesh.texcoords.is_empty();
+    let has_normals = !mesh.normals.is_empty();
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut unique_vertices: HashMap<[u32; 11], u32> = HashMap::new();
+
+    for &index in mesh.indices.iter() {
+        let i = index as usize;
+
+        let position = mesh.positions
+            .get(3 * i..3 * i + 3)
+            .ok_or(format!("index {} has no position", index))?;
+
+        // obj puts the uv origin at the bottom left, images have it at the top left
+        let tex_coord = if has_tex_coords {
+            let uv = mesh.texcoords
+                .get(2 * i..2 * i + 2)
+                .ok_or(format!("index {} has no texture coordinate", index))?;
+            [uv[0], 1.0 - uv[1]]
+        } else {
+            [0.0, 0.0]
+        };
+
+        let normal = if has_normals {
+            let n = mesh.normals
+                .get(3 * i..3 * i + 3)
+                .ok_or(format!("index {} has no normal", index))?;
+            [n[0], n[1], n[2]]
+        } else {
+            [0.0, 0.0, 0.0]
+        };
+
+        let vertex = Vertex::new([position[0], position[1], position[2]], color, tex_coord, normal);
+
+        let key = [
+            position[0].to_bits(), position[1].to_bits(), position[2].to_bits(),
+            color[0].to_bits(), color[1].to_bits(), color[2].to_bits(),
+            tex_coord[0].to_bits(), tex_coord[1].to_bits(),
+            normal[0].to_bits(), normal[1].to_bits(), normal[2].to_bits(),
+        ];
+
+        let vertex_index = *unique_vertices.entry(key).or_insert_with(|| {
+            vertices.push(vertex);
+            (vertices.len() - 1) as u32
+        });
+
+        indices.push(vertex_index);
+    }
+
+    if !has_normals {
+        generate_normals(&mut vertices, &indices);
+    }
+
+    Ok((vertices, indices))
+}
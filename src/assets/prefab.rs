@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::assets::component_registry::{ComponentRegistry, LoadContext};
+use crate::assets::gltf::load_gltf;
+use crate::components::children::Children;
+use crate::components::global_transform::GlobalTransform;
+use crate::components::parent::Parent;
+use crate::utils::data_path;
+
+use legion::{Entity, World};
+
+// how deep prefabs can be based on each other before it's assumed they're based on themselves
+const MAX_BASE_DEPTH: usize = 16;
+
+// Components by the name they're registered under, in the same format as scenes. Overriding a component
+// merges into it, so `{ "transform": { "position": [1.0, 0.0, 0.0] } }` only moves an instance.
+pub type Overrides = BTreeMap<String, Value>;
+
+// A template for an entity and its children. A prefab can start from another one with `base`, its own
+// components override the base's and its children are added after the base's. `gltf` is a model whose
+// nodes get spawned below the entity, a prefab's own replaces the base's.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Prefab {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub components: Overrides,
+    #[serde(default)]
+    pub children: Vec<Prefab>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gltf: Option<String>,
+}
+
+// Prefabs by name, loaded from `<directory>/<name>.json` in the data directory the first time they're
+// used.
+pub struct PrefabLibrary {
+    directory: String,
+    prefabs: HashMap<String, Prefab>,
+    // keeps meshes around between instances
+    context: LoadContext,
+}
+
+impl PrefabLibrary {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: directory.to_string(),
+            prefabs: HashMap::new(),
+            context: LoadContext::new(HashMap::new()),
+        }
+    }
+
+    // Spawns a new instance of the prefab and returns its root entity. If anything goes wrong nothing
+    // is left behind in the world.
+    pub fn instantiate(&mut self, world: &mut World, registry: &ComponentRegistry, name: &str, overrides: &Overrides) -> Result<Entity, String> {
+        let prefab = self.resolve(name)?;
+        let entity = world.insert_from((), vec![(GlobalTransform::identity(),)])[0];
+
+        let mut spawned = vec![entity];
+        let mut context = std::mem::replace(&mut self.context, LoadContext::new(HashMap::new()));
+        let result = spawn_prefab(world, registry, &mut context, entity, &prefab, overrides, &mut spawned);
+        self.context = context;
+
+        match result {
+            Ok(()) => Ok(entity),
+            Err(e) => {
+                for entity in spawned {
+                    world.delete(entity);
+                }
+
+                Err(format!("can't instantiate prefab {}: {}", name, e))
+            },
+        }
+    }
+
+    // the prefab with every base applied
+    pub fn resolve(&mut self, name: &str) -> Result<Prefab, String> {
+        self.resolve_at_depth(name, 0)
+    }
+
+    fn resolve_at_depth(&mut self, name: &str, depth: usize) -> Result<Prefab, String> {
+        let prefab = self.load(name)?;
+        self.resolve_node(&prefab, depth)
+    }
+
+    // children can be based on other prefabs too
+    fn resolve_node(&mut self, prefab: &Prefab, depth: usize) -> Result<Prefab, String> {
+        let mut resolved = match prefab.base.as_ref() {
+            Some(base) if depth >= MAX_BASE_DEPTH => return Err(format!("prefab {} is based on itself", base)),
+            Some(base) => {
+                let base = self.resolve_at_depth(base, depth + 1)?;
+                apply(&base, prefab)
+            },
+            None => prefab.clone(),
+        };
+
+        let mut children = Vec::with_capacity(resolved.children.len());
+        for child in resolved.children.iter() {
+            children.push(self.resolve_node(child, depth)?);
+        }
+        resolved.children = children;
+
+        Ok(resolved)
+    }
+
+    fn load(&mut self, name: &str) -> Result<Prefab, String> {
+        if let Some(prefab) = self.prefabs.get(name) {
+            return Ok(prefab.clone());
+        }
+
+        let path = format!("{}/{}.json", self.directory, name);
+        let contents = std::fs::read_to_string(data_path(&path)).map_err(|e| format!("can't read prefab {}: {}", path, e))?;
+        let prefab: Prefab = serde_json::from_str(&contents).map_err(|e| format!("can't parse prefab {}: {}", path, e))?;
+
+        self.prefabs.insert(name.to_string(), prefab.clone());
+        Ok(prefab)
+    }
+}
+
+// Loads the prefab's components, with `overrides` merged in, into `entity` and spawns its children below
+// it. Everything spawned is added to `spawned` so it can be cleaned up if something fails later.
+pub(crate) fn spawn_prefab(
+    world: &mut World,
+    registry: &ComponentRegistry,
+    context: &mut LoadContext,
+    entity: Entity,
+    prefab: &Prefab,
+    overrides: &Overrides,
+    spawned: &mut Vec<Entity>,
+) -> Result<(), String> {
+    if prefab.base.is_some() {
+        return Err(String::from("prefabs have to be resolved before they're spawned"));
+    }
+
+    for (name, value) in merge_components(&prefab.components, overrides) {
+        registry.load(world, entity, &name, value, context)?;
+    }
+
+    if let Some(gltf) = prefab.gltf.as_ref() {
+        spawned.extend(load_gltf(world, gltf, Some(entity))?);
+    }
+
+    if prefab.children.is_empty() {
+        return Ok(());
+    }
+
+    if world.entity_data::<Children>(entity).is_none() {
+        world.mutate_entity(entity, |entity| entity.add_component(Children::default()));
+    }
+
+    for child in prefab.children.iter() {
+        let child_entity = world.insert_from((), vec![(GlobalTransform::identity(), Parent(entity))])[0];
+        spawned.push(child_entity);
+
+        spawn_prefab(world, registry, context, child_entity, child, &Overrides::new(), spawned)?;
+    }
+
+    Ok(())
+}
+
+// `prefab` on top of `base`, which has to be resolved already
+fn apply(base: &Prefab, prefab: &Prefab) -> Prefab {
+    Prefab {
+        base: None,
+        components: merge_components(&base.components, &prefab.components),
+        children: base.children.iter().chain(prefab.children.iter()).cloned().collect(),
+        gltf: prefab.gltf.clone().or_else(|| base.gltf.clone()),
+    }
+}
+
+fn merge_components(components: &Overrides, overrides: &Overrides) -> Overrides {
+    let mut merged = components.clone();
+
+    for (name, value) in overrides.iter() {
+        match merged.get_mut(name) {
+            Some(existing) => merge_value(existing, value),
+            None => {
+                merged.insert(name.clone(), value.clone());
+            },
+        }
+    }
+
+    merged
+}
+
+// objects are merged key by key, anything else is replaced
+fn merge_value(value: &mut Value, with: &Value) {
+    match (value, with) {
+        (Value::Object(object), Value::Object(with)) => {
+            for (key, with_value) in with.iter() {
+                match object.get_mut(key) {
+                    Some(existing) => merge_value(existing, with_value),
+                    None => {
+                        object.insert(key.clone(), with_value.clone());
+                    },
+                }
+            }
+        },
+        (value, with) => *value = with.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn overrides(value: Value) -> Overrides {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn prefab(value: Value) -> Prefab {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn with_prefabs(prefabs: Vec<(&str, Value)>) -> PrefabLibrary {
+        let mut library = PrefabLibrary::new("prefabs/missing");
+        for (name, value) in prefabs {
+            library.prefabs.insert(name.to_string(), prefab(value));
+        }
+
+        library
+    }
+
+    #[test]
+    fn objects_merge_key_by_key() {
+        let mut value = json!({ "position": [0.0, 0.0, 0.0], "scale": { "x": 1.0, "y": 1.0 } });
+        merge_value(&mut value, &json!({ "position": [1.0, 0.0, 0.0], "scale": { "y": 2.0 }, "visible": true }));
+
+        assert_eq!(value, json!({ "position": [1.0, 0.0, 0.0], "scale": { "x": 1.0, "y": 2.0 }, "visible": true }));
+    }
+
+    #[test]
+    fn anything_but_two_objects_is_replaced() {
+        let mut value = json!({ "color": { "r": 1.0 } });
+        merge_value(&mut value, &json!({ "color": "red" }));
+        assert_eq!(value, json!({ "color": "red" }));
+
+        merge_value(&mut value, &json!({ "color": { "g": 1.0 } }));
+        assert_eq!(value, json!({ "color": { "g": 1.0 } }));
+
+        let mut value = json!([1.0, 2.0, 3.0]);
+        merge_value(&mut value, &json!([4.0]));
+        assert_eq!(value, json!([4.0]));
+
+        merge_value(&mut value, &Value::Null);
+        assert_eq!(value, Value::Null);
+    }
+
+    #[test]
+    fn overrides_add_missing_components() {
+        let components = overrides(json!({ "transform": { "position": [0.0, 0.0, 0.0] } }));
+        let merged = merge_components(&components, &overrides(json!({
+            "transform": { "rotation": [0.0, 0.0, 0.0, 1.0] },
+            "mesh": "cube",
+        })));
+
+        assert_eq!(merged, overrides(json!({
+            "transform": { "position": [0.0, 0.0, 0.0], "rotation": [0.0, 0.0, 0.0, 1.0] },
+            "mesh": "cube",
+        })));
+    }
+
+    #[test]
+    fn prefabs_apply_on_top_of_their_base() {
+        let mut library = with_prefabs(vec![
+            ("lamp", json!({
+                "components": { "light": { "intensity": 1.0, "range": 10.0 } },
+                "children": [{ "components": { "mesh": "bulb" } }],
+                "gltf": "models/lamp.gltf",
+            })),
+            ("red_lamp", json!({
+                "base": "lamp",
+                "components": { "light": { "intensity": 2.0 } },
+                "children": [{ "components": { "mesh": "shade" } }],
+            })),
+        ]);
+
+        let resolved = library.resolve("red_lamp").unwrap();
+
+        assert_eq!(resolved.base, None);
+        assert_eq!(resolved.components, overrides(json!({ "light": { "intensity": 2.0, "range": 10.0 } })));
+        assert_eq!(resolved.gltf, Some(String::from("models/lamp.gltf")));
+        assert_eq!(
+            resolved.children.iter().map(|child| child.components["mesh"].clone()).collect::<Vec<Value>>(),
+            vec![json!("bulb"), json!("shade")],
+        );
+    }
+
+    #[test]
+    fn children_resolve_their_own_base() {
+        let mut library = with_prefabs(vec![
+            ("wheel", json!({ "components": { "mesh": "wheel" } })),
+            ("cart", json!({ "children": [{ "base": "wheel", "gltf": "models/spokes.gltf" }] })),
+        ]);
+
+        let resolved = library.resolve("cart").unwrap();
+
+        assert_eq!(resolved.children.len(), 1);
+        assert_eq!(resolved.children[0].base, None);
+        assert_eq!(resolved.children[0].components, overrides(json!({ "mesh": "wheel" })));
+        assert_eq!(resolved.children[0].gltf, Some(String::from("models/spokes.gltf")));
+    }
+
+    #[test]
+    fn bases_stop_at_the_depth_limit() {
+        // prefabs each based on the next one
+        let chain = |length: usize| {
+            (0..length)
+                .map(|index| {
+                    let value = if index + 1 < length {
+                        json!({ "base": format!("level{}", index + 1) })
+                    } else {
+                        json!({ "components": { "mesh": "cube" } })
+                    };
+
+                    (format!("level{}", index), value)
+                })
+                .collect::<Vec<(String, Value)>>()
+        };
+
+        // MAX_BASE_DEPTH bases below the first prefab are fine
+        let deepest = chain(MAX_BASE_DEPTH + 1);
+        let mut library = with_prefabs(deepest.iter().map(|(name, value)| (name.as_str(), value.clone())).collect());
+        assert_eq!(library.resolve("level0").unwrap().components, overrides(json!({ "mesh": "cube" })));
+
+        let too_deep = chain(MAX_BASE_DEPTH + 2);
+        let mut library = with_prefabs(too_deep.iter().map(|(name, value)| (name.as_str(), value.clone())).collect());
+        assert!(library.resolve("level0").is_err());
+    }
+
+    #[test]
+    fn prefabs_based_on_themselves_are_rejected() {
+        let mut library = with_prefabs(vec![
+            ("a", json!({ "base": "b" })),
+            ("b", json!({ "base": "a" })),
+        ]);
+
+        assert!(library.resolve("a").unwrap_err().contains("is based on itself"));
+    }
+
+    #[test]
+    fn missing_bases_are_an_error() {
+        let mut library = with_prefabs(vec![("orphan", json!({ "base": "nowhere" }))]);
+
+        assert!(library.resolve("orphan").unwrap_err().starts_with("can't read prefab prefabs/missing/nowhere.json"));
+    }
+}
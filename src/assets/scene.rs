@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::assets::component_registry::{ComponentRegistry, LoadContext, SaveContext};
+use crate::assets::prefab::{spawn_prefab, Prefab, PrefabLibrary};
+use crate::components::children::Children;
+use crate::components::global_transform::GlobalTransform;
+use crate::components::parent::Parent;
+use crate::utils::data_path;
+
+use legion::{Entity, World};
+
+// A scene is a list of entities, each with an id other entities can refer to (like a parent does) and
+// its components by the name they're registered under, e.g.
+//
+// { "entities": [ { "id": 0, "components": { "transform": { "position": [0.0, 1.0, 0.0] }, "mesh": "cube" } } ] }
+//
+// An entity can also be an instance of a prefab, then its components override the prefab's and the
+// prefab's children get spawned below it. With `gltf` the nodes of that model are spawned below it too.
+//
+// { "entities": [ { "id": 0, "components": { "transform": {} }, "gltf": "models/helmet.gltf" } ] }
+#[derive(Debug, Serialize, Deserialize)]
+struct SceneFile {
+    entities: Vec<SceneEntity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SceneEntity {
+    id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefab: Option<String>,
+    #[serde(default)]
+    components: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gltf: Option<String>,
+}
+
+// Writes every entity that has a registered component to `path`, relative to the data directory.
+pub fn save_scene(world: &World, registry: &ComponentRegistry, path: &str) -> Result<(), String> {
+    let entities = registry.entities(world);
+
+    let context = SaveContext::new(
+        entities
+            .iter()
+            .enumerate()
+            .map(|(id, entity)| (*entity, id as u64))
+            .collect(),
+    );
+
+    let scene = SceneFile {
+        entities: entities
+            .iter()
+            .enumerate()
+            .map(|(id, entity)| {
+                Ok(SceneEntity {
+                    id: id as u64,
+                    prefab: None,
+                    gltf: None,
+                    components: registry.save(world, *entity, &context)?.into_iter().collect(),
+                })
+            })
+            .collect::<Result<Vec<SceneEntity>, String>>()?,
+    };
+
+    let contents = serde_json::to_string_pretty(&scene).map_err(|e| format!("can't serialize scene: {}", e))?;
+    std::fs::write(data_path(path), contents).map_err(|e| format!("can't write scene {}: {}", path, e))
+}
+
+// Adds the entities of the scene at `path` to the world and returns them. Either the whole scene makes
+// it in or none of it does.
+pub fn load_scene(world: &mut World, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary, path: &str) -> Result<Vec<Entity>, String> {
+    let contents = std::fs::read_to_string(data_path(path)).map_err(|e| format!("can't read scene {}: {}", path, e))?;
+    let scene: SceneFile = serde_json::from_str(&contents).map_err(|e| format!("can't parse scene {}: {}", path, e))?;
+
+    // everything in a scene can end up with a parent, so everything gets a GlobalTransform up front
+    let entities = world
+        .insert_from((), scene.entities.iter().map(|_| (GlobalTransform::identity(),)).collect::<Vec<_>>())
+        .to_vec();
+
+    let mut spawned = entities.clone();
+    let result = load_components(world, registry, prefabs, scene, &entities, &mut spawned)
+        .map_err(|e| format!("can't load scene {}: {}", path, e));
+
+    if result.is_err() {
+        for entity in spawned {
+            world.delete(entity);
+        }
+    }
+
+    result.map(|_| entities)
+}
+
+fn load_components(
+    world: &mut World,
+    registry: &ComponentRegistry,
+    prefabs: &mut PrefabLibrary,
+    scene: SceneFile,
+    entities: &[Entity],
+    spawned: &mut Vec<Entity>,
+) -> Result<(), String> {
+    let mut ids = HashMap::new();
+    for (scene_entity, entity) in scene.entities.iter().zip(entities) {
+        if ids.insert(scene_entity.id, *entity).is_some() {
+            return Err(format!("there's more than one entity {}", scene_entity.id));
+        }
+    }
+
+    let mut context = LoadContext::new(ids);
+
+    for (scene_entity, entity) in scene.entities.into_iter().zip(entities) {
+        let mut prefab = match scene_entity.prefab.as_ref() {
+            Some(name) => prefabs.resolve(name)?,
+            None => Prefab::default(),
+        };
+        if scene_entity.gltf.is_some() {
+            prefab.gltf = scene_entity.gltf.clone();
+        }
+
+        spawn_prefab(world, registry, &mut context, *entity, &prefab, &scene_entity.components, spawned)
+            .map_err(|e| format!("entity {}: {}", scene_entity.id, e))?;
+    }
+
+    // only entities that have Children get them kept up to date
+    let parents = entities
+        .iter()
+        .filter_map(|entity| world.entity_data::<Parent>(*entity).map(|parent| parent.0))
+        .collect::<Vec<Entity>>();
+
+    for parent in parents {
+        if world.entity_data::<Children>(parent).is_none() {
+            world.mutate_entity(parent, |entity| entity.add_component(Children::default()));
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,113 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use cgmath::{Deg, Matrix4};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+use crate::primitives::three_d::projection::{orthographic, perspective};
+
+const DEFAULT_FOV: f32 = 45.0;
+const DEFAULT_NEAR: f32 = 0.1;
+const DEFAULT_FAR: f32 = 1000.0;
+
+// `fov` is the vertical field of view in degrees, `size` the height of the view in world units. The
+// width of either follows the aspect ratio of the viewport.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Perspective { fov: f32, near: f32, far: f32 },
+    Orthographic { size: f32, near: f32, far: f32 },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fov: DEFAULT_FOV,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+        }
+    }
+}
+
+// With `reversed_z` the near plane ends up at depth 1 and the far plane at 0, which spreads the float
+// precision more evenly over the distance and gets rid of most z-fighting far away.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
-   pub displaying: bool,
+    pub displaying: bool,
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default)]
+    pub reversed_z: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            displaying: true,
+            projection: Projection::default(),
+            reversed_z: false,
+        }
+    }
+}
+
+impl Camera {
+    pub fn perspective(fov: f32) -> Self {
+        Camera {
+            projection: Projection::Perspective {
+                fov,
+                near: DEFAULT_NEAR,
+                far: DEFAULT_FAR,
+            },
+            ..Camera::default()
+        }
+    }
+
+    pub fn orthographic(size: f32) -> Self {
+        Camera {
+            projection: Projection::Orthographic {
+                size,
+                near: DEFAULT_NEAR,
+                far: DEFAULT_FAR,
+            },
+            ..Camera::default()
+        }
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.projection = match self.projection {
+            Projection::Perspective { fov, .. } => Projection::Perspective { fov, near, far },
+            Projection::Orthographic { size, .. } => Projection::Orthographic { size, near, far },
+        };
+        self
+    }
+
+    pub fn with_reversed_z(mut self, reversed_z: bool) -> Self {
+        self.reversed_z = reversed_z;
+        self
+    }
+
+    // already in vulkan's clip space, y pointing down and depth going from 0 to 1
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        let mut proj = match self.projection {
+            Projection::Perspective { fov, near, far } => perspective(Deg(fov), aspect, near, far, self.reversed_z),
+            Projection::Orthographic { size, near, far } => {
+                let half_height = size / 2.0;
+                orthographic(half_height * aspect, half_height, near, far, self.reversed_z)
+            },
+        };
+
+        // both are symmetric around the view direction, so flipping y only takes its scale
+        proj.y.y = -proj.y.y;
+        proj
+    }
+}
+
+impl SceneComponent for Camera {
+    type Data = Self;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<Self, String> {
+        Ok(self.clone())
+    }
+
+    fn from_data(data: Self, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(data)
+    }
 }
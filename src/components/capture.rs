@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Asks the engine to write the next `frames_remaining` frames to `directory` as numbered pngs.
+#[derive(Clone, Debug)]
+pub struct FrameCapture {
+    pub directory: PathBuf,
+    pub frames_remaining: u32,
+    pub exit_when_done: bool,
+    // compared against the last frame of the capture
+    pub golden: Option<PathBuf>,
+    name: String,
+    next_frame: u32,
+}
+
+impl FrameCapture {
+    pub fn screenshot(directory: PathBuf) -> Self {
+        Self {
+            name: started_now("screenshot"),
+            ..Self::sequence(directory, 1)
+        }
+    }
+
+    // a sequence taken from the running engine, like a turntable of the scene
+    pub fn recording(directory: PathBuf, frame_count: u32) -> Self {
+        Self {
+            name: started_now("sequence"),
+            ..Self::sequence(directory, frame_count)
+        }
+    }
+
+    pub fn sequence(directory: PathBuf, frame_count: u32) -> Self {
+        Self {
+            directory,
+            frames_remaining: frame_count,
+            exit_when_done: false,
+            golden: None,
+            name: String::from("frame"),
+            next_frame: 0,
+        }
+    }
+
+    pub fn exit_when_done(mut self) -> Self {
+        self.exit_when_done = true;
+        self
+    }
+
+    pub fn with_golden(mut self, golden: PathBuf) -> Self {
+        self.golden = Some(golden);
+        self
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frames_remaining == 0
+    }
+
+    // hands out the path for the next frame and counts it as captured
+    pub fn next_path(&mut self) -> PathBuf {
+        let path = self.directory.join(format!("{}_{:05}.png", self.name, self.next_frame));
+
+        self.next_frame += 1;
+        self.frames_remaining = self.frames_remaining.saturating_sub(1);
+
+        path
+    }
+}
+
+// captures taken while the engine runs are named after when they started so repeated ones don't
+// overwrite each other
+fn started_now(prefix: &str) -> String {
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+
+    format!("{}_{}", prefix, started_at)
+}
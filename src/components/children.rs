@@ -0,0 +1,6 @@
+use legion::Entity;
+
+// The entities whose Parent is this one, in no particular order. Transform propagation keeps it up to
+// date, but only on entities that were given one, since systems can't add components.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Children(pub Vec<Entity>);
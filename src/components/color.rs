@@ -1,6 +1,22 @@
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct Color {
     r: u8,
     g: u8,
     b: u8
 }
+
+impl SceneComponent for Color {
+    type Data = Self;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<Self, String> {
+        Ok(self.clone())
+    }
+
+    fn from_data(data: Self, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(data)
+    }
+}
@@ -1,12 +1,21 @@
+use crate::components::capture::FrameCapture;
+
 #[derive(Clone, Debug)]
 pub struct Config where {
     pub should_record_commands: bool,
+    pub should_recreate_swapchain: bool,
+    pub frame_capture: Option<FrameCapture>,
+    // where to write the world to at the end of the frame, relative to the data directory
+    pub save_scene: Option<String>,
 }
 
 impl Config {
     pub fn new() -> Self {
         Self {
             should_record_commands: true,
+            should_recreate_swapchain: false,
+            frame_capture: None,
+            save_scene: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use cgmath::{Matrix4, SquareMatrix, Vector3};
+
+use crate::components::transform::Transform;
+use crate::primitives::uniform_buffer_object::ObjectUniformBufferObject;
+
+// Where an entity ends up in the world once the transforms of all its parents are applied. Worked out
+// every frame by transform propagation, the drawer only ever looks at this.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalTransform(pub Matrix4<f32>);
+
+impl GlobalTransform {
+    pub fn identity() -> Self {
+        GlobalTransform(Matrix4::identity())
+    }
+
+    pub fn position(&self) -> Vector3<f32> {
+        self.0.w.truncate()
+    }
+
+    pub fn to_ubo(&self) -> ObjectUniformBufferObject {
+        ObjectUniformBufferObject::new(self.0)
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+// what an entity without a parent ends up with
+impl From<&Transform> for GlobalTransform {
+    fn from(transform: &Transform) -> Self {
+        GlobalTransform(transform.matrix())
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
+
+use crate::components::input_state::InputState;
+use crate::events::application_events::{ApplicationEvent, MouseButton};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Input {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+}
+
+impl Input {
+    fn is_held(&self, input: &InputState) -> bool {
+        match self {
+            Input::Key(key) => input.is_held(*key),
+            Input::MouseButton(button) => input.is_button_held(*button),
+        }
+    }
+}
+
+// either the left or the right one counts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+    Logo,
+}
+
+impl Modifier {
+    fn is_held(&self, input: &InputState) -> bool {
+        let (left, right) = match self {
+            Modifier::Shift => (VirtualKeyCode::LShift, VirtualKeyCode::RShift),
+            Modifier::Ctrl => (VirtualKeyCode::LControl, VirtualKeyCode::RControl),
+            Modifier::Alt => (VirtualKeyCode::LAlt, VirtualKeyCode::RAlt),
+            Modifier::Logo => (VirtualKeyCode::LWin, VirtualKeyCode::RWin),
+        };
+
+        input.is_held(left) || input.is_held(right)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActionBinding {
+    pub input: Input,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+impl ActionBinding {
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            modifiers: vec![],
+        }
+    }
+
+    pub fn with_modifiers(mut self, modifiers: Vec<Modifier>) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    fn modifiers_held(&self, input: &InputState) -> bool {
+        self.modifiers.iter().all(|modifier| modifier.is_held(input))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisSource {
+    // 1 while `positive` is held, -1 while `negative` is, 0 for both or neither
+    Keys { positive: Input, negative: Input },
+    // the raw mouse movement and the scrolled lines this frame
+    MouseX,
+    MouseY,
+    ScrollX,
+    ScrollY,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub invert: bool,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl AxisBinding {
+    pub fn new(source: AxisSource) -> Self {
+        Self {
+            source,
+            scale: default_scale(),
+            invert: false,
+        }
+    }
+
+    fn value(&self, input: &InputState) -> f32 {
+        let value = match self.source {
+            AxisSource::Keys { positive, negative } => {
+                (positive.is_held(input) as i32 - negative.is_held(input) as i32) as f32
+            },
+            AxisSource::MouseX => input.mouse_delta[0] as f32,
+            AxisSource::MouseY => input.mouse_delta[1] as f32,
+            AxisSource::ScrollX => input.scroll_delta[0] as f32,
+            AxisSource::ScrollY => input.scroll_delta[1] as f32,
+        };
+
+        if self.invert { -value * self.scale } else { value * self.scale }
+    }
+}
+
+// Named actions and axes, and what they're bound to. Lives in the world next to `InputState`, game logic
+// asks this about "move_forward" instead of looking at keys so controls can be remapped, either in the
+// bindings file or at runtime through `bind` and friends.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    #[serde(default)]
+    actions: HashMap<String, Vec<ActionBinding>>,
+    #[serde(default)]
+    axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read input bindings {:?}: {}", path, e))?;
+
+        ron::de::from_str(&contents).map_err(|e| format!("can't parse input bindings {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("can't serialize input bindings: {}", e))?;
+
+        std::fs::write(path, contents).map_err(|e| format!("can't write input bindings {:?}: {}", path, e))
+    }
+
+    // adds to whatever the action is already bound to
+    pub fn bind(&mut self, action: &str, binding: ActionBinding) {
+        self.actions.entry(action.to_string()).or_default().push(binding);
+    }
+
+    // replaces everything the action is bound to
+    pub fn rebind(&mut self, action: &str, bindings: Vec<ActionBinding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+
+    pub fn unbind_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[ActionBinding] {
+        self.actions.get(action).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn axis_bindings_mut(&mut self, axis: &str) -> &mut [AxisBinding] {
+        self.axes.get_mut(axis).map(|bindings| bindings.as_mut_slice()).unwrap_or(&mut [])
+    }
+
+    pub fn is_held(&self, action: &str, input: &InputState) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.input.is_held(input) && binding.modifiers_held(input))
+    }
+
+    // the sum of everything bound to the axis
+    pub fn axis(&self, axis: &str, input: &InputState) -> f32 {
+        self.axes
+            .get(axis)
+            .map_or(0.0, |bindings| bindings.iter().map(|binding| binding.value(input)).sum())
+    }
+
+    // Actions that a raw key or button event starts or ends. A press starts every action bound to the
+    // input whose modifiers are held, checked against `input` which should already have seen the event.
+    // The release ends exactly the actions the press started, even if the modifiers were let go first or
+    // the bindings changed in between. Actions also held through another input don't start or end again.
+    pub fn actions_for(&self, event: &ApplicationEvent, input: &mut InputState) -> Vec<ApplicationEvent> {
+        match event {
+            ApplicationEvent::KeyPressed(key) => self.start_actions(Input::Key(*key), input),
+            ApplicationEvent::KeyReleased(key) => Self::end_actions(Input::Key(*key), input),
+            ApplicationEvent::MouseButtonPressed(button) => self.start_actions(Input::MouseButton(*button), input),
+            ApplicationEvent::MouseButtonReleased(button) => Self::end_actions(Input::MouseButton(*button), input),
+            _ => vec![],
+        }
+    }
+
+    fn start_actions(&self, triggered: Input, input: &mut InputState) -> Vec<ApplicationEvent> {
+        let mut actions = self.actions
+            .iter()
+            .filter(|(_, bindings)| {
+                bindings
+                    .iter()
+                    .any(|binding| binding.input == triggered && binding.modifiers_held(input))
+            })
+            .map(|(action, _)| action.clone())
+            .collect::<Vec<String>>();
+
+        // the map's order changes from run to run
+        actions.sort();
+
+        let started = actions
+            .iter()
+            .filter(|action| !input.is_action_active(action))
+            .map(|action| ApplicationEvent::ActionPressed(action.clone()))
+            .collect();
+
+        input.activate(triggered, actions);
+        started
+    }
+
+    fn end_actions(triggered: Input, input: &mut InputState) -> Vec<ApplicationEvent> {
+        input
+            .deactivate(triggered)
+            .into_iter()
+            .filter(|action| !input.is_action_active(action))
+            .map(ApplicationEvent::ActionReleased)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(map: &InputMap, input: &mut InputState, key: VirtualKeyCode) -> Vec<ApplicationEvent> {
+        let event = ApplicationEvent::KeyPressed(key);
+        input.handle(&event);
+        map.actions_for(&event, input)
+    }
+
+    fn release(map: &InputMap, input: &mut InputState, key: VirtualKeyCode) -> Vec<ApplicationEvent> {
+        let event = ApplicationEvent::KeyReleased(key);
+        input.handle(&event);
+        map.actions_for(&event, input)
+    }
+
+    fn names(events: Vec<ApplicationEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                ApplicationEvent::ActionPressed(action) => format!("+{}", action),
+                ApplicationEvent::ActionReleased(action) => format!("-{}", action),
+                other => panic!("{:?} isn't an action", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bind_adds_to_the_existing_bindings() {
+        let mut map = InputMap::new();
+        map.bind("jump", ActionBinding::new(Input::Key(VirtualKeyCode::Space)));
+        map.bind("jump", ActionBinding::new(Input::MouseButton(MouseButton::Right)));
+
+        assert_eq!(map.bindings("jump").len(), 2);
+
+        let mut input = InputState::new();
+        input.handle(&ApplicationEvent::MouseButtonPressed(MouseButton::Right));
+        assert!(map.is_held("jump", &input));
+    }
+
+    #[test]
+    fn rebind_replaces_the_existing_bindings() {
+        let mut map = InputMap::new();
+        map.bind("jump", ActionBinding::new(Input::Key(VirtualKeyCode::Space)));
+        map.rebind("jump", vec![ActionBinding::new(Input::Key(VirtualKeyCode::J))]);
+
+        let mut input = InputState::new();
+        assert!(names(press(&map, &mut input, VirtualKeyCode::Space)).is_empty());
+        assert_eq!(names(press(&map, &mut input, VirtualKeyCode::J)), vec!["+jump"]);
+    }
+
+    #[test]
+    fn unbind_removes_the_action() {
+        let mut map = InputMap::new();
+        map.bind("jump", ActionBinding::new(Input::Key(VirtualKeyCode::Space)));
+        map.unbind("jump");
+
+        let mut input = InputState::new();
+        assert!(map.bindings("jump").is_empty());
+        assert!(names(press(&map, &mut input, VirtualKeyCode::Space)).is_empty());
+    }
+
+    #[test]
+    fn axes_can_be_bound_rebound_and_unbound() {
+        let keys = AxisSource::Keys {
+            positive: Input::Key(VirtualKeyCode::D),
+            negative: Input::Key(VirtualKeyCode::A),
+        };
+
+        let mut map = InputMap::new();
+        map.bind_axis("strafe", AxisBinding::new(keys));
+        map.bind_axis("strafe", AxisBinding::new(AxisSource::MouseX));
+
+        let mut input = InputState::new();
+        input.handle(&ApplicationEvent::KeyPressed(VirtualKeyCode::D));
+        input.handle(&ApplicationEvent::MouseMotion { x: 2.0, y: 0.0 });
+        assert_eq!(map.axis("strafe", &input), 3.0);
+
+        map.rebind_axis("strafe", vec![AxisBinding { invert: true, ..AxisBinding::new(AxisSource::MouseX) }]);
+        assert_eq!(map.axis("strafe", &input), -2.0);
+
+        map.unbind_axis("strafe");
+        assert_eq!(map.axis("strafe", &input), 0.0);
+    }
+
+    #[test]
+    fn modifiers_have_to_be_held_for_a_press_to_start_the_action() {
+        let mut map = InputMap::new();
+        map.bind("screenshot", ActionBinding::new(Input::Key(VirtualKeyCode::P)).with_modifiers(vec![Modifier::Ctrl]));
+
+        let mut input = InputState::new();
+        assert!(names(press(&map, &mut input, VirtualKeyCode::P)).is_empty());
+        assert!(names(release(&map, &mut input, VirtualKeyCode::P)).is_empty());
+
+        press(&map, &mut input, VirtualKeyCode::RControl);
+        assert_eq!(names(press(&map, &mut input, VirtualKeyCode::P)), vec!["+screenshot"]);
+    }
+
+    #[test]
+    fn a_release_ends_what_its_press_started_even_after_a_rebind() {
+        let mut map = InputMap::new();
+        map.bind("screenshot", ActionBinding::new(Input::Key(VirtualKeyCode::P)).with_modifiers(vec![Modifier::Ctrl]));
+
+        let mut input = InputState::new();
+        press(&map, &mut input, VirtualKeyCode::LControl);
+        press(&map, &mut input, VirtualKeyCode::P);
+
+        release(&map, &mut input, VirtualKeyCode::LControl);
+        map.unbind("screenshot");
+        assert_eq!(names(release(&map, &mut input, VirtualKeyCode::P)), vec!["-screenshot"]);
+    }
+
+    #[test]
+    fn an_action_held_through_two_inputs_only_ends_with_the_last() {
+        let mut map = InputMap::new();
+        map.bind("fire", ActionBinding::new(Input::Key(VirtualKeyCode::F)));
+        map.bind("fire", ActionBinding::new(Input::Key(VirtualKeyCode::G)));
+
+        let mut input = InputState::new();
+        assert_eq!(names(press(&map, &mut input, VirtualKeyCode::F)), vec!["+fire"]);
+        assert!(names(press(&map, &mut input, VirtualKeyCode::G)).is_empty());
+        assert!(names(release(&map, &mut input, VirtualKeyCode::F)).is_empty());
+        assert_eq!(names(release(&map, &mut input, VirtualKeyCode::G)), vec!["-fire"]);
+    }
+
+    #[test]
+    fn saved_bindings_load_back_the_same() {
+        let mut map = InputMap::new();
+        map.bind("jump", ActionBinding::new(Input::Key(VirtualKeyCode::Space)));
+        map.bind("screenshot", ActionBinding::new(Input::Key(VirtualKeyCode::P)).with_modifiers(vec![Modifier::Ctrl, Modifier::Shift]));
+        map.bind_axis("look_x", AxisBinding { scale: 0.25, ..AxisBinding::new(AxisSource::MouseX) });
+
+        let path = std::env::temp_dir().join(format!("input_map_test_{}.ron", std::process::id()));
+        map.save(&path).unwrap();
+        let loaded = InputMap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), map);
+    }
+}
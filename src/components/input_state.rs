@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+
+use winit::event::VirtualKeyCode;
+
+use crate::components::input_map::Input;
+use crate::events::application_events::{ApplicationEvent, MouseButton};
+
+// Everything the keyboard and mouse are doing right now. Lives in the world next to `Config` and is
+// fed by the event handler, anything that runs every frame should read this instead of reacting to
+// events so it moves at the same speed however often the os repeats a key.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    held_keys: HashSet<VirtualKeyCode>,
+    pressed_keys: HashSet<VirtualKeyCode>,
+    released_keys: HashSet<VirtualKeyCode>,
+
+    held_buttons: HashSet<MouseButton>,
+    pressed_buttons: HashSet<MouseButton>,
+    released_buttons: HashSet<MouseButton>,
+
+    // the actions each held key or button started, see `InputMap::actions_for`
+    active_actions: HashMap<Input, Vec<String>>,
+
+    // in pixels from the top left of the window
+    pub cursor: [f64; 2],
+    // raw mouse movement and scrolled lines since the start of the frame
+    pub mouse_delta: [f64; 2],
+    pub scroll_delta: [f64; 2],
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // forgets what happened last frame, held keys and buttons stay held
+    pub fn begin_frame(&mut self) {
+        self.pressed_keys.clear();
+        self.released_keys.clear();
+        self.pressed_buttons.clear();
+        self.released_buttons.clear();
+        self.mouse_delta = [0.0, 0.0];
+        self.scroll_delta = [0.0, 0.0];
+    }
+
+    // the os repeating a press for a key or button that's already held
+    pub fn is_repeat(&self, event: &ApplicationEvent) -> bool {
+        match event {
+            ApplicationEvent::KeyPressed(key) => self.is_held(*key),
+            ApplicationEvent::MouseButtonPressed(button) => self.is_button_held(*button),
+            _ => false,
+        }
+    }
+
+    pub fn handle(&mut self, event: &ApplicationEvent) {
+        match event {
+            // the os repeats presses while a key is held, only the first one counts as pressed
+            ApplicationEvent::KeyPressed(key) => {
+                if !self.is_held(*key) {
+                    self.pressed_keys.insert(*key);
+                }
+                self.held_keys.insert(*key);
+            },
+            ApplicationEvent::KeyReleased(key) => {
+                self.held_keys.remove(key);
+                self.released_keys.insert(*key);
+            },
+            ApplicationEvent::MouseButtonPressed(button) => {
+                if !self.is_button_held(*button) {
+                    self.pressed_buttons.insert(*button);
+                }
+                self.held_buttons.insert(*button);
+            },
+            ApplicationEvent::MouseButtonReleased(button) => {
+                self.held_buttons.remove(button);
+                self.released_buttons.insert(*button);
+            },
+            ApplicationEvent::CursorMoved { x, y } => self.cursor = [*x, *y],
+            ApplicationEvent::MouseMotion { x, y } => {
+                self.mouse_delta[0] += x;
+                self.mouse_delta[1] += y;
+            },
+            ApplicationEvent::MouseScroll { x, y } => {
+                self.scroll_delta[0] += x;
+                self.scroll_delta[1] += y;
+            },
+            _ => (),
+        }
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    // went down this frame
+    pub fn was_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    // went up this frame
+    pub fn was_released(&self, key: VirtualKeyCode) -> bool {
+        self.released_keys.contains(&key)
+    }
+
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.held_buttons.contains(&button)
+    }
+
+    pub fn was_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    pub fn was_button_released(&self, button: MouseButton) -> bool {
+        self.released_buttons.contains(&button)
+    }
+
+    // held through any key or button
+    pub fn is_action_active(&self, action: &str) -> bool {
+        self.active_actions.values().any(|actions| actions.iter().any(|active| active == action))
+    }
+
+    pub fn activate(&mut self, input: Input, actions: Vec<String>) {
+        if !actions.is_empty() {
+            self.active_actions.insert(input, actions);
+        }
+    }
+
+    // the actions the input started, which it doesn't hold anymore
+    pub fn deactivate(&mut self, input: Input) -> Vec<String> {
+        self.active_actions.remove(&input).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_press_is_only_pressed_for_the_frame_it_happened_in() {
+        let mut input = InputState::new();
+
+        input.handle(&ApplicationEvent::KeyPressed(VirtualKeyCode::W));
+        assert!(input.was_pressed(VirtualKeyCode::W));
+        assert!(input.is_held(VirtualKeyCode::W));
+
+        input.begin_frame();
+        assert!(!input.was_pressed(VirtualKeyCode::W));
+        assert!(input.is_held(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn repeated_presses_dont_count_as_pressed_again() {
+        let mut input = InputState::new();
+
+        input.handle(&ApplicationEvent::KeyPressed(VirtualKeyCode::W));
+        input.begin_frame();
+
+        let repeat = ApplicationEvent::KeyPressed(VirtualKeyCode::W);
+        assert!(input.is_repeat(&repeat));
+        input.handle(&repeat);
+        assert!(!input.was_pressed(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn a_release_is_only_released_for_the_frame_it_happened_in() {
+        let mut input = InputState::new();
+
+        input.handle(&ApplicationEvent::KeyPressed(VirtualKeyCode::W));
+        input.handle(&ApplicationEvent::KeyReleased(VirtualKeyCode::W));
+        assert!(input.was_pressed(VirtualKeyCode::W));
+        assert!(input.was_released(VirtualKeyCode::W));
+        assert!(!input.is_held(VirtualKeyCode::W));
+
+        input.begin_frame();
+        assert!(!input.was_released(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn mouse_buttons_have_the_same_edges_as_keys() {
+        let mut input = InputState::new();
+
+        input.handle(&ApplicationEvent::MouseButtonPressed(MouseButton::Left));
+        assert!(input.was_button_pressed(MouseButton::Left));
+        assert!(input.is_button_held(MouseButton::Left));
+        assert!(!input.was_button_pressed(MouseButton::Right));
+
+        input.begin_frame();
+        input.handle(&ApplicationEvent::MouseButtonReleased(MouseButton::Left));
+        assert!(!input.was_button_pressed(MouseButton::Left));
+        assert!(input.was_button_released(MouseButton::Left));
+        assert!(!input.is_button_held(MouseButton::Left));
+
+        input.begin_frame();
+        assert!(!input.was_button_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn movement_is_summed_over_the_frame() {
+        let mut input = InputState::new();
+
+        input.handle(&ApplicationEvent::MouseMotion { x: 2.0, y: -1.0 });
+        input.handle(&ApplicationEvent::MouseMotion { x: 3.0, y: 4.0 });
+        input.handle(&ApplicationEvent::MouseScroll { x: 0.0, y: 1.0 });
+        assert_eq!(input.mouse_delta, [5.0, 3.0]);
+        assert_eq!(input.scroll_delta, [0.0, 1.0]);
+
+        input.begin_frame();
+        assert_eq!(input.mouse_delta, [0.0, 0.0]);
+        assert_eq!(input.scroll_delta, [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,177 @@
+use cgmath::{Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+
+use crate::components::global_transform::GlobalTransform;
+use crate::primitives::three_d::projection::{orthographic, perspective};
+use crate::primitives::uniform_buffer_object::LightUniform;
+
+// light types as the standard shader tells them apart
+const DIRECTIONAL_LIGHT: f32 = 0.0;
+const POINT_LIGHT: f32 = 1.0;
+const SPOT_LIGHT: f32 = 2.0;
+
+// how far from the camera directional light shadows reach
+const DIRECTIONAL_SHADOW_EXTENT: f32 = 40.0;
+const SPOT_SHADOW_NEAR: f32 = 0.1;
+
+// Lights infinitely far away, like the sun. Only the direction matters so it doesn't need a Transform.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    #[serde(with = "vector_array")]
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub casts_shadows: bool,
+}
+
+// Shines in every direction from where its entity ends up in the world and fades out to nothing at
+// `range`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+// A point light limited to a cone around `direction`, fading out between the inner and outer angle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpotLight {
+    #[serde(with = "vector_array")]
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: Deg<f32>,
+    pub outer_angle: Deg<f32>,
+    pub casts_shadows: bool,
+}
+
+// directions are written as [x, y, z] like positions in a transform are
+mod vector_array {
+    use cgmath::Vector3;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(vector: &Vector3<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        <[f32; 3]>::from(*vector).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vector3<f32>, D::Error> {
+        <[f32; 3]>::deserialize(deserializer).map(Vector3::from)
+    }
+}
+
+impl DirectionalLight {
+    pub fn to_uniform(&self) -> LightUniform {
+        let direction = self.direction.normalize();
+
+        LightUniform {
+            position: [0.0, 0.0, 0.0, DIRECTIONAL_LIGHT],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [self.color[0], self.color[1], self.color[2], self.intensity],
+            cutoffs: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    // An orthographic box around `center` looking down the light's direction, so shadows follow the
+    // camera around instead of covering the whole world.
+    pub fn shadow_view_proj(&self, center: Vector3<f32>) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+        let eye = Point3::from_vec(center - direction * DIRECTIONAL_SHADOW_EXTENT);
+
+        let view = Matrix4::look_at_dir(eye, direction, up_vector(direction));
+        let proj = orthographic(
+            DIRECTIONAL_SHADOW_EXTENT,
+            DIRECTIONAL_SHADOW_EXTENT,
+            0.0,
+            DIRECTIONAL_SHADOW_EXTENT * 2.0,
+            false,
+        );
+
+        proj * view
+    }
+}
+
+impl PointLight {
+    pub fn to_uniform(&self, transform: &GlobalTransform) -> LightUniform {
+        let position = transform.position();
+
+        LightUniform {
+            position: [position.x, position.y, position.z, POINT_LIGHT],
+            direction: [0.0, 0.0, 0.0, self.range],
+            color: [self.color[0], self.color[1], self.color[2], self.intensity],
+            cutoffs: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl SpotLight {
+    pub fn to_uniform(&self, transform: &GlobalTransform) -> LightUniform {
+        let position = transform.position();
+        let direction = self.direction.normalize();
+
+        // the shader compares against the cosine of the angle between the light and the fragment
+        LightUniform {
+            position: [position.x, position.y, position.z, SPOT_LIGHT],
+            direction: [direction.x, direction.y, direction.z, self.range],
+            color: [self.color[0], self.color[1], self.color[2], self.intensity],
+            cutoffs: [self.inner_angle.cos(), self.outer_angle.cos(), 0.0, 0.0],
+        }
+    }
+
+    // a perspective frustum covering the outer cone
+    pub fn shadow_view_proj(&self, transform: &GlobalTransform) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+
+        let view = Matrix4::look_at_dir(Point3::from_vec(transform.position()), direction, up_vector(direction));
+        let proj = perspective(self.outer_angle * 2.0, 1.0, SPOT_SHADOW_NEAR, self.range, false);
+
+        proj * view
+    }
+}
+
+impl SceneComponent for DirectionalLight {
+    type Data = Self;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<Self, String> {
+        Ok(self.clone())
+    }
+
+    fn from_data(data: Self, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(data)
+    }
+}
+
+impl SceneComponent for PointLight {
+    type Data = Self;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<Self, String> {
+        Ok(self.clone())
+    }
+
+    fn from_data(data: Self, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(data)
+    }
+}
+
+impl SceneComponent for SpotLight {
+    type Data = Self;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<Self, String> {
+        Ok(self.clone())
+    }
+
+    fn from_data(data: Self, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(data)
+    }
+}
+
+// any up vector works as long as it isn't parallel to the direction
+fn up_vector(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    }
+}
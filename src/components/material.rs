@@ -0,0 +1,191 @@
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+use crate::components::texture::{Texture, TextureData};
+
+// Metallic-roughness material parameters as they come out of glTF. Maps are multiplied by their factors,
+// a missing map counts as white (or as a flat normal for the normal map).
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub shading_model: ShadingModel,
+
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+
+    pub base_color_texture: Option<Texture>,
+    pub metallic_roughness_texture: Option<Texture>,
+    pub normal_texture: Option<Texture>,
+    pub occlusion_texture: Option<Texture>,
+    pub emissive_texture: Option<Texture>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadingModel {
+    // only uses the base color, for things that were never authored with pbr in mind
+    BlinnPhong,
+    // cook-torrance with the metallic-roughness workflow
+    Pbr,
+}
+
+impl Material {
+    // matches the glTF defaults
+    pub fn new() -> Self {
+        Self {
+            shading_model: ShadingModel::Pbr,
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+
+    // what entities that only have a Texture get drawn with
+    pub fn from_texture(texture: Option<Texture>) -> Self {
+        Self {
+            shading_model: ShadingModel::BlinnPhong,
+            metallic_factor: 0.0,
+            base_color_texture: texture,
+            ..Self::new()
+        }
+    }
+
+    // in the order the standard shader binds them
+    pub fn textures(&self) -> [&Option<Texture>; 5] {
+        [
+            &self.base_color_texture,
+            &self.metallic_roughness_texture,
+            &self.normal_texture,
+            &self.occlusion_texture,
+            &self.emissive_texture,
+        ]
+    }
+
+    // materials with the same maps share a descriptor set, the factors don't matter for it
+    pub(crate) fn textures_render_name(&self) -> String {
+        self.textures()
+            .iter()
+            .map(|texture| texture.as_ref().map_or(String::from("NULL_TEX"), |texture| texture.render_name()))
+            .collect::<Vec<String>>()
+            .join("|")
+    }
+
+    fn factor_bits(&self) -> Vec<u32> {
+        self.base_color_factor
+            .iter()
+            .chain(self.emissive_factor.iter())
+            .chain([self.metallic_factor, self.roughness_factor].iter())
+            .map(|factor| factor.to_bits())
+            .collect()
+    }
+}
+
+// factors are compared bit for bit so materials can be used as batch keys
+impl PartialEq for Material {
+    fn eq(&self, other: &Self) -> bool {
+        self.shading_model == other.shading_model
+            && self.factor_bits() == other.factor_bits()
+            && self.textures() == other.textures()
+    }
+}
+
+impl Eq for Material {}
+
+impl Hash for Material {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.shading_model.hash(state);
+        self.factor_bits().hash(state);
+
+        for texture in self.textures().iter() {
+            texture.hash(state);
+        }
+    }
+}
+
+// Maps are stored like Texture components are. Anything left out of a hand written scene keeps its
+// glTF default.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialData {
+    shading_model: ShadingModel,
+    base_color_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    emissive_factor: [f32; 3],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_color_texture: Option<TextureData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metallic_roughness_texture: Option<TextureData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normal_texture: Option<TextureData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    occlusion_texture: Option<TextureData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emissive_texture: Option<TextureData>,
+}
+
+impl Default for MaterialData {
+    fn default() -> Self {
+        let material = Material::new();
+
+        Self {
+            shading_model: material.shading_model,
+            base_color_factor: material.base_color_factor,
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            emissive_factor: material.emissive_factor,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl SceneComponent for Material {
+    type Data = MaterialData;
+
+    fn to_data(&self, context: &SaveContext) -> Result<MaterialData, String> {
+        let map = |texture: &Option<Texture>| texture.as_ref().map(|texture| texture.to_data(context)).transpose();
+
+        Ok(MaterialData {
+            shading_model: self.shading_model,
+            base_color_factor: self.base_color_factor,
+            metallic_factor: self.metallic_factor,
+            roughness_factor: self.roughness_factor,
+            emissive_factor: self.emissive_factor,
+            base_color_texture: map(&self.base_color_texture)?,
+            metallic_roughness_texture: map(&self.metallic_roughness_texture)?,
+            normal_texture: map(&self.normal_texture)?,
+            occlusion_texture: map(&self.occlusion_texture)?,
+            emissive_texture: map(&self.emissive_texture)?,
+        })
+    }
+
+    fn from_data(data: MaterialData, context: &mut LoadContext) -> Result<Self, String> {
+        let mut map = |texture: Option<TextureData>| texture.map(|texture| Texture::from_data(texture, context)).transpose();
+
+        Ok(Material {
+            shading_model: data.shading_model,
+            base_color_factor: data.base_color_factor,
+            metallic_factor: data.metallic_factor,
+            roughness_factor: data.roughness_factor,
+            emissive_factor: data.emissive_factor,
+            base_color_texture: map(data.base_color_texture)?,
+            metallic_roughness_texture: map(data.metallic_roughness_texture)?,
+            normal_texture: map(data.normal_texture)?,
+            occlusion_texture: map(data.occlusion_texture)?,
+            emissive_texture: map(data.emissive_texture)?,
+        })
+    }
+}
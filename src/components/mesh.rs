@@ -1,9 +1,24 @@
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
 use crate::primitives::vertex::Vertex;
 
 #[derive(Clone, Debug)]
 pub struct Mesh {
+    // meshes with the same key are assumed to have the same geometry and get drawn instanced
     pub key: String,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub rendered: bool,
 }
+
+// only the key is stored, the geometry gets loaded again from wherever the key says it came from
+impl SceneComponent for Mesh {
+    type Data = String;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<String, String> {
+        Ok(self.key.clone())
+    }
+
+    fn from_data(key: String, context: &mut LoadContext) -> Result<Self, String> {
+        context.mesh(&key)
+    }
+}
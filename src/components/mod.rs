@@ -3,4 +3,13 @@ pub mod transform;
 pub mod camera;
 pub mod texture;
 pub mod color;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod capture;
+pub mod material;
+pub mod parent;
+pub mod light;
+pub mod text;
+pub mod input_state;
+pub mod input_map;
+pub mod children;
+pub mod global_transform;
@@ -0,0 +1,20 @@
+use legion::Entity;
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+
+// the entity this one is attached to, its Transform is relative to the parent's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parent(pub Entity);
+
+// stored as the id of the parent in the same scene
+impl SceneComponent for Parent {
+    type Data = u64;
+
+    fn to_data(&self, context: &SaveContext) -> Result<u64, String> {
+        context.id(self.0)
+    }
+
+    fn from_data(id: u64, context: &mut LoadContext) -> Result<Self, String> {
+        context.entity(id).map(Parent)
+    }
+}
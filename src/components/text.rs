@@ -0,0 +1,49 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// A string drawn in the ui overlay. Everything is in pixels from the top left of the window, `position`
+// is the top of the first line and, depending on the alignment, its left edge, center or right edge.
+#[derive(Clone, Debug)]
+pub struct Text {
+    pub content: String,
+    pub position: [f32; 2],
+    pub size: f32,
+    pub color: [f32; 3],
+    pub align: TextAlign,
+    // lines wider than this wrap at the last space that still fits
+    pub max_width: Option<f32>,
+    pub rendered: bool,
+}
+
+impl Text {
+    pub fn new(content: &str, position: [f32; 2], size: f32) -> Self {
+        Self {
+            content: content.to_string(),
+            position,
+            size,
+            color: [1.0, 1.0, 1.0],
+            align: TextAlign::Left,
+            max_width: None,
+            rendered: true,
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
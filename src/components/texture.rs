@@ -1,13 +1,152 @@
 use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
+use crate::assets::gltf::gltf_source;
+
+// Textures are identified by their path and sampler, images that don't live in their own file (like the
+// ones embedded in a glb) get a made up path and carry their decoded pixels with them.
+#[derive(Clone, Debug)]
 pub struct Texture {
     pub(crate) path: String,
+    pub(crate) pixels: Option<Arc<TexturePixels>>,
+    pub sampler: SamplerOptions,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FilterMode {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WrapMode {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+// color textures are stored as srgb, data like normals or roughness has to be sampled as is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SamplerOptions {
+    pub filter: FilterMode,
+    pub wrap: WrapMode,
+    // max anisotropy, ignored when the device doesn't support anisotropic filtering
+    pub anisotropy: Option<u8>,
+    pub color_space: ColorSpace,
+    pub mipmaps: bool,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            filter: FilterMode::Linear,
+            wrap: WrapMode::Clamp,
+            anisotropy: None,
+            color_space: ColorSpace::Srgb,
+            mipmaps: true,
+        }
+    }
+}
+
+// tightly packed rgba8 rows
+#[derive(Clone)]
+pub struct TexturePixels {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for TexturePixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TexturePixels {{ width: {}, height: {} }}", self.width, self.height)
+    }
+}
+
+impl Texture {
+    // `path` is relative to the data directory
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            pixels: None,
+            sampler: SamplerOptions::default(),
+        }
+    }
+
+    pub fn from_pixels(path: &str, pixels: TexturePixels) -> Self {
+        Self {
+            path: path.to_string(),
+            pixels: Some(Arc::new(pixels)),
+            sampler: SamplerOptions::default(),
+        }
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerOptions) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    // the same image sampled differently needs its own gpu texture
+    pub(crate) fn render_name(&self) -> String {
+        format!("{}?{:?}", self.path, self.sampler)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextureData {
+    path: String,
+    #[serde(default)]
+    sampler: SamplerOptions,
+}
+
+// only textures that live in their own file or come out of a gltf file can be stored, they're loaded
+// from there again
+impl SceneComponent for Texture {
+    type Data = TextureData;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<TextureData, String> {
+        if self.pixels.is_some() && gltf_source(&self.path).is_none() {
+            return Err(format!("{} doesn't have a file of its own", self.path));
+        }
+
+        Ok(TextureData {
+            path: self.path.clone(),
+            sampler: self.sampler,
+        })
+    }
+
+    fn from_data(data: TextureData, context: &mut LoadContext) -> Result<Self, String> {
+        Ok(context.texture(&data.path)?.with_sampler(data.sampler))
+    }
+}
+
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.sampler == other.sampler
+    }
+}
+
+impl Eq for Texture {}
+
+impl Hash for Texture {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.sampler.hash(state);
+    }
 }
 
 impl Ord for Texture {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.path.cmp(&other.path)
+        self.render_name().cmp(&other.render_name())
     }
 }
 
@@ -9,7 +9,9 @@ use cgmath::{
     Angle
 };
 
-use crate::primitives::uniform_buffer_object::ObjectUniformBufferObject;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::component_registry::{LoadContext, SaveContext, SceneComponent};
 
 const UP_VECTOR: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
 
@@ -65,17 +67,56 @@ impl Transform {
         forward.cross(UP_VECTOR) * -1.0
     }
 
-    // TODO -> Turn this into From and Into impls!
-    pub fn to_ubo(&self) -> ObjectUniformBufferObject {
+    // relative to the parent if there is one, see GlobalTransform for where it ends up in the world
+    pub fn matrix(&self) -> Matrix4<f32> {
         let translation = Matrix4::from_translation(self.position);
 
         let scale = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
 
-        let mat = Matrix4::identity()
+        Matrix4::identity()
             .concat(&translation)
             .concat(&self.rotation.into())
-            .concat(&scale);
+            .concat(&scale)
+    }
+}
+
+// rotations are stored as euler angles in degrees since that's what people can write by hand
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransformData {
+    #[serde(default)]
+    position: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "unit_scale")]
+    scale: [f32; 3],
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl SceneComponent for Transform {
+    type Data = TransformData;
+
+    fn to_data(&self, _context: &SaveContext) -> Result<TransformData, String> {
+        let rotation = Euler::from(self.rotation);
+
+        Ok(TransformData {
+            position: self.position.into(),
+            rotation: [Deg::from(rotation.x).0, Deg::from(rotation.y).0, Deg::from(rotation.z).0],
+            scale: self.scale.into(),
+        })
+    }
 
-        ObjectUniformBufferObject::new(mat)
+    fn from_data(data: TransformData, _context: &mut LoadContext) -> Result<Self, String> {
+        Ok(Transform {
+            position: Vector3::from(data.position),
+            scale: Vector3::from(data.scale),
+            rotation: Quaternion::from(Euler {
+                x: Deg(data.rotation[0]),
+                y: Deg(data.rotation[1]),
+                z: Deg(data.rotation[2]),
+            }),
+        })
     }
 }
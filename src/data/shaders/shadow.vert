@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// has to match MAX_LIGHTS and MAX_SHADOW_MAPS in uniform_buffer_object.rs
+#define MAX_LIGHTS 16
+#define MAX_SHADOW_MAPS 4
+
+struct Light {
+    vec4 position;
+    vec4 direction;
+    vec4 color;
+    vec4 cutoffs;
+};
+
+layout(set = 0, binding = 0) uniform Lights {
+    vec4 ambient;
+    uvec4 light_count;
+    Light lights[MAX_LIGHTS];
+    mat4 shadow_view_proj[MAX_SHADOW_MAPS];
+} l_ubo;
+
+layout(push_constant) uniform ShadowPass {
+    uint shadow_map;
+} pass;
+
+layout(location = 0) in vec3 in_position;
+layout(location = 4) in mat4 in_model;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    gl_Position = l_ubo.shadow_view_proj[pass.shadow_map] * in_model * vec4(in_position, 1.0);
+}
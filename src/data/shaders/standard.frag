@@ -1,15 +1,196 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(set = 2, binding = 0) uniform sampler2D tex_sampler;
+// has to match MAX_LIGHTS and MAX_SHADOW_MAPS in uniform_buffer_object.rs
+#define MAX_LIGHTS 16
+#define MAX_SHADOW_MAPS 4
+
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
+#define BLINN_PHONG 0
+#define PBR 1
+
+#define SHININESS 32.0
+#define PI 3.14159265359
+
+struct Light {
+    vec4 position;  // w is the light type
+    vec4 direction; // w is the range
+    vec4 color;     // w is the intensity
+    vec4 cutoffs;   // x and y are the cosines of the inner and outer spot angles, z is the shadow map
+};
+
+// in the order of Material::textures, missing maps are bound to 1x1 stand-ins
+layout(set = 1, binding = 0) uniform sampler2D base_color_map;
+layout(set = 1, binding = 1) uniform sampler2D metallic_roughness_map;
+layout(set = 1, binding = 2) uniform sampler2D normal_map;
+layout(set = 1, binding = 3) uniform sampler2D occlusion_map;
+layout(set = 1, binding = 4) uniform sampler2D emissive_map;
+
+// has to match MaterialConstants in uniform_buffer_object.rs
+layout(push_constant) uniform MaterialFactors {
+    vec4 base_color;
+    vec4 emissive;           // w is the shading model
+    vec4 metallic_roughness; // x is metallic, y is roughness
+} material;
+
+layout(set = 2, binding = 0) uniform Lights {
+    vec4 ambient;
+    uvec4 light_count;
+    Light lights[MAX_LIGHTS];
+    mat4 shadow_view_proj[MAX_SHADOW_MAPS];
+} l_ubo;
+
+layout(set = 3, binding = 0) uniform sampler2DShadow shadow_maps[MAX_SHADOW_MAPS];
 
 layout(location = 0) in vec3 frag_color;
 layout(location = 1) in vec2 frag_tex_coord;
+layout(location = 2) in vec3 frag_world_position;
+layout(location = 3) in vec3 frag_normal;
+layout(location = 4) in vec3 frag_camera_position;
 
 layout(location = 0) out vec4 outColor;
 
-void main() {
-    outColor = texture(tex_sampler, frag_tex_coord);
-//     outColor = vec4(frag_color, 1.0);
+// smoothly reaches 0 at the range instead of cutting off
+float range_attenuation(float distance, float range) {
+    float falloff = clamp(1.0 - pow(distance / range, 2.0), 0.0, 1.0);
+    return falloff * falloff;
+}
+
+// Percentage closer filtering over a 3x3 grid of texels, each of which the comparison sampler already
+// blends bilinearly. Anything outside the light's frustum counts as lit.
+float shadow_factor(int shadow_map, vec3 world_position) {
+    vec4 light_clip = l_ubo.shadow_view_proj[shadow_map] * vec4(world_position, 1.0);
+    vec3 ndc = light_clip.xyz / light_clip.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(shadow_maps[shadow_map], 0));
+    float lit = 0.0;
+
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_maps[shadow_map], vec3(uv + vec2(x, y) * texel, ndc.z));
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// builds a tangent frame out of screen space derivatives so meshes don't need tangents
+vec3 perturb_normal(vec3 normal, vec3 position, vec2 uv) {
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float inv_max = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    mat3 tbn = mat3(tangent * inv_max, bitangent * inv_max, normal);
+
+    vec3 mapped = texture(normal_map, uv).xyz * 2.0 - 1.0;
+    return normalize(tbn * mapped);
+}
+
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a2 = pow(roughness, 4.0);
+    float denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
 }
 
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float k = pow(roughness + 1.0, 2.0) / 8.0;
+    float ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+void main() {
+    vec4 albedo = texture(base_color_map, frag_tex_coord) * material.base_color;
+    int shading_model = int(material.emissive.w);
+
+    vec3 normal = normalize(frag_normal);
+    if (shading_model == PBR) {
+        normal = perturb_normal(normal, frag_world_position, frag_tex_coord);
+    }
+    vec3 to_camera = normalize(frag_camera_position - frag_world_position);
+
+    // the glTF packing, roughness in green and metallic in blue
+    vec4 metallic_roughness = texture(metallic_roughness_map, frag_tex_coord);
+    float metallic = clamp(material.metallic_roughness.x * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(material.metallic_roughness.y * metallic_roughness.g, 0.04, 1.0);
+    vec3 f0 = mix(vec3(0.04), albedo.rgb, metallic);
+
+    float n_dot_v = max(dot(normal, to_camera), 0.0001);
+
+    vec3 lighting = vec3(0.0);
+
+    for (uint i = 0; i < min(l_ubo.light_count.x, MAX_LIGHTS); i++) {
+        Light light = l_ubo.lights[i];
+        int light_type = int(light.position.w);
+
+        vec3 to_light;
+        float attenuation = 1.0;
+
+        if (light_type == DIRECTIONAL_LIGHT) {
+            to_light = -normalize(light.direction.xyz);
+        } else {
+            vec3 offset = light.position.xyz - frag_world_position;
+            to_light = normalize(offset);
+            attenuation = range_attenuation(length(offset), light.direction.w);
+
+            if (light_type == SPOT_LIGHT) {
+                float cos_angle = dot(-to_light, normalize(light.direction.xyz));
+                attenuation *= smoothstep(light.cutoffs.y, light.cutoffs.x, cos_angle);
+            }
+        }
+
+        int shadow_map = int(light.cutoffs.z);
+        if (shadow_map >= 0) {
+            attenuation *= shadow_factor(shadow_map, frag_world_position);
+        }
+
+        vec3 radiance = light.color.rgb * light.color.w * attenuation;
+        vec3 halfway = normalize(to_light + to_camera);
+        float n_dot_l = max(dot(normal, to_light), 0.0);
+
+        if (shading_model == BLINN_PHONG) {
+            float specular = n_dot_l > 0.0 ? pow(max(dot(normal, halfway), 0.0), SHININESS) : 0.0;
+            lighting += (n_dot_l + specular) * radiance;
+        } else {
+            float n_dot_h = max(dot(normal, halfway), 0.0);
+            vec3 fresnel = fresnel_schlick(max(dot(halfway, to_camera), 0.0), f0);
+            float d = distribution_ggx(n_dot_h, roughness);
+            float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+            vec3 specular = d * g * fresnel / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+            vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo.rgb / PI;
+
+            lighting += (diffuse + specular) * radiance * n_dot_l;
+        }
+    }
+
+    vec3 color;
+    if (shading_model == BLINN_PHONG) {
+        color = albedo.rgb * (l_ubo.ambient.rgb + lighting);
+    } else {
+        float occlusion = texture(occlusion_map, frag_tex_coord).r;
+        vec3 emissive = texture(emissive_map, frag_tex_coord).rgb * material.emissive.rgb;
+        color = l_ubo.ambient.rgb * albedo.rgb * occlusion + lighting + emissive;
+    }
+
+    outColor = vec4(color, albedo.a);
+}
@@ -6,25 +6,31 @@ layout(set = 0, binding = 0) uniform StaticUnfiorms {
     mat4 proj;
 } s_ubo;
 
-layout(set = 1, binding = 0) uniform DynamicUniforms {
-    mat4 model;
-} d_ubo;
-
 layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec3 in_color;
 layout(location = 2) in vec2 in_tex_coord;
+layout(location = 3) in vec3 in_normal;
+layout(location = 4) in mat4 in_model;
 
 layout(location = 0) out vec3 frag_color;
 layout(location = 1) out vec2 frag_tex_coord;
+layout(location = 2) out vec3 frag_world_position;
+layout(location = 3) out vec3 frag_normal;
+layout(location = 4) out vec3 frag_camera_position;
 
 out gl_PerVertex {
     vec4 gl_Position;
 };
 
 void main() {
+    vec4 world_position = in_model * vec4(in_position, 1.0);
+
     frag_color = in_color;
     frag_tex_coord = in_tex_coord;
+    frag_world_position = world_position.xyz;
+    // inverse transpose so non uniform scaling doesn't skew the normals
+    frag_normal = mat3(transpose(inverse(in_model))) * in_normal;
+    frag_camera_position = inverse(s_ubo.view)[3].xyz;
 
-    gl_Position = s_ubo.proj * s_ubo.view * d_ubo.model * vec4(in_position, 1.0);
+    gl_Position = s_ubo.proj * s_ubo.view * world_position;
 }
-
@@ -1,17 +1,42 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ApplicationEvent {
-    KeyPress(KeyPress),
+    // repeated by the os for as long as the key is held
+    KeyPressed(VirtualKeyCode),
+    KeyReleased(VirtualKeyCode),
+    // raw movement of the mouse, not limited to the window
     MouseMotion { x: f64, y: f64 },
-    MouseScroll { delta: f64 },
+    // in lines
+    MouseScroll { x: f64, y: f64 },
+    WindowResized { width: u32, height: u32 },
+    // in pixels from the top left of the window
+    CursorMoved { x: f64, y: f64 },
+    MouseButtonPressed(MouseButton),
+    MouseButtonReleased(MouseButton),
+    ReceivedCharacter(char),
+    // an action from the input map started or ended, see `InputMap::actions_for`
+    ActionPressed(String),
+    ActionReleased(String),
+    // something a widget did, `key` is the key of the widget that did it
+    Widget { key: String, event: WidgetEvent },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u8),
 }
 
-#[derive(Clone)]
-pub enum KeyPress {
-    EscKey,
-    W,
-    A,
-    S,
-    D,
-    Space,
-    LShift,
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WidgetEvent {
+    Clicked,
+    Toggled(bool),
+    ValueChanged(f32),
+    TextChanged(String),
+    // enter was pressed in a text field
+    Submitted(String),
 }
@@ -1,4 +1,5 @@
-use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::path::PathBuf;
 
 use winit::event::{
     Event,
@@ -9,38 +10,65 @@ use winit::event::{
     TouchPhase,
     MouseScrollDelta,
     KeyboardInput,
-    VirtualKeyCode,
 };
 
+use crate::events::application_events;
 use crate::events::application_events::ApplicationEvent;
-use crate::events::application_events::KeyPress;
-use crate::primitives::two_d::widget::Widget;
-use crate::primitives::two_d::quad::Quad;
-use crate::components::camera::Camera;
-use crate::components::transform::Transform;
+use crate::events::application_events::WidgetEvent;
+use crate::events::recording::{InputSource, RecordedFrame};
+use crate::primitives::two_d::widget::{UiRoot, Widget};
 use crate::components::config::Config;
-
-use cgmath::Vector3;
+use crate::components::input_map::InputMap;
+use crate::components::input_state::InputState;
+use crate::components::capture::FrameCapture;
+use crate::timing::Time;
 
 use legion::World;
 use legion::query::{Read, Write, IntoQuery, Query};
 
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+// how many frames the capture_sequence action writes, two seconds at 60 fps
+const SEQUENCE_FRAMES: u32 = 120;
+// can be loaded again by pointing ENGINE_SCENE at it
+const SAVED_SCENE: &str = "scenes/saved.json";
+// what a line of scrolling is worth for touchpads that scroll in pixels
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
+
 // This struct takes all incoming window events and converts them to application events to be passed down to widgets
 pub struct EventHandler {
-    // todo -> not make this pub
-    pub application_events: Vec<ApplicationEvent>,
+    application_events: Vec<ApplicationEvent>,
+    input_source: InputSource,
 
     prev_mouse_position: (f64, f64),
+
+    // set from the esc menu
+    screenshot_directory: String,
 }
 
 impl EventHandler {
     pub fn new() -> EventHandler {
         EventHandler {
             application_events: vec![],
-            prev_mouse_position: (0.0, 0.0)
+            input_source: InputSource::Live,
+            prev_mouse_position: (0.0, 0.0),
+            screenshot_directory: String::from(SCREENSHOT_DIRECTORY),
         }
     }
 
+    pub fn with_input_source(mut self, input_source: InputSource) -> Self {
+        self.input_source = input_source;
+        self
+    }
+
+    // events from the window, dropped while a replay is feeding the handler instead
+    pub fn push_events(&mut self, mut events: Vec<ApplicationEvent>) {
+        if let InputSource::Replay(_) = self.input_source {
+            return;
+        }
+
+        self.application_events.append(&mut events);
+    }
+
     // pub fn read_events_from_event_loop(&mut self)
     //     -> impl FnMut(Event<()>, &EventLoopWindowTarget<()>, &mut ControlFlow) {
     //     return move |event, _, control_flow| {
@@ -59,85 +87,177 @@ impl EventHandler {
     //     }
     // }
 
-    pub fn handle_events(&mut self, world: &World) {
-        for event in self.application_events.drain(0..) {
-            match event {
-                ApplicationEvent::KeyPress(key) => match key {
-                    KeyPress::EscKey => {
-                        if let Some(menu) = <Write<Quad>>::query().iter(world).next() {
-                            menu.rendered = !menu.rendered;
+    // Widgets get first pick of every event, whatever they use up doesn't reach the rest of the
+    // application. Events the widgets emit are handled right after the one that caused them.
+    pub fn handle_events(&mut self, world: &World, time: &mut Time) {
+        self.sync_input_source(time);
+
+        if let Some(input) = <Write<InputState>>::query().iter(world).next() {
+            input.begin_frame();
+        }
+
+        let mut events = self.application_events.drain(0..).collect::<VecDeque<ApplicationEvent>>();
+
+        while let Some(event) = events.pop_front() {
+            let mut handled = false;
+
+            for root in <Write<UiRoot>>::query().iter(world) {
+                if Self::widget_should_receive_event(&event, root.widget()) {
+                    handled |= root.widget_mut().on(&event);
+                }
+
+                events.extend(root.widget_mut().events());
+            }
 
-                            if let Some(config) = <Write<Config>>::query().iter(world).next() {
-                                config.should_record_commands = true;
+            // a widget can take a key's press but the release still has to reach the input state,
+            // otherwise the key would stay held forever
+            let is_release = match event {
+                ApplicationEvent::KeyReleased(_) | ApplicationEvent::MouseButtonReleased(_) => true,
+                _ => false,
+            };
+
+            if !handled || is_release {
+                if let Some(input) = <Write<InputState>>::query().iter(world).next() {
+                    let repeat = input.is_repeat(&event);
+                    input.handle(&event);
+
+                    // Whatever the event starts or ends goes next, so widgets hear about it as well. A
+                    // release a widget took still ends the actions its press started.
+                    if !repeat {
+                        if let Some(input_map) = <Read<InputMap>>::query().iter(world).next() {
+                            for action in input_map.actions_for(&event, &mut *input).into_iter().rev() {
+                                events.push_front(action);
                             }
                         }
-                    },
-                    KeyPress::W => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(0.0, 0.0, 1.0));
-                        }
-                    },
-                    KeyPress::A => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(-1.0, 0.0, 0.0));
-                        }
-                    },
-                    KeyPress::S => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(0.0, 0.0, -1.0));
-                        }
-                    },
-                    KeyPress::D => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(1.0, 0.0, 0.0));
-                        }
-                    },
-                    KeyPress::Space => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(0.0, 1.0, 0.0));
-                        }
-                    },
-                    KeyPress::LShift => {
-                        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                            transform.translate(Vector3::new(0.0, -1.0, 0.0));
-                        }
-                    },
-                },
-                ApplicationEvent::MouseMotion { x, y} => {
-                    for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
-                        let x_diff = (x * -0.1) as f32;
-                        let y_diff = (y * -0.1) as f32;
+                    }
+                }
+            }
+
+            if handled {
+                continue;
+            }
 
-                        transform.rotate(x_diff, y_diff, 0.0);
+            match event {
+                ApplicationEvent::ActionReleased(ref action) if action == "screenshot" => {
+                    if let Some(config) = <Write<Config>>::query().iter(world).next() {
+                        config.frame_capture = Some(FrameCapture::screenshot(PathBuf::from(&self.screenshot_directory)));
+                    }
+                },
+                ApplicationEvent::ActionReleased(ref action) if action == "capture_sequence" => {
+                    if let Some(config) = <Write<Config>>::query().iter(world).next() {
+                        config.frame_capture = Some(FrameCapture::recording(PathBuf::from(&self.screenshot_directory), SEQUENCE_FRAMES));
                     }
                 },
-                ApplicationEvent::MouseScroll { delta: _delta } => {
+                ApplicationEvent::ActionReleased(ref action) if action == "save_scene" => {
+                    if let Some(config) = <Write<Config>>::query().iter(world).next() {
+                        config.save_scene = Some(String::from(SAVED_SCENE));
+                    }
+                },
+                ApplicationEvent::WindowResized { .. } => {
+                    if let Some(config) = <Write<Config>>::query().iter(world).next() {
+                        config.should_recreate_swapchain = true;
+                    }
+                },
+                ApplicationEvent::Widget { key, event } => self.handle_widget_event(world, &key, event),
+                ApplicationEvent::ActionPressed(_)
+                | ApplicationEvent::ActionReleased(_)
+                | ApplicationEvent::MouseMotion { .. }
+                | ApplicationEvent::KeyPressed(_)
+                | ApplicationEvent::KeyReleased(_)
+                | ApplicationEvent::MouseScroll { .. }
+                | ApplicationEvent::CursorMoved { .. }
+                | ApplicationEvent::MouseButtonPressed(_)
+                | ApplicationEvent::MouseButtonReleased(_)
+                | ApplicationEvent::ReceivedCharacter(_) => (),
+            }
+        }
+    }
+
+    // Writes this frame's events to the recording, or swaps them for the next recorded frame along with
+    // the delta the systems ran with back then. Once a replay runs out the window takes over again.
+    fn sync_input_source(&mut self, time: &mut Time) {
+        let finished = match &mut self.input_source {
+            InputSource::Live => false,
+            InputSource::Record(recorder) => {
+                let frame = RecordedFrame {
+                    time: time.total_time() as u64,
+                    delta_time: time.delta_time as u64,
+                    events: self.application_events.clone(),
+                };
+
+                recorder.record(&frame).map_err(|e| log::error!("stopped recording input: {}", e)).is_err()
+            },
+            InputSource::Replay(replay) => match replay.next_frame() {
+                Some(frame) => {
+                    self.application_events = frame.events;
+                    time.delta_time = frame.delta_time as u128;
+                    false
+                },
+                None => {
+                    log::info!("replay finished, switching to live input");
+                    true
+                },
+            },
+        };
+
+        if finished {
+            self.input_source = InputSource::Live;
+        }
+    }
 
+    fn handle_widget_event(&mut self, world: &World, key: &str, event: WidgetEvent) {
+        let input_map = <Write<InputMap>>::query().iter(world).next();
+
+        match (key, event, input_map) {
+            ("mouse_sensitivity", WidgetEvent::ValueChanged(value), Some(input_map)) => {
+                for axis in &["look_x", "look_y"] {
+                    for binding in input_map.axis_bindings_mut(axis) {
+                        binding.scale = value;
+                    }
                 }
-            }
+            },
+            ("invert_mouse_y", WidgetEvent::Toggled(checked), Some(input_map)) => {
+                for binding in input_map.axis_bindings_mut("look_y") {
+                    binding.invert = checked;
+                }
+            },
+            ("screenshot_directory", WidgetEvent::Submitted(directory), _) => self.screenshot_directory = directory,
+            _ => (),
         }
     }
 
-    fn widget_should_receive_event(_event: ApplicationEvent, _widget: Arc<Mutex<dyn Widget>>) -> bool {
-        // todo -> calculate if this widget should receive the event
-        false
+    // Pointer events go to widgets under the cursor, and to widgets that need to see the cursor leave, a
+    // button being released, or a click somewhere else taking their focus away. Typed characters only go
+    // to focused widgets, everything else goes to every widget.
+    pub fn widget_should_receive_event(event: &ApplicationEvent, widget: &dyn Widget) -> bool {
+        let state = widget.state();
+
+        match event {
+            ApplicationEvent::CursorMoved { x, y } => {
+                widget.rect().contains([*x as f32, *y as f32]) || state.hovered || state.pressed
+            },
+            ApplicationEvent::MouseButtonPressed(_) => state.hovered || state.focused,
+            ApplicationEvent::MouseButtonReleased(_) => state.hovered || state.pressed,
+            ApplicationEvent::ReceivedCharacter(_) => state.focused,
+            _ => true,
+        }
     }
 
     pub fn transform_event(window_event: Event<()>) -> Vec<ApplicationEvent> {
         return match window_event {
             Event::WindowEvent { event, .. } => {
                 match event {
-                    WindowEvent::Resized(_) => vec![],
+                    WindowEvent::Resized(size) => Self::handle_resize(size.width, size.height),
                     WindowEvent::Moved(_) => vec![],
                     WindowEvent::CloseRequested => panic!("matthew's horrible way of handling window exit"),
                     WindowEvent::Destroyed => vec![],
                     WindowEvent::DroppedFile(_) => vec![],
                     WindowEvent::HoveredFile(_) => vec![],
                     WindowEvent::HoveredFileCancelled => vec![],
-                    WindowEvent::ReceivedCharacter(_) => vec![],
+                    WindowEvent::ReceivedCharacter(c) => vec![ApplicationEvent::ReceivedCharacter(c)],
                     WindowEvent::Focused(_) => vec![],
                     WindowEvent::KeyboardInput { input, .. } => Self::handle_keyboard_input(input),
-                    WindowEvent::CursorMoved { .. } => vec![],
+                    WindowEvent::CursorMoved { position, .. } => vec![ApplicationEvent::CursorMoved { x: position.x, y: position.y }],
                     WindowEvent::CursorEntered { .. } => vec![],
                     WindowEvent::CursorLeft { .. } => vec![],
                     WindowEvent::MouseWheel { delta, phase, .. } => Self::handle_mouse_scroll(delta, phase),
@@ -145,7 +265,7 @@ impl EventHandler {
                     WindowEvent::TouchpadPressure { .. } => vec![],
                     WindowEvent::AxisMotion { .. } => vec![],
                     WindowEvent::Touch(_) => vec![],
-                    WindowEvent::ScaleFactorChanged { scale_factor: _, new_inner_size: _ } => vec![],
+                    WindowEvent::ScaleFactorChanged { scale_factor: _, new_inner_size } => Self::handle_resize(new_inner_size.width, new_inner_size.height),
                     WindowEvent::ThemeChanged(_) => vec![],
                     WindowEvent::ModifiersChanged(_) => vec![],
                 }
@@ -174,66 +294,43 @@ impl EventHandler {
     }
 
     fn handle_keyboard_input(input: KeyboardInput) -> Vec<ApplicationEvent> {
-        // println!("\n");
-        // println!("keyboard input!");
-        // println!("\tscancode {:?}", input.scancode);
-        // println!("\tstate: {:?}", input.state);
-        // println!("\tvirtual_keycode: {:?}", input.virtual_keycode);
-        // println!("\tmodifiers: {:?}", input.modifiers);
-        // println!("\n");
-
-        return match input.virtual_keycode {
-            Some(key_code) => {
-                let key_press = match key_code {
-                    VirtualKeyCode::A => Some(KeyPress::A),
-                    VirtualKeyCode::D => Some(KeyPress::D),
-                    VirtualKeyCode::S => Some(KeyPress::S),
-                    VirtualKeyCode::W => Some(KeyPress::W),
-                    VirtualKeyCode::Escape => Some(KeyPress::EscKey),
-                    VirtualKeyCode::Space => Some(KeyPress::Space),
-                    VirtualKeyCode::LShift => Some(KeyPress::LShift),
-                    _ => None,
-                };
-
-                // ui_layer.lock().unwrap().add_geometry(new_vertices, new_indices);
-                return if input.state == winit::event::ElementState::Released {
-                    match key_press {
-                        Some(k) => vec![ApplicationEvent::KeyPress(k)],
-                        None => vec![]
-                    }
-                } else {
-                    vec![]
-                }
-            },
-            _ => vec![],
+        match (input.virtual_keycode, input.state) {
+            (Some(key), ElementState::Pressed) => vec![ApplicationEvent::KeyPressed(key)],
+            (Some(key), ElementState::Released) => vec![ApplicationEvent::KeyReleased(key)],
+            (None, _) => vec![],
         }
     }
 
-    fn handle_mouse_scroll(_delta: MouseScrollDelta, _phase: TouchPhase) -> Vec<ApplicationEvent> {
-        // println!("\n");
-        // println!("mouse scroll!");
-        // println!("\tdelta: {:?}", delta);
-        // println!("\tphase: {:?}", phase);
-        // println!("\tmodifiers: {:?}", modifiers);
-        // println!("\n");
+    fn handle_mouse_scroll(delta: MouseScrollDelta, _phase: TouchPhase) -> Vec<ApplicationEvent> {
+        let (x, y) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+            MouseScrollDelta::PixelDelta(position) => (position.x / PIXELS_PER_SCROLL_LINE, position.y / PIXELS_PER_SCROLL_LINE),
+        };
 
-        vec![]
+        vec![ApplicationEvent::MouseScroll { x, y }]
     }
 
-    fn handle_mouse_click(_state: ElementState, _button: MouseButton) -> Vec<ApplicationEvent> {
-        // println!("\n");
-        // println!("mouse input!");
-        // println!("\tstate {:?}", state);
-        // println!("\tbutton: {:?}", button);
-        // println!("\tmodifiers: {:?}", modifiers);
-        // println!("\n");
+    fn handle_mouse_click(state: ElementState, button: MouseButton) -> Vec<ApplicationEvent> {
+        let button = match button {
+            MouseButton::Left => application_events::MouseButton::Left,
+            MouseButton::Right => application_events::MouseButton::Right,
+            MouseButton::Middle => application_events::MouseButton::Middle,
+            MouseButton::Other(other) => application_events::MouseButton::Other(other),
+        };
 
-        vec![]
+        match state {
+            ElementState::Pressed => vec![ApplicationEvent::MouseButtonPressed(button)],
+            ElementState::Released => vec![ApplicationEvent::MouseButtonReleased(button)],
+        }
     }
 
     fn handle_mouse_motion(delta: (f64, f64)) -> Vec<ApplicationEvent> {
         vec![ApplicationEvent::MouseMotion { x: delta.0, y: delta.1 }]
     }
+
+    fn handle_resize(width: u32, height: u32) -> Vec<ApplicationEvent> {
+        vec![ApplicationEvent::WindowResized { width, height }]
+    }
 }
 
 //                              copied from renderer::draw_frame
@@ -1,2 +1,3 @@
 pub mod application_events;
-pub mod event_handler;
\ No newline at end of file
+pub mod event_handler;
+pub mod recording;
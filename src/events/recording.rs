@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::events::application_events::ApplicationEvent;
+
+// where to write a recording, or which one to replay, and the seed for everything random
+const RECORD_VARIABLE: &str = "ENGINE_RECORD";
+const REPLAY_VARIABLE: &str = "ENGINE_REPLAY";
+const SEED_VARIABLE: &str = "ENGINE_SEED";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordingHeader {
+    seed: u64,
+}
+
+// everything the event handler was given for one frame
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    // milliseconds since the engine started
+    pub time: u64,
+    // the frame delta the systems ran with
+    pub delta_time: u64,
+    pub events: Vec<ApplicationEvent>,
+}
+
+// Writes a header with the seed and then a line per frame, flushed as it goes so a recording survives
+// the engine crashing, which is usually when it's wanted.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, seed: u64) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("can't create recording {:?}: {}", path, e))?;
+        let mut recorder = Self { writer: BufWriter::new(file) };
+        recorder.write_line(&RecordingHeader { seed })?;
+
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, frame: &RecordedFrame) -> Result<(), String> {
+        self.write_line(frame)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), String> {
+        let line = ron::ser::to_string(value).map_err(|e| format!("can't serialize recording: {}", e))?;
+
+        writeln!(self.writer, "{}", line)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("can't write recording: {}", e))
+    }
+}
+
+pub struct InputReplay {
+    seed: u64,
+    frames: VecDeque<RecordedFrame>,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("can't open recording {:?}: {}", path, e))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header = lines.next().ok_or_else(|| format!("recording {:?} is empty", path))?;
+        let header: RecordingHeader = Self::parse_line(header)?;
+
+        let frames = lines
+            .map(|line| Self::parse_line(line))
+            .collect::<Result<VecDeque<RecordedFrame>, String>>()?;
+
+        Ok(Self {
+            seed: header.seed,
+            frames,
+        })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+
+    fn parse_line<T: for<'de> Deserialize<'de>>(line: std::io::Result<String>) -> Result<T, String> {
+        let line = line.map_err(|e| format!("can't read recording: {}", e))?;
+        ron::de::from_str(&line).map_err(|e| format!("can't parse recording: {}", e))
+    }
+}
+
+// where the event handler gets its events from
+pub enum InputSource {
+    Live,
+    Record(InputRecorder),
+    Replay(InputReplay),
+}
+
+impl InputSource {
+    // Picks the source and the seed from the environment. A replay brings its own seed, otherwise it's
+    // whatever `ENGINE_SEED` says or a random one.
+    pub fn from_env() -> Result<(InputSource, u64), String> {
+        if let Some(path) = std::env::var_os(REPLAY_VARIABLE) {
+            let replay = InputReplay::load(&PathBuf::from(path))?;
+            let seed = replay.seed();
+
+            return Ok((InputSource::Replay(replay), seed));
+        }
+
+        let seed = match std::env::var(SEED_VARIABLE) {
+            Ok(seed) => seed.parse::<u64>().map_err(|e| format!("{} isn't a number: {}", SEED_VARIABLE, e))?,
+            Err(_) => rand::thread_rng().gen(),
+        };
+
+        match std::env::var_os(RECORD_VARIABLE) {
+            Some(path) => Ok((InputSource::Record(InputRecorder::create(&PathBuf::from(path), seed)?), seed)),
+            None => Ok((InputSource::Live, seed)),
+        }
+    }
+}
@@ -58,13 +58,16 @@ mod components;
 mod timing;
 mod systems;
 mod utils;
+mod assets;
 
 use std::sync::{
     Arc,
     RwLock
 };
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde_json::json;
 
 use cgmath::Vector3;
 
@@ -72,30 +75,55 @@ use crate::components::{
     camera::Camera,
     color::Color,
     config::Config,
+    global_transform::GlobalTransform,
+    input_map::InputMap,
+    input_state::InputState,
+    light::{DirectionalLight, PointLight, SpotLight},
+    material::Material,
     mesh::Mesh,
+    text::Text,
     texture::Texture,
     transform::Transform,
 };
 use crate::primitives::{
     drawable::Drawable,
-    three_d::cube::Cube,
-    uniform_buffer_object::ObjectUniformBufferObject,
+    two_d::{quad::Quad, widget::{EscMenu, UiRoot}},
+    uniform_buffer_object::{ObjectUniformBufferObject, LightsUniformBufferObject},
 };
 use crate::timing::Time;
-use crate::systems::rotation::Rotation;
+use crate::utils::data_path;
+use crate::systems::input::InputSystem;
+use crate::systems::transform_propagation::TransformPropagation;
+use crate::systems::schedule::{Schedule, Stage};
 use crate::events::event_handler::EventHandler;
+use crate::events::recording::InputSource;
+use crate::assets::component_registry::ComponentRegistry;
+use crate::assets::prefab::{Overrides, PrefabLibrary};
+use crate::assets::scene::{load_scene, save_scene};
 
 use legion::Universe;
 use legion::query::{Read, Write, IntoQuery, Query};
+#[cfg(not(feature = "headless"))]
 use winit::event::{Event, WindowEvent};
+#[cfg(not(feature = "headless"))]
 use winit::event_loop::ControlFlow;
 use crate::renderer::{
     core::RendererCore,
     allocator::GfxAllocator,
+    capture::CapturedFrame,
     drawer::{Drawer, GfxDrawer},
-    presenter::{Presenter, MonitorPresenter, XrPresenter}
+    presenter::Presenter,
 };
-
+#[cfg(all(not(feature = "headless"), not(feature = "xr")))]
+use crate::renderer::presenter::MonitorPresenter;
+#[cfg(all(not(feature = "headless"), feature = "xr"))]
+use crate::renderer::presenter::XrPresenter;
+#[cfg(feature = "headless")]
+use crate::renderer::presenter::{OffscreenPresenter, DIMS};
+#[cfg(feature = "headless")]
+use crate::components::capture::FrameCapture;
+
+#[cfg(not(feature = "headless"))]
 fn main() {
     env_logger::init();
 
@@ -109,17 +137,18 @@ fn main() {
     let allocator = Arc::new(RwLock::new(GfxAllocator::new(&renderer_core)));
 
     #[cfg(feature = "xr")]
-    let mut presenter = XrPresenter::new(&renderer_core, &allocator);
+    let presenter = XrPresenter::new(&renderer_core, &allocator);
 
     #[cfg(not(feature = "xr"))]
-    let mut presenter = MonitorPresenter::new(&renderer_core, &allocator);
+    let presenter = MonitorPresenter::new(&renderer_core, &allocator);
 
     let (images, image_format) = presenter.images();
     let drawer = GfxDrawer::new(&renderer_core, &allocator, presenter.viewport(), images, image_format);
 
-    let event_handler = Arc::new(RwLock::new(EventHandler::new()));
+    let (input_source, seed) = InputSource::from_env().expect("Can't set up input recording");
+    let event_handler = Arc::new(RwLock::new(EventHandler::new().with_input_source(input_source)));
 
-    start_engine(drawer, presenter, &event_handler);
+    start_engine(drawer, presenter, Config::new(), seed, &event_handler);
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -132,50 +161,124 @@ fn main() {
             },
         }
 
-        let mut new_events = EventHandler::transform_event(event);
-        event_handler.write().unwrap().application_events.append(&mut new_events);
+        event_handler.write().unwrap().push_events(EventHandler::transform_event(event));
     });
 }
 
-fn start_engine<B: hal::Backend, D: Drawer<B> + 'static, P: Presenter<B> + 'static>(mut drawer: D, mut presenter: P, event_handler_shared: &Arc<RwLock<EventHandler>>) {
+#[cfg(feature = "headless")]
+const HEADLESS_CAPTURE_DIRECTORY: &str = "captures";
+#[cfg(feature = "headless")]
+const HEADLESS_CAPTURE_FRAMES: u32 = 60;
+// a png the last captured frame has to match, for catching rendering regressions
+#[cfg(feature = "headless")]
+const GOLDEN_VARIABLE: &str = "ENGINE_GOLDEN";
+
+// Renders without a window or surface. Nothing feeds the event handler unless ENGINE_REPLAY points at a
+// recording, so the scene either runs on its own or plays back exactly what was recorded.
+#[cfg(feature = "headless")]
+fn main() {
+    env_logger::init();
+
+    let renderer_core = Arc::new(RwLock::new(RendererCore::new_headless()));
+    let allocator = Arc::new(RwLock::new(GfxAllocator::new(&renderer_core)));
+
+    let presenter = OffscreenPresenter::new(&renderer_core, &allocator, DIMS);
+
+    let (images, image_format) = presenter.images();
+    let drawer = GfxDrawer::new(&renderer_core, &allocator, presenter.viewport(), images, image_format);
+
+    let (input_source, seed) = InputSource::from_env().expect("Can't set up input recording");
+    let event_handler = Arc::new(RwLock::new(EventHandler::new().with_input_source(input_source)));
+
+    let mut frame_capture = FrameCapture::sequence(HEADLESS_CAPTURE_DIRECTORY.into(), HEADLESS_CAPTURE_FRAMES).exit_when_done();
+    if let Ok(golden) = std::env::var(GOLDEN_VARIABLE) {
+        frame_capture = frame_capture.with_golden(golden.into());
+    }
+
+    let config = Config {
+        frame_capture: Some(frame_capture),
+        ..Config::new()
+    };
+
+    let result = start_engine(drawer, presenter, config, seed, &event_handler)
+        .join()
+        .unwrap();
+
+    if let Err(e) = result {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// how long to wait before trying again while the swapchain can't be recreated, like while minimized
+const SWAPCHAIN_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+// The engine thread only finishes once a capture asks it to, with an error if the capture failed its golden image.
+fn start_engine<B: hal::Backend, D: Drawer<B> + 'static, P: Presenter<B> + 'static>(mut drawer: D, mut presenter: P, config: Config, seed: u64, event_handler_shared: &Arc<RwLock<EventHandler>>) -> std::thread::JoinHandle<Result<(), String>> {
     let event_handler = event_handler_shared.clone();
 
     std::thread::spawn(move || {
         let time = Arc::new(RwLock::new(Time::new()));
-        let rotation_system = Rotation::new(&time);
+        // everything random comes from the seed so a replay builds the same scene
+        log::info!("seed {}", seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut schedule = Schedule::new()
+            .expect("Can't create the system schedule")
+            .with_system(Stage::Input, InputSystem::new(&event_handler, &time))
+            .with_system(Stage::RenderExtract, TransformPropagation::new());
+        systems::register(&mut schedule, &time, rng.gen());
 
         // Create a world to store our entities
         // TODO -> create universe with logger
         let universe = Universe::new(None);
         let mut world = universe.create_world();
 
+        let registry = ComponentRegistry::default();
+        let mut prefabs = PrefabLibrary::new(PREFAB_DIRECTORY);
+
+        load_scene_or_default(&mut world, &registry, &mut prefabs);
+        spawn_cubes(&mut world, &registry, &mut prefabs, 64, &mut rng);
+        world.insert_from(
+            (),
+            vec![(UiRoot::new(EscMenu::new()),)],
+        );
+        let fps_counter = world.insert_from(
+            (),
+            vec![(Text::new("", [8.0, 8.0], 18.0),)],
+        )[0];
         world.insert_from(
             (),
-            vec![(Transform::new(), Camera { displaying: true })],
+            vec![(config,)],
         );
         world.insert_from(
             (),
-            generate_n_objs(64),
+            vec![(InputState::new(),)],
         );
         world.insert_from(
             (),
-            vec![(Config::new() ,)],
+            vec![(load_input_map(),)],
         );
 
-        drawer.update_drawables(fetch_drawables(&world));
+        if let Err(e) = drawer.update_drawables(fetch_drawables(&world)) {
+            log::error!("failed to update drawables: {}", e);
+        }
 
-        loop {
-            event_handler.write().unwrap().handle_events(&world);
+        // so a swapchain that can't be recreated for a while only gets logged once
+        let mut swapchain_failing = false;
 
-            // TODO -> run all systems
-            rotation_system.run(&world);
+        loop {
+            schedule.run(&world);
+            save_requested_scene(&world, &registry);
 
             // update frame timing
             time.write().unwrap().tick();
 
             let mut need_to_update_config = false;
             if <Read<Config>>::query().iter(&mut world).next().unwrap().should_record_commands {
-                drawer.update_drawables(fetch_drawables(&world));
+                if let Err(e) = drawer.update_drawables(fetch_drawables(&world)) {
+                    log::error!("failed to update drawables: {}", e);
+                }
                 need_to_update_config = true;
             }
 
@@ -188,57 +291,246 @@ fn start_engine<B: hal::Backend, D: Drawer<B> + 'static, P: Presenter<B> + 'stat
                 config.should_record_commands = false;
             }
 
+            if presenter.needs_recreation() || <Read<Config>>::query().iter(&mut world).next().unwrap().should_recreate_swapchain {
+                if let Err(e) = recreate_swapchain(&mut drawer, &mut presenter) {
+                    if !swapchain_failing {
+                        log::warn!("skipping frames until the swapchain can be recreated: {}", e);
+                        swapchain_failing = true;
+                    }
+
+                    std::thread::sleep(SWAPCHAIN_RETRY_DELAY);
+                    continue;
+                }
+
+                if swapchain_failing {
+                    log::info!("recreated swapchain");
+                    swapchain_failing = false;
+                }
+
+                let config = <Write<Config>>::query()
+                    .iter(&mut world)
+                    .next()
+                    .unwrap();
+
+                config.should_recreate_swapchain = false;
+            }
+
             drawer.update_uniforms(fetch_uniforms(&world)).unwrap();
-            drawer.update_camera(fetch_camera_transform(&world)).unwrap();
-            let image_index = presenter.acquire_image().unwrap();
+            if let Some((camera, camera_transform, camera_global)) = fetch_camera(&world) {
+                drawer.update_camera(camera, camera_transform, camera_global).unwrap();
+            }
+            drawer.update_lights(fetch_lights(&world)).unwrap();
+
+            update_fps_counter(&world, fps_counter, &time.read().unwrap());
+            layout_ui(&world, &presenter.viewport());
+            if let Err(e) = drawer.update_ui(fetch_quads(&world), fetch_texts(&world)) {
+                log::error!("failed to update ui: {}", e);
+            }
+
+            let image_index = match presenter.acquire_image() {
+                Ok(image_index) => image_index,
+                Err(e) => {
+                    log::warn!("skipping frame, could not acquire image: {}", e);
+                    continue;
+                }
+            };
+
             let (acquire_semaphore, present_semaphore) = presenter.semaphores();
             drawer.draw(image_index as usize, acquire_semaphore, present_semaphore);
-            presenter.present();
+
+            let capture_result = capture_frame(&mut presenter, &world);
+
+            if let Err(e) = presenter.present() {
+                log::warn!("failed to present image {}: {}", image_index, e);
+            }
+
+            if let Some(result) = capture_result {
+                break result;
+            }
         }
-    });
+    })
 }
 
-fn generate_n_objs(n: u32) -> Vec<(Transform, Mesh, Texture)> {
-    let mut objects = Vec::new();
-    let mut rng = rand::thread_rng();
+const INPUT_BINDINGS: &str = "config/input.ron";
+const DEFAULT_SCENE: &str = "scenes/default.json";
+const PREFAB_DIRECTORY: &str = "prefabs";
+// a scene to load instead of the default one, relative to the data directory
+const SCENE_VARIABLE: &str = "ENGINE_SCENE";
 
-    for _i in 0..n {
-        let (mut transform, mesh) = Cube::new();
-        let x = rng.gen_range(-15.0, 15.0);
-        let y = rng.gen_range(-15.0, 15.0);
-        let z = rng.gen_range(-15.0, 15.0);
-        transform.translate(Vector3::new(x, y, z));
-
-        let i: u32 = rng.gen_range(0, 3);
-
-        let texture = Texture {
-            path: match i {
-                0 => "textures/container.jpg".into(),
-                1 => "textures/demo.jpg".into(),
-                2 => "textures/wall.jpg".into(),
-                _ => unreachable!()
-            }
-        };
+// A broken scene still gets a light to see by, and any scene without a camera gets a default one so
+// there's something to look through.
+fn load_scene_or_default(world: &mut legion::World, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) {
+    let path = std::env::var(SCENE_VARIABLE).unwrap_or_else(|_| DEFAULT_SCENE.to_string());
+
+    if let Err(e) = load_scene(world, registry, prefabs, &path) {
+        log::error!("{}", e);
+
+        world.insert_from(
+            (),
+            vec![(DirectionalLight { direction: Vector3::new(-0.4, -1.0, -0.6), color: [1.0, 1.0, 1.0], intensity: 1.0, casts_shadows: true },)],
+        );
+    }
+
+    if fetch_camera(world).is_none() {
+        log::warn!("there's no camera in {}, adding a default one", path);
+
+        world.insert_from(
+            (),
+            vec![(Transform::new(), GlobalTransform::identity(), Camera::default())],
+        );
+    }
+}
+
+fn save_requested_scene(world: &legion::World, registry: &ComponentRegistry) {
+    let path = match <Write<Config>>::query().iter(world).next().and_then(|config| config.save_scene.take()) {
+        Some(path) => path,
+        None => return,
+    };
 
-        objects.push((transform, mesh, texture));
+    match save_scene(world, registry, &path) {
+        Ok(()) => log::info!("saved scene to {}", path),
+        Err(e) => log::error!("failed to save scene: {}", e),
     }
+}
+
+// without any bindings nothing responds to input, but the scene still runs
+fn load_input_map() -> InputMap {
+    InputMap::load(&data_path(INPUT_BINDINGS)).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        InputMap::new()
+    })
+}
+
+fn recreate_swapchain<B: hal::Backend, D: Drawer<B>, P: Presenter<B>>(drawer: &mut D, presenter: &mut P) -> Result<(), String> {
+    presenter.recreate_swapchain()?;
+
+    let (images, image_format) = presenter.images();
+    drawer.rebuild_framebuffers(presenter.viewport(), images, image_format)
+}
+
+// Writes the frame that was just drawn to disk if a capture was requested. Returns the result of the
+// golden image check once a capture that asked to exit the engine has written its last frame.
+fn capture_frame<B: hal::Backend, P: Presenter<B>>(presenter: &mut P, world: &legion::World) -> Option<Result<(), String>> {
+    let config = <Write<Config>>::query()
+        .iter(world)
+        .next()
+        .unwrap();
+
+    let frame_capture = config.frame_capture.as_mut()?;
+    let path = frame_capture.next_path();
+    let frame = presenter.capture_frame();
+
+    if let Err(e) = frame.as_ref().map_err(String::clone).and_then(|frame| frame.save_png(&path)) {
+        log::warn!("failed to capture frame to {:?}: {}", path, e);
+    }
+
+    if !frame_capture.is_done() {
+        return None;
+    }
+
+    // only the last frame is held against the golden image
+    let result = match frame_capture.golden.as_ref() {
+        Some(golden) => frame.and_then(|frame| compare_to_golden(&frame, golden)),
+        None => Ok(()),
+    };
+    let should_exit = frame_capture.exit_when_done;
+    config.frame_capture = None;
 
-    objects
+    if should_exit {
+        return Some(result);
+    }
+
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+    None
+}
+
+// mean difference per channel out of 255, enough to absorb differences between drivers
+const GOLDEN_TOLERANCE: f32 = 2.0;
+
+fn compare_to_golden(frame: &CapturedFrame, golden: &std::path::Path) -> Result<(), String> {
+    let difference = frame.difference(&CapturedFrame::load_png(golden)?)?;
+
+    if difference > GOLDEN_TOLERANCE {
+        return Err(format!("frame differs from golden image {:?} by {} on average", golden, difference));
+    }
+
+    Ok(())
+}
+
+const CUBE_PREFAB: &str = "cube";
+const CUBE_TEXTURES: [&str; 3] = ["textures/container.jpg", "textures/demo.jpg", "textures/wall.jpg"];
+
+// cubes scattered around the origin, each with one of the textures
+fn spawn_cubes<R: Rng>(world: &mut legion::World, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary, n: u32, rng: &mut R) {
+    for _i in 0..n {
+        let position: [f32; 3] = [rng.gen_range(-15.0, 15.0), rng.gen_range(-15.0, 15.0), rng.gen_range(-15.0, 15.0)];
+        let texture = CUBE_TEXTURES[rng.gen_range(0, CUBE_TEXTURES.len())];
+
+        let mut overrides = Overrides::new();
+        overrides.insert(String::from("transform"), json!({ "position": position }));
+        overrides.insert(String::from("texture"), json!({ "path": texture }));
+
+        if let Err(e) = prefabs.instantiate(world, registry, CUBE_PREFAB, &overrides) {
+            log::error!("{}", e);
+            return;
+        }
+    }
 }
 
-fn fetch_camera_transform(world: &legion::World) -> Transform {
+// without a camera the last one's view stays on screen
+fn fetch_camera(world: &legion::World) -> Option<(Camera, Transform, GlobalTransform)> {
     <(Read<Transform>, Read<Camera>)>::query()
-        .iter(&world)
-        .map(|(transform, _cam)| transform.clone())
+        .iter_entities(world)
+        .map(|(entity, (transform, camera))| (*camera, *transform, global_transform(world, entity, &transform)))
         .next()
-        .unwrap()
 }
 
-fn fetch_uniforms(world: &legion::World) -> Vec<ObjectUniformBufferObject> {
-    <(Read<Transform>, Read<Mesh>)>::query()
+// so faces turned away from every light aren't pitch black
+const AMBIENT_LIGHT: [f32; 3] = [0.1, 0.1, 0.1];
+
+fn fetch_lights(world: &legion::World) -> LightsUniformBufferObject {
+    let camera_position = fetch_camera(world).map_or(Vector3::new(0.0, 0.0, 0.0), |(_, _, global)| global.position());
+
+    let directional_lights = <Read<DirectionalLight>>::query()
         .iter(world)
-        .map(|(transform, _mesh)| {
-            transform.clone().to_ubo()
+        .map(|light| {
+            let shadow = if light.casts_shadows { Some(light.shadow_view_proj(camera_position)) } else { None };
+            (light.to_uniform(), shadow)
+        });
+
+    let point_lights = <(Read<Transform>, Read<PointLight>)>::query()
+        .iter_entities(world)
+        .map(|(entity, (transform, light))| (light.to_uniform(&global_transform(world, entity, &transform)), None));
+
+    let spot_lights = <(Read<Transform>, Read<SpotLight>)>::query()
+        .iter_entities(world)
+        .map(|(entity, (transform, light))| {
+            let global = global_transform(world, entity, &transform);
+            let shadow = if light.casts_shadows { Some(light.shadow_view_proj(&global)) } else { None };
+            (light.to_uniform(&global), shadow)
+        });
+
+    LightsUniformBufferObject::new(
+        AMBIENT_LIGHT,
+        directional_lights.chain(point_lights).chain(spot_lights).collect(),
+    )
+}
+
+// entities spawned without a GlobalTransform are drawn as if they had no parent
+fn global_transform(world: &legion::World, entity: legion::Entity, transform: &Transform) -> GlobalTransform {
+    world
+        .entity_data::<GlobalTransform>(entity)
+        .map(|global| *global)
+        .unwrap_or_else(|| GlobalTransform::from(transform))
+}
+
+fn fetch_uniforms(world: &legion::World) -> Vec<(legion::Entity, ObjectUniformBufferObject)> {
+    <(Read<Transform>, Read<Mesh>)>::query()
+        .iter_entities(world)
+        .map(|(entity, (transform, _mesh))| {
+            (entity, global_transform(world, entity, &transform).to_ubo())
         })
         .collect()
 }
@@ -247,7 +539,7 @@ fn fetch_drawables(world: &legion::World) -> Vec<Drawable> {
     <(Read<Transform>, Read<Mesh>)>::query()
         .iter_entities(world)
         .map(|(entity, (transform, mesh))| {
-            let mut drawable = Drawable::new(mesh.clone(), transform.clone());
+            let mut drawable = Drawable::new(entity, mesh.clone(), global_transform(world, entity, &transform));
 
             if let Some(color) = world.entity_data::<Color>(entity) {
                 drawable.with_color(color.clone());
@@ -257,7 +549,43 @@ fn fetch_drawables(world: &legion::World) -> Vec<Drawable> {
                 drawable.with_texture(texture.clone());
             }
 
+            if let Some(material) = world.entity_data::<Material>(entity) {
+                drawable.with_material(material.clone());
+            }
+
             drawable
         })
         .collect()
 }
+
+fn fetch_quads(world: &legion::World) -> Vec<Quad> {
+    <Read<UiRoot>>::query()
+        .iter(world)
+        .map(|root| root.quad().clone())
+        .collect()
+}
+
+// widgets are laid out against the window every frame so they follow it when it's resized
+fn layout_ui(world: &legion::World, viewport: &hal::pso::Viewport) {
+    for root in <Write<UiRoot>>::query().iter(world) {
+        root.layout((viewport.rect.w as u32, viewport.rect.h as u32));
+    }
+}
+
+fn fetch_texts(world: &legion::World) -> Vec<Text> {
+    <Read<Text>>::query()
+        .iter(world)
+        .map(|text| text.clone())
+        .collect()
+}
+
+fn update_fps_counter(world: &legion::World, fps_counter: legion::Entity, time: &Time) {
+    let fps = match time.delta_time {
+        0 => String::from("-- fps"),
+        delta_time => format!("{} fps", 1000 / delta_time),
+    };
+
+    for (_entity, text) in <Write<Text>>::query().iter_entities(world).filter(|(entity, _)| *entity == fps_counter) {
+        text.content = fps.clone();
+    }
+}
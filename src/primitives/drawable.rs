@@ -1,23 +1,30 @@
 use crate::components::mesh::Mesh;
-use crate::components::transform::Transform;
+use crate::components::global_transform::GlobalTransform;
 use crate::components::texture::Texture;
 use crate::components::color::Color;
+use crate::components::material::Material;
+
+use legion::Entity;
 
 #[derive(Debug)]
 pub struct Drawable {
+    pub entity: Entity,
     pub mesh: Mesh,
-    pub transform: Transform,
+    pub transform: GlobalTransform,
     pub color: Option<Color>,
-    pub texture: Option<Texture>
+    pub texture: Option<Texture>,
+    pub material: Option<Material>,
 }
 
 impl Drawable {
-    pub fn new(m: Mesh, t: Transform) -> Self {
+    pub fn new(e: Entity, m: Mesh, t: GlobalTransform) -> Self {
         Self {
+            entity: e,
             mesh: m,
             transform: t,
             color: None,
             texture: None,
+            material: None,
         }
     }
 
@@ -30,4 +37,17 @@ impl Drawable {
         self.texture = Some(t);
         self
     }
+
+    pub fn with_material(&mut self, m: Material) -> &Self {
+        self.material = Some(m);
+        self
+    }
+
+    // drawables without a material fall back to one built from their texture
+    pub fn effective_material(&self) -> Material {
+        match self.material.as_ref() {
+            Some(material) => material.clone(),
+            None => Material::from_texture(self.texture.clone()),
+        }
+    }
 }
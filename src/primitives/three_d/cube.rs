@@ -1,9 +1,10 @@
-use uuid::Uuid;
-
 use crate::primitives::vertex::Vertex;
 use crate::components::mesh::Mesh;
 use crate::components::transform::Transform;
 
+// every cube has the same geometry, so they all share a key and get drawn instanced
+pub const CUBE_MESH_KEY: &str = "cube";
+
 pub struct Cube {
     key: String,
 }
@@ -18,6 +19,13 @@ impl Cube {
         let purple = [1.0, 0.0, 1.0];
         let blue_green = [0.0, 1.0, 1.0];
 
+        let back = [0.0, 0.0, -1.0];
+        let front = [0.0, 0.0, 1.0];
+        let left = [-1.0, 0.0, 0.0];
+        let right = [1.0, 0.0, 0.0];
+        let top = [0.0, 1.0, 0.0];
+        let bottom = [0.0, -1.0, 0.0];
+
         let lower_x = -0.5;
         let lower_y = -0.5;
         let lower_z = -0.5;
@@ -27,40 +35,40 @@ impl Cube {
 
         let vertices = vec![
             // back face
-            Vertex::new([lower_x, lower_y, lower_z], red, [0.0, 0.0]),
-            Vertex::new([lower_x, upper_y, lower_z], red, [1.0, 0.0]),
-            Vertex::new([upper_x, upper_y, lower_z], red, [1.0, 1.0]),
-            Vertex::new([upper_x, lower_y, lower_z], red, [0.0, 1.0]),
+            Vertex::new([lower_x, lower_y, lower_z], red, [0.0, 0.0], back),
+            Vertex::new([lower_x, upper_y, lower_z], red, [1.0, 0.0], back),
+            Vertex::new([upper_x, upper_y, lower_z], red, [1.0, 1.0], back),
+            Vertex::new([upper_x, lower_y, lower_z], red, [0.0, 1.0], back),
 
             // front face
-            Vertex::new([lower_x, lower_y, upper_z], green, [0.0, 0.0]),
-            Vertex::new([upper_x, lower_y, upper_z], green, [1.0, 0.0]),
-            Vertex::new([upper_x, upper_y, upper_z], green, [1.0, 1.0]),
-            Vertex::new([lower_x, upper_y, upper_z], green, [0.0, 1.0]),
+            Vertex::new([lower_x, lower_y, upper_z], green, [0.0, 0.0], front),
+            Vertex::new([upper_x, lower_y, upper_z], green, [1.0, 0.0], front),
+            Vertex::new([upper_x, upper_y, upper_z], green, [1.0, 1.0], front),
+            Vertex::new([lower_x, upper_y, upper_z], green, [0.0, 1.0], front),
 
             // left face
-            Vertex::new([lower_x, lower_y, upper_z], blue, [0.0, 0.0]),
-            Vertex::new([lower_x, upper_y, upper_z], blue, [1.0, 0.0]),
-            Vertex::new([lower_x, upper_y, lower_z], blue, [1.0, 1.0]),
-            Vertex::new([lower_x, lower_y, lower_z], blue, [0.0, 1.0]),
+            Vertex::new([lower_x, lower_y, upper_z], blue, [0.0, 0.0], left),
+            Vertex::new([lower_x, upper_y, upper_z], blue, [1.0, 0.0], left),
+            Vertex::new([lower_x, upper_y, lower_z], blue, [1.0, 1.0], left),
+            Vertex::new([lower_x, lower_y, lower_z], blue, [0.0, 1.0], left),
 
             // right face
-            Vertex::new([upper_x, lower_y, upper_z], yellow, [0.0, 0.0]),
-            Vertex::new([upper_x, lower_y, lower_z], yellow, [1.0, 0.0]),
-            Vertex::new([upper_x, upper_y, lower_z], yellow, [1.0, 1.0]),
-            Vertex::new([upper_x, upper_y, upper_z], yellow, [0.0, 1.0]),
+            Vertex::new([upper_x, lower_y, upper_z], yellow, [0.0, 0.0], right),
+            Vertex::new([upper_x, lower_y, lower_z], yellow, [1.0, 0.0], right),
+            Vertex::new([upper_x, upper_y, lower_z], yellow, [1.0, 1.0], right),
+            Vertex::new([upper_x, upper_y, upper_z], yellow, [0.0, 1.0], right),
 
             // top face
-            Vertex::new([lower_x, upper_y, upper_z], purple, [0.0, 0.0]),
-            Vertex::new([upper_x, upper_y, upper_z], purple, [1.0, 0.0]),
-            Vertex::new([upper_x, upper_y, lower_z], purple, [1.0, 1.0]),
-            Vertex::new([lower_x, upper_y, lower_z], purple, [0.0, 1.0]),
+            Vertex::new([lower_x, upper_y, upper_z], purple, [0.0, 0.0], top),
+            Vertex::new([upper_x, upper_y, upper_z], purple, [1.0, 0.0], top),
+            Vertex::new([upper_x, upper_y, lower_z], purple, [1.0, 1.0], top),
+            Vertex::new([lower_x, upper_y, lower_z], purple, [0.0, 1.0], top),
 
             // bottom face
-            Vertex::new([lower_x, lower_y, upper_z], blue_green, [0.0, 0.0]),
-            Vertex::new([lower_x, lower_y, lower_z], blue_green, [1.0, 0.0]),
-            Vertex::new([upper_x, lower_y, lower_z], blue_green, [1.0, 1.0]),
-            Vertex::new([upper_x, lower_y, upper_z], blue_green, [0.0, 1.0]),
+            Vertex::new([lower_x, lower_y, upper_z], blue_green, [0.0, 0.0], bottom),
+            Vertex::new([lower_x, lower_y, lower_z], blue_green, [1.0, 0.0], bottom),
+            Vertex::new([upper_x, lower_y, lower_z], blue_green, [1.0, 1.0], bottom),
+            Vertex::new([upper_x, lower_y, upper_z], blue_green, [0.0, 1.0], bottom),
         ];
 
         let indices = vec![
@@ -73,7 +81,7 @@ impl Cube {
         ];
 
         let mesh = Mesh {
-            key: Uuid::new_v4().to_string(),
+            key: CUBE_MESH_KEY.to_string(),
             vertices,
             indices,
             rendered: true
@@ -0,0 +1,103 @@
+use cgmath::{Angle, Deg, Matrix4, Rad};
+
+// Projections straight into vulkan's depth range, right handed and looking down -z like cgmath's. Depth
+// goes from 0 at the near plane to 1 at the far plane, or the other way around with `reversed_z`. Y
+// stays pointing up, whoever wants it down on screen flips it.
+pub fn perspective(fovy: Deg<f32>, aspect: f32, near: f32, far: f32, reversed_z: bool) -> Matrix4<f32> {
+    let focal = Rad::from(fovy / 2.0).cot();
+
+    let (depth_scale, depth_offset) = if reversed_z {
+        (near / (far - near), near * far / (far - near))
+    } else {
+        (far / (near - far), near * far / (near - far))
+    };
+
+    Matrix4::new(
+        focal / aspect, 0.0, 0.0, 0.0,
+        0.0, focal, 0.0, 0.0,
+        0.0, 0.0, depth_scale, -1.0,
+        0.0, 0.0, depth_offset, 0.0,
+    )
+}
+
+// a box centered on the view direction, `half_width` and `half_height` to either side of it
+pub fn orthographic(half_width: f32, half_height: f32, near: f32, far: f32, reversed_z: bool) -> Matrix4<f32> {
+    let (depth_scale, depth_offset) = if reversed_z {
+        (1.0 / (far - near), far / (far - near))
+    } else {
+        (-1.0 / (far - near), -near / (far - near))
+    };
+
+    Matrix4::new(
+        1.0 / half_width, 0.0, 0.0, 0.0,
+        0.0, 1.0 / half_height, 0.0, 0.0,
+        0.0, 0.0, depth_scale, 0.0,
+        0.0, 0.0, depth_offset, 1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Vector4, Transform};
+
+    fn depth(proj: Matrix4<f32>, z: f32) -> f32 {
+        let clip = proj * Vector4::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} isn't {}", a, b);
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_the_depth_range() {
+        let proj = perspective(Deg(60.0), 1.5, 0.1, 100.0, false);
+        assert_close(depth(proj, -0.1), 0.0);
+        assert_close(depth(proj, -100.0), 1.0);
+
+        let reversed = perspective(Deg(60.0), 1.5, 0.1, 100.0, true);
+        assert_close(depth(reversed, -0.1), 1.0);
+        assert_close(depth(reversed, -100.0), 0.0);
+    }
+
+    #[test]
+    fn perspective_matches_cgmath_apart_from_depth() {
+        let proj = perspective(Deg(45.0), 2.0, 1.0, 50.0, false);
+        let gl = cgmath::perspective(Deg(45.0), 2.0, 1.0, 50.0);
+
+        let point = cgmath::Point3::new(3.0, -2.0, -10.0);
+        let ours = proj.transform_point(point);
+        let theirs = gl.transform_point(point);
+
+        assert_close(ours.x, theirs.x);
+        assert_close(ours.y, theirs.y);
+        assert_close(ours.z, theirs.z * 0.5 + 0.5);
+    }
+
+    #[test]
+    fn orthographic_maps_near_and_far_to_the_depth_range() {
+        let proj = orthographic(4.0, 3.0, 0.5, 20.0, false);
+        assert_close(depth(proj, -0.5), 0.0);
+        assert_close(depth(proj, -20.0), 1.0);
+
+        let reversed = orthographic(4.0, 3.0, 0.5, 20.0, true);
+        assert_close(depth(reversed, -0.5), 1.0);
+        assert_close(depth(reversed, -20.0), 0.0);
+    }
+
+    #[test]
+    fn orthographic_matches_cgmath_apart_from_depth() {
+        let proj = orthographic(4.0, 3.0, 0.5, 20.0, false);
+        let gl = cgmath::ortho(-4.0, 4.0, -3.0, 3.0, 0.5, 20.0);
+
+        let point = cgmath::Point3::new(1.0, 2.5, -7.0);
+        let ours = proj.transform_point(point);
+        let theirs = gl.transform_point(point);
+
+        assert_close(ours.x, theirs.x);
+        assert_close(ours.y, theirs.y);
+        assert_close(ours.z, theirs.z * 0.5 + 0.5);
+    }
+}
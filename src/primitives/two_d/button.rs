@@ -0,0 +1,74 @@
+use crate::components::text::TextAlign;
+use crate::events::application_events::{ApplicationEvent, WidgetEvent};
+use crate::primitives::two_d::layout::{Edges, Rect, Size, Style};
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::widget::{label, Widget, WidgetState};
+
+pub fn control_style() -> Style {
+    Style {
+        height: Size::Pixels(36.0),
+        padding: Edges::symmetric(0.0, 12.0),
+        margin: Edges { bottom: 8.0, ..Edges::default() },
+        ..Style::default()
+    }
+}
+
+// Emits `Clicked` when it's pressed and released with the cursor over it.
+pub struct Button {
+    key: String,
+    label: String,
+    style: Style,
+    state: WidgetState,
+    rect: Rect,
+    events: Vec<ApplicationEvent>,
+}
+
+impl Button {
+    pub fn new(key: &str, label: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            label: label.to_string(),
+            style: control_style(),
+            state: WidgetState::default(),
+            rect: Rect::default(),
+            events: vec![],
+        }
+    }
+
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+impl Widget for Button {
+    fn on(&mut self, event: &ApplicationEvent) -> bool {
+        if self.state.handle_pointer(event, &self.rect, false) {
+            self.events.push(ApplicationEvent::Widget { key: self.key.clone(), event: WidgetEvent::Clicked });
+        }
+
+        self.state.uses_pointer(event)
+    }
+
+    fn quad(&self) -> Quad {
+        Quad::new(&self.key, self.style)
+            .with_color(self.state.background())
+            .with_label(label(&self.label, TextAlign::Center))
+    }
+
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.rect = laid_out.rect();
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        self.events.drain(..).collect()
+    }
+}
@@ -0,0 +1,83 @@
+use crate::components::text::TextAlign;
+use crate::events::application_events::{ApplicationEvent, WidgetEvent};
+use crate::primitives::two_d::button::control_style;
+use crate::primitives::two_d::layout::{Align, Edges, Rect, Size, Style};
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::widget::{label, Widget, WidgetState, ACCENT_COLOR};
+
+const BOX_SIZE: f32 = 20.0;
+
+// A box followed by its label, clicking anywhere on either flips it and emits `Toggled`.
+pub struct Checkbox {
+    key: String,
+    label: String,
+    checked: bool,
+    state: WidgetState,
+    rect: Rect,
+    events: Vec<ApplicationEvent>,
+}
+
+impl Checkbox {
+    pub fn new(key: &str, label: &str, checked: bool) -> Self {
+        Self {
+            key: key.to_string(),
+            label: label.to_string(),
+            checked,
+            state: WidgetState::default(),
+            rect: Rect::default(),
+            events: vec![],
+        }
+    }
+
+    pub fn checked(&self) -> bool {
+        self.checked
+    }
+}
+
+impl Widget for Checkbox {
+    fn on(&mut self, event: &ApplicationEvent) -> bool {
+        if self.state.handle_pointer(event, &self.rect, false) {
+            self.checked = !self.checked;
+            self.events.push(ApplicationEvent::Widget { key: self.key.clone(), event: WidgetEvent::Toggled(self.checked) });
+        }
+
+        self.state.uses_pointer(event)
+    }
+
+    fn quad(&self) -> Quad {
+        let check_box = Quad::new(&format!("{}#box", self.key), Style {
+            width: Size::Pixels(BOX_SIZE),
+            height: Size::Pixels(BOX_SIZE),
+            align_self: Some(Align::Center),
+            ..Style::default()
+        }).with_color(if self.checked { ACCENT_COLOR } else { [0.08, 0.08, 0.1] });
+
+        let text = Quad::new(&format!("{}#label", self.key), Style {
+            padding: Edges { left: 10.0, ..Edges::default() },
+            grow: 1.0,
+            ..Style::default()
+        })
+            .with_color(self.state.background())
+            .with_label(label(&self.label, TextAlign::Left));
+
+        Quad::new(&self.key, control_style())
+            .with_color(self.state.background())
+            .with_children(vec![check_box, text])
+    }
+
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.rect = laid_out.rect();
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        self.events.drain(..).collect()
+    }
+}
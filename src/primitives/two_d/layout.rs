@@ -0,0 +1,524 @@
+// A small subset of flexbox. Every node is a box with a style, children are placed one after another
+// along their parent's direction, leftover space goes to the children that grow, and whatever doesn't
+// fit is taken from the children in proportion to their size. Everything is in pixels from the top left
+// of the window.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Size {
+    // as big as the children need, or the parent's whole cross axis when stretched
+    Auto,
+    Pixels(f32),
+    // of the parent's content box
+    Percent(f32),
+}
+
+impl Size {
+    fn resolve(&self, parent: f32) -> Option<f32> {
+        match self {
+            Size::Auto => None,
+            Size::Pixels(pixels) => Some(*pixels),
+            Size::Percent(percent) => Some(parent * percent / 100.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Edges {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Edges {
+    pub fn all(size: f32) -> Self {
+        Self { top: size, right: size, bottom: size, left: size }
+    }
+
+    pub fn symmetric(vertical: f32, horizontal: f32) -> Self {
+        Self { top: vertical, right: horizontal, bottom: vertical, left: horizontal }
+    }
+
+    // the edges before and after a box along the direction
+    fn along(&self, direction: Direction) -> (f32, f32) {
+        match direction {
+            Direction::Row => (self.left, self.right),
+            Direction::Column => (self.top, self.bottom),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+impl Direction {
+    fn cross(&self) -> Direction {
+        match self {
+            Direction::Row => Direction::Column,
+            Direction::Column => Direction::Row,
+        }
+    }
+}
+
+// where children go along the direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+}
+
+// where children go across the direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    Stretch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub width: Size,
+    pub height: Size,
+    pub min_width: Size,
+    pub min_height: Size,
+    pub max_width: Size,
+    pub max_height: Size,
+
+    pub padding: Edges,
+    pub margin: Edges,
+
+    pub direction: Direction,
+    pub justify: Justify,
+    pub align: Align,
+    // overrides the parent's `align` for this box. Boxes without a parent are placed in the window with
+    // it on both axes.
+    pub align_self: Option<Align>,
+    // share of the parent's leftover space
+    pub grow: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: Size::Auto,
+            height: Size::Auto,
+            min_width: Size::Auto,
+            min_height: Size::Auto,
+            max_width: Size::Auto,
+            max_height: Size::Auto,
+            padding: Edges::default(),
+            margin: Edges::default(),
+            direction: Direction::Row,
+            justify: Justify::Start,
+            align: Align::Stretch,
+            align_self: None,
+            grow: 0.0,
+        }
+    }
+}
+
+impl Style {
+    fn size(&self, direction: Direction) -> Size {
+        match direction {
+            Direction::Row => self.width,
+            Direction::Column => self.height,
+        }
+    }
+
+    fn clamp(&self, direction: Direction, size: f32, parent: f32) -> f32 {
+        let (min, max) = match direction {
+            Direction::Row => (self.min_width, self.max_width),
+            Direction::Column => (self.min_height, self.max_height),
+        };
+
+        let size = max.resolve(parent).map_or(size, |max| size.min(max));
+        min.resolve(parent).map_or(size, |min| size.max(min)).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self.x && point[0] < self.x + self.width && point[1] >= self.y && point[1] < self.y + self.height
+    }
+
+    fn inset(&self, edges: &Edges) -> Rect {
+        Rect {
+            x: self.x + edges.left,
+            y: self.y + edges.top,
+            width: (self.width - edges.left - edges.right).max(0.0),
+            height: (self.height - edges.top - edges.bottom).max(0.0),
+        }
+    }
+
+    fn start(&self, direction: Direction) -> f32 {
+        match direction {
+            Direction::Row => self.x,
+            Direction::Column => self.y,
+        }
+    }
+
+    fn length(&self, direction: Direction) -> f32 {
+        match direction {
+            Direction::Row => self.width,
+            Direction::Column => self.height,
+        }
+    }
+
+    fn from_axes(direction: Direction, main: (f32, f32), cross: (f32, f32)) -> Rect {
+        match direction {
+            Direction::Row => Rect { x: main.0, y: cross.0, width: main.1, height: cross.1 },
+            Direction::Column => Rect { x: cross.0, y: main.0, width: cross.1, height: main.1 },
+        }
+    }
+}
+
+// anything that is laid out as a tree of boxes
+pub trait LayoutNode: Sized {
+    fn style(&self) -> &Style;
+    fn children(&self) -> &[Self];
+    fn children_mut(&mut self) -> &mut [Self];
+    fn set_rect(&mut self, rect: Rect);
+}
+
+// lays out a whole tree in a window of the given size
+pub fn layout_root<N: LayoutNode>(root: &mut N, screen: (f32, f32)) {
+    let style = *root.style();
+    let screen_rect = Rect { x: 0.0, y: 0.0, width: screen.0, height: screen.1 };
+    let align = style.align_self.unwrap_or(Align::Start);
+
+    let (x, width) = place(align, &style, Direction::Row, &screen_rect, intrinsic_size(root, Direction::Row, screen.0));
+    let (y, height) = place(align, &style, Direction::Column, &screen_rect, intrinsic_size(root, Direction::Column, screen.1));
+
+    layout_node(root, Rect { x, y, width, height });
+}
+
+// `rect` is the node's box without its margin
+fn layout_node<N: LayoutNode>(node: &mut N, rect: Rect) {
+    node.set_rect(rect);
+
+    let style = *node.style();
+    let direction = style.direction;
+    let content = rect.inset(&style.padding);
+    let main_length = content.length(direction);
+
+    let mut main_sizes = node
+        .children()
+        .iter()
+        .map(|child| intrinsic_size(child, direction, main_length))
+        .collect::<Vec<f32>>();
+
+    let margins = node
+        .children()
+        .iter()
+        .map(|child| child.style().margin.along(direction))
+        .collect::<Vec<(f32, f32)>>();
+
+    let used = main_sizes.iter().sum::<f32>() + margins.iter().map(|(start, end)| start + end).sum::<f32>();
+    let mut free = main_length - used;
+
+    let total_grow = node.children().iter().map(|child| child.style().grow.max(0.0)).sum::<f32>();
+    if free > 0.0 && total_grow > 0.0 {
+        for (size, child) in main_sizes.iter_mut().zip(node.children().iter()) {
+            let grown = *size + free * child.style().grow.max(0.0) / total_grow;
+            *size = child.style().clamp(direction, grown, main_length);
+        }
+
+        free = main_length - main_sizes.iter().sum::<f32>() - margins.iter().map(|(start, end)| start + end).sum::<f32>();
+    } else if free < 0.0 {
+        let total_size = main_sizes.iter().sum::<f32>();
+        if total_size > 0.0 {
+            for size in main_sizes.iter_mut() {
+                *size = (*size + free * *size / total_size).max(0.0);
+            }
+        }
+
+        free = 0.0;
+    }
+
+    let child_count = main_sizes.len();
+    let free = free.max(0.0);
+    let (mut cursor, gap) = match style.justify {
+        Justify::Start => (0.0, 0.0),
+        Justify::Center => (free / 2.0, 0.0),
+        Justify::End => (free, 0.0),
+        Justify::SpaceBetween if child_count > 1 => (0.0, free / (child_count - 1) as f32),
+        Justify::SpaceBetween => (0.0, 0.0),
+    };
+    cursor += content.start(direction);
+
+    for ((child, main_size), (margin_start, margin_end)) in node.children_mut().iter_mut().zip(main_sizes).zip(margins) {
+        let child_style = *child.style();
+        let align = child_style.align_self.unwrap_or(style.align);
+        let cross = direction.cross();
+        let cross_size = intrinsic_size(child, cross, content.length(cross));
+        let (cross_start, cross_size) = place(align, &child_style, cross, &content, cross_size);
+
+        cursor += margin_start;
+        layout_node(child, Rect::from_axes(direction, (cursor, main_size), (cross_start, cross_size)));
+        cursor += main_size + margin_end + gap;
+    }
+}
+
+// Positions a box across the space it has, stretching it first if its size is up to its parent.
+// Returns where it starts and how long it ends up.
+fn place(align: Align, style: &Style, direction: Direction, space: &Rect, size: f32) -> (f32, f32) {
+    let (margin_start, margin_end) = style.margin.along(direction);
+    let available = space.length(direction) - margin_start - margin_end;
+
+    let size = match (align, style.size(direction)) {
+        (Align::Stretch, Size::Auto) => style.clamp(direction, available, space.length(direction)),
+        _ => size,
+    };
+
+    let offset = match align {
+        Align::Start | Align::Stretch => 0.0,
+        Align::Center => (available - size) / 2.0,
+        Align::End => available - size,
+    };
+
+    (space.start(direction) + margin_start + offset, size)
+}
+
+// how long a box wants to be along the direction, `parent` is the length of its parent's content box
+fn intrinsic_size<N: LayoutNode>(node: &N, direction: Direction, parent: f32) -> f32 {
+    let style = node.style();
+    let (padding_start, padding_end) = style.padding.along(direction);
+
+    let size = match style.size(direction).resolve(parent) {
+        Some(size) => size,
+        None => {
+            let content = (parent - padding_start - padding_end).max(0.0);
+            let children = node
+                .children()
+                .iter()
+                .map(|child| {
+                    let (margin_start, margin_end) = child.style().margin.along(direction);
+                    intrinsic_size(child, direction, content) + margin_start + margin_end
+                });
+
+            // children add up along the direction, across it the biggest one wins
+            let children_size = if style.direction == direction {
+                children.sum::<f32>()
+            } else {
+                children.fold(0.0, f32::max)
+            };
+
+            children_size + padding_start + padding_end
+        },
+    };
+
+    style.clamp(direction, size, parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Node {
+        style: Style,
+        children: Vec<Node>,
+        rect: Rect,
+    }
+
+    impl Node {
+        fn new(style: Style, children: Vec<Node>) -> Self {
+            Self { style, children, rect: Rect::default() }
+        }
+
+        fn leaf(width: f32, height: f32) -> Self {
+            Self::new(Style { width: Size::Pixels(width), height: Size::Pixels(height), ..Style::default() }, Vec::new())
+        }
+
+        fn with_grow(mut self, grow: f32) -> Self {
+            self.style.grow = grow;
+            self
+        }
+
+        fn child_rects(&self) -> Vec<Rect> {
+            self.children.iter().map(|child| child.rect).collect()
+        }
+    }
+
+    impl LayoutNode for Node {
+        fn style(&self) -> &Style {
+            &self.style
+        }
+
+        fn children(&self) -> &[Self] {
+            &self.children
+        }
+
+        fn children_mut(&mut self) -> &mut [Self] {
+            &mut self.children
+        }
+
+        fn set_rect(&mut self, rect: Rect) {
+            self.rect = rect;
+        }
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    fn layout(style: Style, children: Vec<Node>, width: f32, height: f32) -> Node {
+        let mut node = Node::new(style, children);
+        layout_node(&mut node, rect(0.0, 0.0, width, height));
+        node
+    }
+
+    #[test]
+    fn leftover_space_goes_to_growing_children() {
+        let node = layout(
+            Style { align: Align::Start, ..Style::default() },
+            vec![Node::leaf(50.0, 10.0).with_grow(1.0), Node::leaf(50.0, 10.0).with_grow(2.0), Node::leaf(50.0, 10.0)],
+            300.0,
+            100.0,
+        );
+
+        assert_eq!(node.child_rects(), vec![
+            rect(0.0, 0.0, 100.0, 10.0),
+            rect(100.0, 0.0, 150.0, 10.0),
+            rect(250.0, 0.0, 50.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn grown_children_stay_within_their_max_size() {
+        let mut capped = Node::leaf(50.0, 10.0).with_grow(1.0);
+        capped.style.max_width = Size::Pixels(80.0);
+
+        let node = layout(
+            Style { align: Align::Start, justify: Justify::End, ..Style::default() },
+            vec![capped, Node::leaf(50.0, 10.0).with_grow(1.0)],
+            300.0,
+            100.0,
+        );
+
+        // the space the capped child couldn't take is left over and justified
+        assert_eq!(node.child_rects(), vec![
+            rect(70.0, 0.0, 80.0, 10.0),
+            rect(150.0, 0.0, 150.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn overflowing_children_shrink_in_proportion_to_their_size() {
+        let mut first = Node::leaf(300.0, 10.0);
+        first.style.margin = Edges::symmetric(0.0, 10.0);
+
+        let node = layout(
+            Style { align: Align::Start, justify: Justify::Center, ..Style::default() },
+            vec![first, Node::leaf(100.0, 10.0)],
+            220.0,
+            100.0,
+        );
+
+        // margins aren't shrunk and nothing is left to center with
+        assert_eq!(node.child_rects(), vec![
+            rect(10.0, 0.0, 150.0, 10.0),
+            rect(170.0, 0.0, 50.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn justify_spreads_leftover_space() {
+        let children = || vec![Node::leaf(20.0, 10.0), Node::leaf(20.0, 10.0), Node::leaf(20.0, 10.0)];
+        let style = |justify| Style { justify, align: Align::Start, ..Style::default() };
+
+        let starts = |node: Node| node.child_rects().iter().map(|r| r.x).collect::<Vec<f32>>();
+
+        assert_eq!(starts(layout(style(Justify::Start), children(), 100.0, 10.0)), vec![0.0, 20.0, 40.0]);
+        assert_eq!(starts(layout(style(Justify::Center), children(), 100.0, 10.0)), vec![20.0, 40.0, 60.0]);
+        assert_eq!(starts(layout(style(Justify::End), children(), 100.0, 10.0)), vec![40.0, 60.0, 80.0]);
+        assert_eq!(starts(layout(style(Justify::SpaceBetween), children(), 100.0, 10.0)), vec![0.0, 40.0, 80.0]);
+        assert_eq!(starts(layout(style(Justify::SpaceBetween), vec![Node::leaf(20.0, 10.0)], 100.0, 10.0)), vec![0.0]);
+    }
+
+    #[test]
+    fn children_are_aligned_across_the_direction() {
+        let mut auto = Node::new(Style { width: Size::Pixels(20.0), ..Style::default() }, Vec::new());
+        auto.style.max_height = Size::Percent(50.0);
+        let mut centered = Node::leaf(20.0, 10.0);
+        centered.style.align_self = Some(Align::Center);
+        let mut end = Node::leaf(20.0, 10.0);
+        end.style.align_self = Some(Align::End);
+
+        let node = layout(
+            Style { direction: Direction::Column, padding: Edges::all(5.0), ..Style::default() },
+            vec![auto, centered, end, Node::leaf(20.0, 10.0)],
+            110.0,
+            100.0,
+        );
+
+        // only boxes with an auto size stretch, and only up to their max size
+        assert_eq!(node.child_rects(), vec![
+            rect(5.0, 5.0, 20.0, 0.0),
+            rect(45.0, 5.0, 20.0, 10.0),
+            rect(85.0, 15.0, 20.0, 10.0),
+            rect(5.0, 25.0, 20.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn auto_sizes_follow_the_children() {
+        let row = Node::new(
+            Style { padding: Edges::all(2.0), ..Style::default() },
+            vec![Node::leaf(10.0, 30.0), Node::leaf(20.0, 40.0)],
+        );
+        let percent = Node::new(
+            Style { width: Size::Percent(50.0), height: Size::Percent(10.0), ..Style::default() },
+            Vec::new(),
+        );
+
+        let node = layout(
+            Style { direction: Direction::Column, align: Align::Start, ..Style::default() },
+            vec![row, percent],
+            200.0,
+            300.0,
+        );
+
+        assert_eq!(node.child_rects(), vec![
+            rect(0.0, 0.0, 34.0, 44.0),
+            rect(0.0, 44.0, 100.0, 30.0),
+        ]);
+        assert_eq!(node.children[0].child_rects(), vec![
+            rect(2.0, 2.0, 10.0, 30.0),
+            rect(12.0, 2.0, 20.0, 40.0),
+        ]);
+    }
+
+    #[test]
+    fn roots_are_placed_in_the_window() {
+        let mut root = Node::new(
+            Style {
+                width: Size::Pixels(200.0),
+                height: Size::Pixels(100.0),
+                margin: Edges::all(10.0),
+                align_self: Some(Align::Center),
+                ..Style::default()
+            },
+            Vec::new(),
+        );
+        layout_root(&mut root, (800.0, 600.0));
+        assert_eq!(root.rect, rect(300.0, 250.0, 200.0, 100.0));
+
+        let mut root = Node::new(Style { margin: Edges::all(10.0), align_self: Some(Align::Stretch), ..Style::default() }, Vec::new());
+        layout_root(&mut root, (800.0, 600.0));
+        assert_eq!(root.rect, rect(10.0, 10.0, 780.0, 580.0));
+    }
+}
@@ -1,2 +1,8 @@
 pub mod quad;
-pub mod widget;
\ No newline at end of file
+pub mod widget;
+pub mod layout;
+pub mod panel;
+pub mod button;
+pub mod checkbox;
+pub mod slider;
+pub mod text_field;
@@ -0,0 +1,88 @@
+use crate::events::application_events::ApplicationEvent;
+use crate::events::event_handler::EventHandler;
+use crate::primitives::two_d::layout::{LayoutNode, Rect, Style};
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::widget::{Widget, WidgetState};
+
+const PANEL_COLOR: [f32; 3] = [0.1, 0.1, 0.12];
+
+// A box that lays out other widgets, each child gets a child quad in the same order.
+pub struct Panel {
+    key: String,
+    style: Style,
+    children: Vec<Box<dyn Widget>>,
+    // the panel's own hovering and pressing, its background takes the pointer like any other widget
+    state: WidgetState,
+    rect: Rect,
+}
+
+impl Panel {
+    pub fn new(key: &str, style: Style) -> Self {
+        Self {
+            key: key.to_string(),
+            style,
+            children: vec![],
+            state: WidgetState::default(),
+            rect: Rect::default(),
+        }
+    }
+
+    pub fn with_child<W: Widget + 'static>(mut self, child: W) -> Self {
+        self.children.push(Box::new(child));
+        self
+    }
+}
+
+impl Widget for Panel {
+    // every child that should see the event gets it, even if one before it already used it up
+    fn on(&mut self, event: &ApplicationEvent) -> bool {
+        self.state.handle_pointer(event, &self.rect, false);
+        let mut handled = self.state.uses_pointer(event);
+
+        for child in self.children.iter_mut() {
+            if EventHandler::widget_should_receive_event(event, child.as_ref()) {
+                handled |= child.on(event);
+            }
+        }
+
+        handled
+    }
+
+    fn quad(&self) -> Quad {
+        Quad::new(&self.key, self.style)
+            .with_color(PANEL_COLOR)
+            .with_children(self.children.iter().map(|child| child.quad()).collect())
+    }
+
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.rect = laid_out.rect();
+
+        for (child, child_quad) in self.children.iter_mut().zip(laid_out.children()) {
+            child.arrange(child_quad);
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    // a panel is as interested in an event as the most interested of itself and its children
+    fn state(&self) -> WidgetState {
+        self.children
+            .iter()
+            .map(|child| child.state())
+            .fold(self.state, |panel, child| WidgetState {
+                hovered: panel.hovered || child.hovered,
+                pressed: panel.pressed || child.pressed,
+                focused: panel.focused || child.focused,
+                cursor: panel.cursor,
+            })
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        self.children
+            .iter_mut()
+            .flat_map(|child| child.events())
+            .collect()
+    }
+}
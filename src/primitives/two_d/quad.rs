@@ -1,150 +1,138 @@
 use std::hash::Hash;
 use std::hash::Hasher;
 
+use crate::components::text::{Text, TextAlign};
 use crate::primitives::vertex::Vertex;
-use crate::components::mesh::Mesh;
-
-const ABSOLUTE_LOWER_BOUND: f32 = -1.0;
-const ABSOLUTE_UPPER_BOUND: f32 = 1.0;
-const ABSOLUTE_RANGE: f32 = ABSOLUTE_UPPER_BOUND - ABSOLUTE_LOWER_BOUND;
+use crate::primitives::two_d::layout::{self, LayoutNode, Rect, Style};
 
+// A box in the ui. Quads only describe how they want to be laid out, where they actually end up is
+// worked out by `layout` whenever the window size is known.
 #[derive(Clone, Debug)]
 pub struct Quad where {
     pub key: String,
-    vertices: Vec<Vertex>,
-    indices: Vec<u32>,
+    pub style: Style,
+    pub color: [f32; 3],
+    // drawn inside the quad once it's laid out, its position gets worked out from the quad's rect
+    pub label: Option<Text>,
     children: Vec<Quad>,
+    rect: Rect,
     pub rendered: bool
 }
 
 impl Quad {
-    // top and bottom are reversed because y is reversed.
-    pub fn new(key: String, top: f32, left: f32, bottom: f32, right: f32, parent: Option<&Quad>) -> Quad {
-        let vertices = Self::calculate_absolute_vertices(top, left, bottom, right, parent);
-
-        let indices = vec![
-            0, 1, 2, 2, 3, 0
-        ];
-
+    pub fn new(key: &str, style: Style) -> Quad {
         Quad {
-            key,
-            vertices,
-            indices,
+            key: key.to_string(),
+            style,
+            color: [1.0, 1.0, 1.0],
+            label: None,
             children: vec![],
+            rect: Rect::default(),
             rendered: true,
         }
     }
 
-    pub fn with_children(mut self, children: Vec<Quad>) -> Self {
-        let max_index = self.indices.iter().max().unwrap();
-
-        let new_children = children
-            .iter()
-            .enumerate()
-            .map(|(i, c)| {
-                let incremented_index = i as u32 + 1;
-                let new_indices = c.indices
-                    .iter()
-                    .map(|val| (*max_index * incremented_index) + *val + incremented_index)
-                    .collect();
-
-                let mut child = c.clone();
-                child.indices = new_indices;
-                child
-            })
-            .collect();
-
-        self.children = new_children;
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
 
+    pub fn with_label(mut self, label: Text) -> Self {
+        self.label = Some(label);
         self
     }
 
-    fn calculate_normalized_distance(range: f32, relative_location: f32) -> f32 {
-        let normalized_ratio = relative_location / ABSOLUTE_RANGE;
-        (normalized_ratio * range).abs()
+    pub fn with_children(mut self, children: Vec<Quad>) -> Self {
+        self.children = children;
+        self
     }
 
-    fn calculate_absolute_lower_bound(range: f32, half_point: f32, relative_location: f32) -> f32 {
-        half_point - Self::calculate_normalized_distance(range, relative_location)
+    // in pixels from the top left of the window, as of the last layout
+    pub fn rect(&self) -> Rect {
+        self.rect
     }
 
-    fn calculate_absolute_upper_bound(range: f32, half_point: f32, relative_location: f32) -> f32 {
-        half_point + Self::calculate_normalized_distance(range, relative_location)
+    pub fn layout(&mut self, screen: (u32, u32)) {
+        layout::layout_root(self, (screen.0 as f32, screen.1 as f32));
     }
 
-    fn calculate_absolute_vertices(top: f32, left: f32, bottom: f32, right: f32, parent: Option<&Quad>) -> Vec<Vertex> {
-        // calc absolute positions of own_vertices;
-        // pass those in to children vertices
-
-        // 1. take bounds of parent
-        // 2. calculate range of (1)
-        // 3. calculate normalized ratio of own coordinates (static)
-        // 4. normalizedRatio * range
-        // 5. from half point between parent bounds, move w/ quants from 4.
-
-        let (parent_lower_x, parent_upper_x, parent_lower_y, parent_upper_y) = match parent {
-            Some(parent_quad) => {
-                let lowers = &parent_quad.vertices[0];
-                let uppers = &parent_quad.vertices[2];
-                (lowers.x(), uppers.x(), lowers.y(), uppers.y())
-            },
-            None => (-1.0, 1.0, -1.0, 1.0)
-        };
-
-        println!("PLXX: {} PUX: {} PLY: {} PUY: {}", parent_lower_x, parent_upper_x, parent_lower_y, parent_upper_y);
-
-        let x_range = parent_upper_x - parent_lower_x;
-        let x_half_point = (x_range / 2.0) + parent_lower_x;
-
-        let y_range = parent_upper_y - parent_lower_y;
-        let y_half_point = (y_range / 2.0) + parent_lower_y;
-
-        let lower_x = Self::calculate_absolute_lower_bound(x_range, x_half_point, left);
-        let upper_x = Self::calculate_absolute_upper_bound(x_range, x_half_point, right);
-        let lower_y = Self::calculate_absolute_lower_bound(y_range, y_half_point, bottom);
-        let upper_y = Self::calculate_absolute_upper_bound(y_range, y_half_point, top);
-
-        println!("LX: {} UX: {} LY: {} UY: {}", lower_x, upper_x, lower_y, upper_y);
-
-        vec![
-            Vertex::new([lower_x, lower_y, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0]),
-            Vertex::new([upper_x, lower_y, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0]),
-            Vertex::new([upper_x, upper_y, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0]),
-            Vertex::new([lower_x, upper_y, 0.0], [1.0, 1.0, 1.0], [0.0, 1.0]),
-        ]
+    // Pixel space vertices and indices for this quad and its visible children. Children come after their
+    // parent so they get drawn on top of it.
+    pub fn geometry(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        self.append_geometry(&mut vertices, &mut indices);
+
+        (vertices, indices)
     }
 
-    pub fn meshes(&self) -> Vec<Mesh> {
-        let mut meshes = vec![
-            Mesh {
-                key: "i hope i dont need this right now".into(),
-                vertices: self.vertices.clone(),
-                indices: self.indices.clone(),
-                rendered: self.rendered,
-            }
-        ];
+    // Labels of this quad and its visible children, vertically centered in their quads and lined up
+    // against the padding depending on their alignment.
+    pub fn texts(&self) -> Vec<Text> {
+        if !self.rendered {
+            return vec![];
+        }
 
-        meshes.append(&mut self.children.iter().flat_map(|quad| quad.meshes()).collect());
+        let mut texts = self.label
+            .iter()
+            .map(|label| {
+                let Rect { x, y, width, height } = self.rect;
+                let padding = self.style.padding;
+
+                let label_x = match label.align {
+                    TextAlign::Left => x + padding.left,
+                    TextAlign::Center => x + width / 2.0,
+                    TextAlign::Right => x + width - padding.right,
+                };
+
+                Text {
+                    position: [label_x, y + (height - label.size) / 2.0],
+                    ..label.clone()
+                }
+            })
+            .collect::<Vec<Text>>();
 
-        meshes
+        texts.extend(self.children.iter().flat_map(|child| child.texts()));
+        texts
     }
 
-    pub fn vertices(&self) -> Vec<Vertex> {
-        let mut vertices = self.vertices.clone();
-        vertices.append(&mut self.children.iter().flat_map(|quad| quad.vertices()).collect());
+    fn append_geometry(&self, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+        if !self.rendered {
+            return;
+        }
+
+        let base_vertex = vertices.len() as u32;
+        let Rect { x, y, width, height } = self.rect;
 
-        println!("{:?}: {:?}", self.key, &vertices);
+        vertices.extend(vec![
+            Vertex::new([x, y, 0.0], self.color, [0.0, 0.0], [0.0, 0.0, 1.0]),
+            Vertex::new([x + width, y, 0.0], self.color, [1.0, 0.0], [0.0, 0.0, 1.0]),
+            Vertex::new([x + width, y + height, 0.0], self.color, [1.0, 1.0], [0.0, 0.0, 1.0]),
+            Vertex::new([x, y + height, 0.0], self.color, [0.0, 1.0], [0.0, 0.0, 1.0]),
+        ]);
+        indices.extend([0, 1, 2, 2, 3, 0].iter().map(|index| base_vertex + index));
 
-        vertices
+        for child in self.children.iter() {
+            child.append_geometry(vertices, indices);
+        }
+    }
+}
+
+impl LayoutNode for Quad {
+    fn style(&self) -> &Style {
+        &self.style
     }
 
-    pub fn indices(&self) -> Vec<u32> {
-        let mut indices = self.indices.clone();
-        indices.append(&mut self.children.iter().flat_map(|quad| quad.indices()).collect());
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
 
-        println!("{:?}: {:?}", self.key, &indices);
+    fn children_mut(&mut self) -> &mut [Self] {
+        &mut self.children
+    }
 
-        indices
+    fn set_rect(&mut self, rect: Rect) {
+        self.rect = rect;
     }
 }
 
@@ -160,4 +148,4 @@ impl Hash for Quad {
     }
 }
 
-impl Eq for Quad {}
\ No newline at end of file
+impl Eq for Quad {}
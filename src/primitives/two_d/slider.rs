@@ -0,0 +1,120 @@
+use crate::components::text::TextAlign;
+use crate::events::application_events::{ApplicationEvent, WidgetEvent};
+use crate::primitives::two_d::button::control_style;
+use crate::primitives::two_d::layout::{Align, LayoutNode, Rect, Size, Style};
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::widget::{label, Widget, WidgetState, ACCENT_COLOR};
+
+const TRACK_HEIGHT: f32 = 6.0;
+
+// A label and a track, pressing on the track or dragging along it sets the value and emits `ValueChanged`.
+pub struct Slider {
+    key: String,
+    label: String,
+    min: f32,
+    max: f32,
+    value: f32,
+    state: WidgetState,
+    rect: Rect,
+    track: Rect,
+    events: Vec<ApplicationEvent>,
+}
+
+impl Slider {
+    pub fn new(key: &str, label: &str, min: f32, max: f32, value: f32) -> Self {
+        Self {
+            key: key.to_string(),
+            label: label.to_string(),
+            min,
+            max,
+            value: value.max(min).min(max),
+            state: WidgetState::default(),
+            rect: Rect::default(),
+            track: Rect::default(),
+            events: vec![],
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.max > self.min { (self.value - self.min) / (self.max - self.min) } else { 0.0 }
+    }
+
+    fn set_from_cursor(&mut self) {
+        if self.track.width <= 0.0 {
+            return;
+        }
+
+        let fraction = ((self.state.cursor[0] - self.track.x) / self.track.width).max(0.0).min(1.0);
+        let value = self.min + fraction * (self.max - self.min);
+
+        if value != self.value {
+            self.value = value;
+            self.events.push(ApplicationEvent::Widget { key: self.key.clone(), event: WidgetEvent::ValueChanged(value) });
+        }
+    }
+}
+
+impl Widget for Slider {
+    fn on(&mut self, event: &ApplicationEvent) -> bool {
+        self.state.handle_pointer(event, &self.rect, false);
+
+        let dragging = match event {
+            ApplicationEvent::MouseButtonPressed(_) | ApplicationEvent::CursorMoved { .. } => self.state.pressed,
+            _ => false,
+        };
+
+        if dragging {
+            self.set_from_cursor();
+        }
+
+        self.state.uses_pointer(event)
+    }
+
+    fn quad(&self) -> Quad {
+        let text = Quad::new(&format!("{}#label", self.key), Style {
+            width: Size::Percent(40.0),
+            ..Style::default()
+        })
+            .with_color(self.state.background())
+            .with_label(label(&format!("{} {:.2}", self.label, self.value), TextAlign::Left));
+
+        let fill = Quad::new(&format!("{}#fill", self.key), Style {
+            width: Size::Percent(self.fraction() * 100.0),
+            ..Style::default()
+        }).with_color(ACCENT_COLOR);
+
+        let track = Quad::new(&format!("{}#track", self.key), Style {
+            height: Size::Pixels(TRACK_HEIGHT),
+            align_self: Some(Align::Center),
+            grow: 1.0,
+            ..Style::default()
+        })
+            .with_color([0.08, 0.08, 0.1])
+            .with_children(vec![fill]);
+
+        Quad::new(&self.key, control_style())
+            .with_color(self.state.background())
+            .with_children(vec![text, track])
+    }
+
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.rect = laid_out.rect();
+        self.track = laid_out.children().get(1).map_or(self.rect, |track| track.rect());
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        self.events.drain(..).collect()
+    }
+}
@@ -0,0 +1,164 @@
+use winit::event::VirtualKeyCode;
+
+use crate::components::text::TextAlign;
+use crate::events::application_events::{ApplicationEvent, WidgetEvent};
+use crate::primitives::two_d::button::control_style;
+use crate::primitives::two_d::layout::Rect;
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::widget::{label, Widget, WidgetState};
+
+// A single line of editable text. Clicking it focuses it, after that typed characters go into it and
+// emit `TextChanged`, enter emits `Submitted` and gives the focus back. Escape gives the focus back too.
+pub struct TextField {
+    key: String,
+    text: String,
+    state: WidgetState,
+    rect: Rect,
+    events: Vec<ApplicationEvent>,
+}
+
+impl TextField {
+    pub fn new(key: &str, text: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            text: text.to_string(),
+            state: WidgetState::default(),
+            rect: Rect::default(),
+            events: vec![],
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn emit(&mut self, event: WidgetEvent) {
+        self.events.push(ApplicationEvent::Widget { key: self.key.clone(), event });
+    }
+}
+
+impl Widget for TextField {
+    fn on(&mut self, event: &ApplicationEvent) -> bool {
+        self.state.handle_pointer(event, &self.rect, true);
+
+        if !self.state.focused {
+            return self.state.uses_pointer(event);
+        }
+
+        match event {
+            // backspace
+            ApplicationEvent::ReceivedCharacter('\u{8}') => {
+                if self.text.pop().is_some() {
+                    self.emit(WidgetEvent::TextChanged(self.text.clone()));
+                }
+            },
+            ApplicationEvent::ReceivedCharacter('\r') | ApplicationEvent::ReceivedCharacter('\n') => {
+                self.state.focused = false;
+                self.emit(WidgetEvent::Submitted(self.text.clone()));
+            },
+            ApplicationEvent::ReceivedCharacter(c) if !c.is_control() => {
+                self.text.push(*c);
+                self.emit(WidgetEvent::TextChanged(self.text.clone()));
+            },
+            ApplicationEvent::KeyPressed(VirtualKeyCode::Escape) => self.state.focused = false,
+            _ => (),
+        }
+
+        // While focused the keyboard belongs to the field, so typing doesn't move the camera around.
+        // Escape and releases go through so keys don't get stuck and escape can still close the menu.
+        match event {
+            ApplicationEvent::KeyPressed(VirtualKeyCode::Escape) => false,
+            ApplicationEvent::ReceivedCharacter(_) | ApplicationEvent::KeyPressed(_) => true,
+            _ => self.state.uses_pointer(event),
+        }
+    }
+
+    fn quad(&self) -> Quad {
+        let content = if self.state.focused { format!("{}|", self.text) } else { self.text.clone() };
+
+        Quad::new(&self.key, control_style())
+            .with_color(if self.state.focused { [0.05, 0.05, 0.07] } else { self.state.background() })
+            .with_label(label(&content, TextAlign::Left))
+    }
+
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.rect = laid_out.rect();
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::components::input_map::{ActionBinding, Input, InputMap};
+    use crate::components::input_state::InputState;
+    use crate::events::application_events::MouseButton;
+
+    fn focused_field() -> TextField {
+        let mut field = TextField::new("name", "");
+        field.rect = Rect { x: 0.0, y: 0.0, width: 100.0, height: 20.0 };
+
+        field.on(&ApplicationEvent::CursorMoved { x: 10.0, y: 10.0 });
+        field.on(&ApplicationEvent::MouseButtonPressed(MouseButton::Left));
+        field.on(&ApplicationEvent::MouseButtonReleased(MouseButton::Left));
+        assert!(field.state().focused);
+
+        field
+    }
+
+    #[test]
+    fn typing_goes_into_the_field_and_nowhere_else() {
+        let mut field = focused_field();
+
+        assert!(field.on(&ApplicationEvent::KeyPressed(VirtualKeyCode::A)));
+        assert!(field.on(&ApplicationEvent::ReceivedCharacter('a')));
+        assert!(!field.on(&ApplicationEvent::KeyReleased(VirtualKeyCode::A)));
+        assert_eq!(field.text(), "a");
+    }
+
+    #[test]
+    fn escape_drops_the_focus_and_goes_through() {
+        let mut field = focused_field();
+
+        assert!(!field.on(&ApplicationEvent::KeyPressed(VirtualKeyCode::Escape)));
+        assert!(!field.state().focused);
+        assert!(!field.on(&ApplicationEvent::KeyPressed(VirtualKeyCode::A)));
+    }
+
+    // what the event handler does with events no widget took
+    #[test]
+    fn escape_still_toggles_the_menu_while_the_field_has_focus() {
+        let mut field = focused_field();
+        let mut input = InputState::new();
+        let mut map = InputMap::new();
+        map.bind("toggle_menu", ActionBinding::new(Input::Key(VirtualKeyCode::Escape)));
+
+        let mut actions = vec![];
+        for event in &[ApplicationEvent::KeyPressed(VirtualKeyCode::Escape), ApplicationEvent::KeyReleased(VirtualKeyCode::Escape)] {
+            if !field.on(event) {
+                input.handle(event);
+                actions.extend(map.actions_for(event, &mut input));
+            }
+        }
+
+        match actions.as_slice() {
+            [ApplicationEvent::ActionPressed(pressed), ApplicationEvent::ActionReleased(released)] => {
+                assert_eq!(pressed, "toggle_menu");
+                assert_eq!(released, "toggle_menu");
+            },
+            other => panic!("expected the menu to be toggled, got {:?}", other),
+        }
+    }
+}
@@ -1,27 +1,155 @@
-use crate::events::application_events::{ApplicationEvent, KeyPress};
+use crate::components::text::{Text, TextAlign};
+use crate::events::application_events::{ApplicationEvent, MouseButton, WidgetEvent};
+use crate::primitives::two_d::button::Button;
+use crate::primitives::two_d::checkbox::Checkbox;
+use crate::primitives::two_d::layout::{Align, Direction, Edges, Rect, Size, Style};
+use crate::primitives::two_d::panel::Panel;
 use crate::primitives::two_d::quad::Quad;
+use crate::primitives::two_d::slider::Slider;
+use crate::primitives::two_d::text_field::TextField;
 
-pub trait Widget {
+pub const LABEL_SIZE: f32 = 16.0;
+pub const ACCENT_COLOR: [f32; 3] = [0.3, 0.55, 0.9];
+
+// Widgets rebuild their quads from their state every frame. The quads get laid out and handed back
+// through `arrange`, which is how a widget finds out where it is for hit testing.
+pub trait Widget: Send + Sync {
     //fn register_events(events: HashMap<String, ApplicationEvent>);
-    //fn commands(&mut self) -> Option<Vec<Command>>;
+    // returns true if the event was used up and shouldn't go on to the rest of the application
     fn on(&mut self, event: &ApplicationEvent) -> bool;
     fn quad(&self) -> Quad;
+    fn arrange(&mut self, laid_out: &Quad);
+    fn rect(&self) -> Rect;
+    fn state(&self) -> WidgetState;
+
+    // what the widget emitted since this was last called
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        vec![]
+    }
 }
 
-pub struct EscMenu {
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WidgetState {
+    pub hovered: bool,
+    pub pressed: bool,
+    pub focused: bool,
+    // where the cursor was last seen, in pixels from the top left of the window
+    pub cursor: [f32; 2],
+}
+
+impl WidgetState {
+    // Tracks hovering, pressing and focus against the widget's rect. Returns true when the event finished a
+    // click, which is a press and a release both over the widget.
+    pub fn handle_pointer(&mut self, event: &ApplicationEvent, rect: &Rect, focusable: bool) -> bool {
+        match event {
+            ApplicationEvent::CursorMoved { x, y } => {
+                self.cursor = [*x as f32, *y as f32];
+                self.hovered = rect.contains(self.cursor);
+                false
+            },
+            ApplicationEvent::MouseButtonPressed(MouseButton::Left) => {
+                self.pressed = self.hovered;
+                if focusable {
+                    self.focused = self.hovered;
+                }
+                false
+            },
+            ApplicationEvent::MouseButtonReleased(MouseButton::Left) => {
+                let clicked = self.pressed && self.hovered;
+                self.pressed = false;
+                clicked
+            },
+            _ => false,
+        }
+    }
+
+    // pointer events over a widget, or that finish something started on it, don't go any further
+    pub fn uses_pointer(&self, event: &ApplicationEvent) -> bool {
+        match event {
+            ApplicationEvent::CursorMoved { .. }
+            | ApplicationEvent::MouseButtonPressed(_)
+            | ApplicationEvent::MouseButtonReleased(_) => self.hovered || self.pressed,
+            _ => false,
+        }
+    }
+
+    // the same palette for every widget so they look like they belong together
+    pub fn background(&self) -> [f32; 3] {
+        if self.pressed {
+            [0.15, 0.15, 0.2]
+        } else if self.hovered || self.focused {
+            [0.35, 0.35, 0.45]
+        } else {
+            [0.25, 0.25, 0.3]
+        }
+    }
+}
+
+pub fn label(content: &str, align: TextAlign) -> Text {
+    Text::new(content, [0.0, 0.0], LABEL_SIZE).with_align(align)
+}
+
+// A widget tree living in the world. It's laid out against the window every frame and keeps the laid
+// out quads around for drawing.
+pub struct UiRoot {
+    widget: Box<dyn Widget>,
     quad: Quad,
 }
 
+impl UiRoot {
+    pub fn new<W: Widget + 'static>(widget: W) -> Self {
+        let quad = widget.quad();
+
+        Self {
+            widget: Box::new(widget),
+            quad,
+        }
+    }
+
+    pub fn layout(&mut self, screen: (u32, u32)) {
+        let mut quad = self.widget.quad();
+        quad.layout(screen);
+        self.widget.arrange(&quad);
+        self.quad = quad;
+    }
+
+    pub fn quad(&self) -> &Quad {
+        &self.quad
+    }
+
+    pub fn widget(&self) -> &dyn Widget {
+        self.widget.as_ref()
+    }
+
+    pub fn widget_mut(&mut self) -> &mut dyn Widget {
+        self.widget.as_mut()
+    }
+}
+
+pub struct EscMenu {
+    panel: Panel,
+    visible: bool,
+}
+
 impl EscMenu {
     pub fn new() -> EscMenu {
-        let mut main_box = Quad::new("escmenu".to_string(), 0.5, -0.5, 0.5, 0.5, None);
-        let child_box1 = Quad::new("escmenu_child1".to_string(), 0.7, -0.85, 0.3, 0.85, Some(&main_box));
-        let child_box2 = Quad::new("escmenu_child2".to_string(), 0.0, -0.5, -0.9, 0.3, Some(&main_box));
-
-        main_box.rendered = false;
+        let panel = Panel::new("escmenu", Style {
+            width: Size::Percent(50.0),
+            height: Size::Percent(60.0),
+            min_width: Size::Pixels(280.0),
+            padding: Edges::all(16.0),
+            direction: Direction::Column,
+            align_self: Some(Align::Center),
+            ..Style::default()
+        })
+            .with_child(Button::new("resume", "Resume"))
+            .with_child(Checkbox::new("invert_mouse_y", "Invert mouse", false))
+            .with_child(Slider::new("mouse_sensitivity", "Sensitivity", 0.01, 0.5, 0.1))
+            .with_child(TextField::new("screenshot_directory", "screenshots"));
 
         EscMenu {
-            quad: main_box.with_children(vec![child_box1, child_box2]),
+            panel,
+            visible: false,
         }
     }
 }
@@ -37,25 +165,45 @@ impl Widget for EscMenu {
     // raw -- we loop all widgets and call on with every event. leaving this here just as a reminder since this is essentially the function that is type ApplicationEventRegistration
     fn on(&mut self, event: &ApplicationEvent) -> bool {
         match event {
-            ApplicationEvent::KeyPress(KeyPress::EscKey) => {
-                // registrations.push(KeyboardInput::Esckey);
-                self.quad.rendered = !self.quad.rendered;
+            ApplicationEvent::ActionReleased(action) if action == "toggle_menu" => {
+                self.visible = !self.visible;
                 return true;
             },
-            _ => {
-                return false;
-            },
+            _ if self.visible => self.panel.on(event),
+            _ => false,
         }
     }
 
     fn quad(&self) -> Quad {
-        self.quad.clone()
+        let mut quad = self.panel.quad();
+        quad.rendered = self.visible;
+        quad
     }
 
-    // fn commands(&mut self) -> Option<Vec<Command>> {
-    //     match self.commands.len() {
-    //         0 => None,
-    //         _ => Some(self.commands.drain().collect())
-    //     }
-    // }
-}
\ No newline at end of file
+    fn arrange(&mut self, laid_out: &Quad) {
+        self.panel.arrange(laid_out);
+    }
+
+    fn rect(&self) -> Rect {
+        self.panel.rect()
+    }
+
+    // a hidden menu doesn't take any pointer events
+    fn state(&self) -> WidgetState {
+        if self.visible { self.panel.state() } else { WidgetState::default() }
+    }
+
+    fn events(&mut self) -> Vec<ApplicationEvent> {
+        let events = self.panel.events();
+
+        for event in events.iter() {
+            if let ApplicationEvent::Widget { key, event: WidgetEvent::Clicked } = event {
+                if key == "resume" {
+                    self.visible = false;
+                }
+            }
+        }
+
+        events
+    }
+}
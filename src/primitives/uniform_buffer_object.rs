@@ -1,6 +1,8 @@
 use cgmath::{Matrix4, SquareMatrix};
 
 use crate::renderer::presenter::DIMS;
+use crate::components::camera::Camera;
+use crate::components::material::{Material, ShadingModel};
 
 #[derive(Clone, Copy, Debug)]
 pub struct CameraUniformBufferObject {
@@ -24,13 +26,7 @@ impl std::default::Default for CameraUniformBufferObject {
             cgmath::Point3::new(0.0, 0.0, 0.0),
             cgmath::Vector3::new(0.0, 1.0, 0.0)
         );
-        let mut proj = cgmath::perspective(
-            cgmath::Deg(45.0),
-            DIMS.width as f32 / DIMS.height as f32,
-            0.1,
-            1000.0
-        );
-        proj.y.y *= -1.0;
+        let proj = Camera::default().projection_matrix(DIMS.width as f32 / DIMS.height as f32);
 
         CameraUniformBufferObject::new(
             view,
@@ -39,7 +35,7 @@ impl std::default::Default for CameraUniformBufferObject {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ObjectUniformBufferObject {
     pub model: Matrix4<f32>,
 }
@@ -59,3 +55,106 @@ impl std::default::Default for ObjectUniformBufferObject {
         )
     }
 }
+
+// has to match MAX_LIGHTS in standard.frag
+pub const MAX_LIGHTS: usize = 16;
+
+// has to match MAX_SHADOW_MAPS in standard.frag and shadow.vert
+pub const MAX_SHADOW_MAPS: usize = 4;
+
+// One light laid out as four vec4s for std140. The w components carry the light type, the range, and
+// the intensity. The z of `cutoffs` is the light's shadow map, or -1 if it doesn't cast shadows.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct LightUniform {
+    pub position: [f32; 4],
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub cutoffs: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct LightsUniformBufferObject {
+    pub ambient: [f32; 4],
+    // x is the number of lights, y the number of shadow maps in use
+    pub light_count: [u32; 4],
+    pub lights: [LightUniform; MAX_LIGHTS],
+    pub shadow_view_proj: [Matrix4<f32>; MAX_SHADOW_MAPS],
+}
+
+impl LightsUniformBufferObject {
+    // Lights come with the view-projection of their shadow map if they cast shadows. Lights past
+    // MAX_LIGHTS are dropped, and shadows past MAX_SHADOW_MAPS are too.
+    pub fn new(ambient: [f32; 3], lights: Vec<(LightUniform, Option<Matrix4<f32>>)>) -> Self {
+        let mut ubo = Self {
+            ambient: [ambient[0], ambient[1], ambient[2], 1.0],
+            light_count: [0; 4],
+            lights: [LightUniform::default(); MAX_LIGHTS],
+            shadow_view_proj: [Matrix4::identity(); MAX_SHADOW_MAPS],
+        };
+
+        for (slot, (mut light, shadow)) in ubo.lights.iter_mut().zip(lights.into_iter()) {
+            let shadow_map = ubo.light_count[1] as usize;
+            light.cutoffs[2] = match shadow {
+                Some(view_proj) if shadow_map < MAX_SHADOW_MAPS => {
+                    ubo.shadow_view_proj[shadow_map] = view_proj;
+                    ubo.light_count[1] += 1;
+                    shadow_map as f32
+                },
+                _ => -1.0,
+            };
+
+            *slot = light;
+            ubo.light_count[0] += 1;
+        }
+
+        ubo
+    }
+
+    pub fn shadow_map_count(&self) -> usize {
+        self.light_count[1] as usize
+    }
+}
+
+impl std::default::Default for LightsUniformBufferObject {
+    fn default() -> Self {
+        LightsUniformBufferObject::new([0.1, 0.1, 0.1], Vec::new())
+    }
+}
+
+// Per batch material factors, pushed as constants right before the batch's draw. The w of
+// `emissive` carries the shading model.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct MaterialConstants {
+    pub base_color_factor: [f32; 4],
+    pub emissive: [f32; 4],
+    pub metallic_roughness: [f32; 4],
+}
+
+impl MaterialConstants {
+    pub fn new(material: &Material) -> Self {
+        let shading_model = match material.shading_model {
+            ShadingModel::BlinnPhong => 0.0,
+            ShadingModel::Pbr => 1.0,
+        };
+        let emissive = material.emissive_factor;
+
+        Self {
+            base_color_factor: material.base_color_factor,
+            emissive: [emissive[0], emissive[1], emissive[2], shading_model],
+            metallic_roughness: [material.metallic_factor, material.roughness_factor, 0.0, 0.0],
+        }
+    }
+
+    // push constants are written as 32 bit words
+    pub fn words(&self) -> Vec<u32> {
+        self.base_color_factor
+            .iter()
+            .chain(self.emissive.iter())
+            .chain(self.metallic_roughness.iter())
+            .map(|value| value.to_bits())
+            .collect()
+    }
+}
@@ -1,13 +1,16 @@
-#[derive(Copy, Clone, Debug)]
+use cgmath::{InnerSpace, Vector3};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vertex {
     pub in_position: [f32; 3],
     pub in_color: [f32; 3],
     pub in_tex_coord: [f32; 2],
+    pub in_normal: [f32; 3],
 }
 
 impl Vertex {
-    pub fn new(in_position: [f32; 3], in_color: [f32; 3], in_tex_coord: [f32; 2]) -> Self {
-        Self {in_position, in_color, in_tex_coord}
+    pub fn new(in_position: [f32; 3], in_color: [f32; 3], in_tex_coord: [f32; 2], in_normal: [f32; 3]) -> Self {
+        Self {in_position, in_color, in_tex_coord, in_normal}
     }
 
     pub fn x(&self) -> f32 {
@@ -29,3 +32,28 @@ impl Vertex {
         self.in_position[1] = new_y;
     }
 }
+
+// Smooth normals for meshes that don't come with any. Every triangle adds its face normal to its three
+// vertices, weighted by its area since the cross product isn't normalized yet.
+pub fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+
+    for triangle in indices.chunks(3).filter(|triangle| triangle.len() == 3) {
+        let a = Vector3::from(vertices[triangle[0] as usize].in_position);
+        let b = Vector3::from(vertices[triangle[1] as usize].in_position);
+        let c = Vector3::from(vertices[triangle[2] as usize].in_position);
+
+        let face_normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += face_normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals.into_iter()) {
+        vertex.in_normal = if normal.magnitude2() > 0.0 {
+            normal.normalize().into()
+        } else {
+            [0.0, 0.0, 1.0]
+        };
+    }
+}
@@ -1,5 +1,6 @@
 use crate::renderer::core::{RendererCore, run_with_device};
-use crate::renderer::types::{Buffer, Uniform, Image, DescSetLayout, DescSet, DescSetWrite, Texture, TextureData};
+use crate::renderer::types::{Buffer, Uniform, Image, DescSetLayout, DescSet, DescSetWrite, Texture, TextureData, MipLevel};
+use crate::components::texture::{FilterMode, SamplerOptions, WrapMode};
 use std::sync::{Arc, RwLock};
 use hal::device::Device;
 use hal::command::CommandBuffer;
@@ -7,6 +8,12 @@ use hal::queue::CommandQueue;
 use hal::pool::CommandPool;
 use hal::pso::DescriptorPool;
 
+// every material set binds base color, metallic-roughness, normal, occlusion and emissive maps
+pub const MATERIAL_TEXTURE_COUNT: usize = 5;
+
+// material sets are never freed, so another pool this size is added whenever the last one fills up
+const IMAGE_DESC_POOL_SETS: usize = 128;
+
 pub const COLOR_RANGE: hal::image::SubresourceRange = hal::image::SubresourceRange {
     aspects: hal::format::Aspects::COLOR,
     levels: 0..1,
@@ -26,15 +33,16 @@ pub(crate) trait Allocator<B: hal::Backend> {
         where T: Copy,
               T: std::fmt::Debug;
     fn alloc_image(&mut self, width: u32, height: u32, format: hal::format::Format, usage: hal::image::Usage, aspects: hal::format::Aspects) -> Image<B>;
-    fn alloc_texture(&mut self, usage: hal::buffer::Usage, img_path: &String, sampler_desc: &hal::image::SamplerDesc, image_desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> Texture<B>;
+    fn alloc_texture(&mut self, usage: hal::buffer::Usage, texture: &crate::components::texture::Texture) -> Result<Texture<B>, String>;
     fn alloc_desc_set_layout(&mut self, bindings: &[hal::pso::DescriptorSetLayoutBinding]) -> DescSetLayout<B>;
-    fn alloc_desc_set(&mut self, pool_type: DescriptorPoolType, desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> DescSet<B>;
+    fn alloc_desc_set(&mut self, pool_type: DescriptorPoolType, desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> Result<DescSet<B>, String>;
+    fn read_image(&mut self, image: &B::Image, layout: hal::image::Layout, width: u32, height: u32) -> Vec<u8>;
 }
 
 pub(crate) struct GfxAllocator<B: hal::Backend> {
     core: Arc<RwLock<RendererCore<B>>>,
 
-    image_desc_pool: Option<B::DescriptorPool>,
+    image_desc_pools: Vec<B::DescriptorPool>,
     uniform_desc_pool: Option<B::DescriptorPool>,
 }
 
@@ -42,21 +50,7 @@ impl <B: hal::Backend> GfxAllocator<B> {
     pub fn new(core: &Arc<RwLock<RendererCore<B>>>) -> Self {
         run_with_device(core, |device| {
             unsafe {
-                let image_desc_pool = device
-                    .create_descriptor_pool(
-                        128,
-                        &[
-                            hal::pso::DescriptorRangeDesc {
-                                ty: hal::pso::DescriptorType::Image {
-                                    ty: hal::pso::ImageDescriptorType::Sampled {
-                                        with_sampler: true,
-                                    }
-                                },
-                                count: 128
-                            }
-                        ],
-                        hal::pso::DescriptorPoolCreateFlags::empty()
-                    )
+                let image_desc_pool = Self::create_image_desc_pool(device)
                     .expect("Can't create descriptor pool");
 
                 // TODO -> render graph, not static
@@ -71,7 +65,7 @@ impl <B: hal::Backend> GfxAllocator<B> {
                                         dynamic_offset: false,
                                     }
                                 },
-                                count: 1
+                                count: 2
                             },
                             hal::pso::DescriptorRangeDesc {
                                 ty: hal::pso::DescriptorType::Buffer {
@@ -89,18 +83,37 @@ impl <B: hal::Backend> GfxAllocator<B> {
 
                 Self {
                     core: Arc::clone(core),
-                    image_desc_pool: Some(image_desc_pool),
+                    image_desc_pools: vec![image_desc_pool],
                     uniform_desc_pool: Some(uniform_desc_pool),
                 }
             }
         })
     }
 
+    unsafe fn create_image_desc_pool(device: &B::Device) -> Result<B::DescriptorPool, String> {
+        device
+            .create_descriptor_pool(
+                IMAGE_DESC_POOL_SETS,
+                &[
+                    hal::pso::DescriptorRangeDesc {
+                        ty: hal::pso::DescriptorType::Image {
+                            ty: hal::pso::ImageDescriptorType::Sampled {
+                                with_sampler: true,
+                            }
+                        },
+                        count: IMAGE_DESC_POOL_SETS * MATERIAL_TEXTURE_COUNT
+                    }
+                ],
+                hal::pso::DescriptorPoolCreateFlags::empty()
+            )
+            .map_err(|e| format!("can't create descriptor pool: {:?}", e))
+    }
+
     fn transfer_image(&self,
                       image: &Image<B>,
                       mut image_upload_buffer: Buffer<B>,
-                      image_extent: hal::image::Extent,
-                      buffer_extent: hal::image::Extent)
+                      levels: &[MipLevel],
+                      pixel_size: usize)
     {
         let queue_family = self.core.read().unwrap().device.queue_group.family;
         run_with_device(&self.core, |device| {
@@ -119,8 +132,8 @@ impl <B: hal::Backend> GfxAllocator<B> {
                 let mut cmds = self.transfer_image_cmd(&mut staging_pool,
                                                    &image,
                                                    &image_upload_buffer,
-                                                   image_extent,
-                                                   buffer_extent);
+                                                   levels,
+                                                   pixel_size);
 
                 self
                     .core
@@ -144,10 +157,36 @@ impl <B: hal::Backend> GfxAllocator<B> {
                           command_pool: &mut B::CommandPool,
                           image: &Image<B>,
                           image_upload_buffer: &Buffer<B>,
-                          image_extent: hal::image::Extent,
-                          buffer_extent: hal::image::Extent)
+                          levels: &[MipLevel],
+                          pixel_size: usize)
         -> B::CommandBuffer
     {
+        let range = hal::image::SubresourceRange {
+            levels: 0..levels.len() as hal::image::Level,
+            ..COLOR_RANGE.clone()
+        };
+
+        let regions = levels
+            .iter()
+            .enumerate()
+            .map(|(level_index, level)| hal::command::BufferImageCopy {
+                buffer_offset: level.offset,
+                buffer_width: level.row_pitch / pixel_size as u32,
+                buffer_height: level.height,
+                image_layers: hal::image::SubresourceLayers {
+                    aspects: hal::format::Aspects::COLOR,
+                    level: level_index as hal::image::Level,
+                    layers: 0..1,
+                },
+                image_offset: hal::image::Offset { x: 0, y: 0, z: 0 },
+                image_extent: hal::image::Extent {
+                    width: level.width,
+                    height: level.height,
+                    depth: 1,
+                },
+            })
+            .collect::<Vec<hal::command::BufferImageCopy>>();
+
         unsafe {
             let mut cmd_buffer = command_pool.allocate_one(hal::command::Level::Primary);
             cmd_buffer.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
@@ -156,7 +195,7 @@ impl <B: hal::Backend> GfxAllocator<B> {
                 states: (hal::image::Access::empty(), hal::image::Layout::Undefined)..(hal::image::Access::TRANSFER_WRITE, hal::image::Layout::TransferDstOptimal),
                 target: image.image.as_ref().unwrap(),
                 families: None,
-                range: COLOR_RANGE.clone(),
+                range: range.clone(),
             };
 
             cmd_buffer.pipeline_barrier(
@@ -169,6 +208,58 @@ impl <B: hal::Backend> GfxAllocator<B> {
                 image_upload_buffer.buffer.as_ref().unwrap(),
                 image.image.as_ref().unwrap(),
                 hal::image::Layout::TransferDstOptimal,
+                &regions,
+            );
+
+            let image_barrier = hal::memory::Barrier::Image {
+                states: (hal::image::Access::TRANSFER_WRITE, hal::image::Layout::TransferDstOptimal)..(hal::image::Access::SHADER_READ, hal::image::Layout::ShaderReadOnlyOptimal),
+                target: image.image.as_ref().unwrap(),
+                families: None,
+                range,
+            };
+
+            cmd_buffer.pipeline_barrier(
+                hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::FRAGMENT_SHADER,
+                hal::memory::Dependencies::empty(),
+                &[image_barrier],
+            );
+
+            cmd_buffer.finish();
+
+            cmd_buffer
+        }
+    }
+
+    fn readback_image_cmd(&self,
+                          command_pool: &mut B::CommandPool,
+                          image: &B::Image,
+                          layout: hal::image::Layout,
+                          image_download_buffer: &Buffer<B>,
+                          image_extent: hal::image::Extent,
+                          buffer_extent: hal::image::Extent)
+        -> B::CommandBuffer
+    {
+        unsafe {
+            let mut cmd_buffer = command_pool.allocate_one(hal::command::Level::Primary);
+            cmd_buffer.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+
+            let image_barrier = hal::memory::Barrier::Image {
+                states: (hal::image::Access::empty(), layout)..(hal::image::Access::TRANSFER_READ, hal::image::Layout::TransferSrcOptimal),
+                target: image,
+                families: None,
+                range: COLOR_RANGE.clone(),
+            };
+
+            cmd_buffer.pipeline_barrier(
+                hal::pso::PipelineStage::BOTTOM_OF_PIPE..hal::pso::PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                &[image_barrier],
+            );
+
+            cmd_buffer.copy_image_to_buffer(
+                image,
+                hal::image::Layout::TransferSrcOptimal,
+                image_download_buffer.buffer.as_ref().unwrap(),
                 &[hal::command::BufferImageCopy {
                     buffer_offset: 0,
                     buffer_width: buffer_extent.width,
@@ -187,15 +278,16 @@ impl <B: hal::Backend> GfxAllocator<B> {
                 }],
             );
 
+            // put the image back the way we found it so the next frame can render into it
             let image_barrier = hal::memory::Barrier::Image {
-                states: (hal::image::Access::TRANSFER_WRITE, hal::image::Layout::TransferDstOptimal)..(hal::image::Access::SHADER_READ, hal::image::Layout::ShaderReadOnlyOptimal),
-                target: image.image.as_ref().unwrap(),
+                states: (hal::image::Access::TRANSFER_READ, hal::image::Layout::TransferSrcOptimal)..(hal::image::Access::empty(), layout),
+                target: image,
                 families: None,
                 range: COLOR_RANGE.clone(),
             };
 
             cmd_buffer.pipeline_barrier(
-                hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::FRAGMENT_SHADER,
+                hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::TOP_OF_PIPE,
                 hal::memory::Dependencies::empty(),
                 &[image_barrier],
             );
@@ -224,6 +316,97 @@ impl <B: hal::Backend> GfxAllocator<B> {
             .into()
     }
 
+    fn create_image(&mut self,
+                    width: u32,
+                    height: u32,
+                    mip_levels: hal::image::Level,
+                    format: hal::format::Format,
+                    usage: hal::image::Usage,
+                    aspects: hal::format::Aspects) -> Image<B> {
+        let mut image = run_with_device(&self.core, |device| {
+            unsafe {
+                device.create_image(
+                         hal::image::Kind::D2(width, height, 1, 1),
+                         mip_levels,
+                         format,
+                         hal::image::Tiling::Optimal,
+                         usage,
+                         hal::image::ViewCapabilities::empty()
+                    )
+                    .expect("failed to create image")
+            }
+
+        });
+
+        let image_req = run_with_device(&self.core, |device| {
+            unsafe { device.get_image_requirements(&image) }
+        });
+
+        let device_type = self.find_memory_type(image_req, hal::memory::Properties::DEVICE_LOCAL);
+
+        let image_memory = run_with_device(&self.core, |device| {
+            unsafe {
+                let memory = device
+                    .allocate_memory(device_type, image_req.size)
+                    .expect("failed to allocate image memory");
+
+                device
+                    .bind_image_memory(&memory, 0, &mut image)
+                    .expect("failed to bind memory to image");
+
+                memory
+            }
+        });
+
+        let image_view = run_with_device(&self.core, |device| {
+            unsafe {
+                device
+                    .create_image_view(
+                        &mut image,
+                        hal::image::ViewKind::D2,
+                        format,
+                        hal::format::Swizzle::NO,
+                        hal::image::SubresourceRange {
+                            aspects,
+                            levels: 0..mip_levels,
+                            layers: 0..1,
+                        }
+                    )
+                    .expect("failed to create image view")
+            }
+        });
+
+        Image::new(
+            Some(image),
+            Some(image_view),
+            Some(image_memory),
+        )
+    }
+
+    fn sampler_desc(&self, options: &SamplerOptions, mip_levels: hal::image::Level) -> hal::image::SamplerDesc {
+        let filter = match options.filter {
+            FilterMode::Nearest => hal::image::Filter::Nearest,
+            FilterMode::Linear => hal::image::Filter::Linear,
+        };
+
+        let wrap = match options.wrap {
+            WrapMode::Clamp => hal::image::WrapMode::Clamp,
+            WrapMode::Repeat => hal::image::WrapMode::Tile,
+            WrapMode::Mirror => hal::image::WrapMode::Mirror,
+        };
+
+        let mut sampler_desc = hal::image::SamplerDesc::new(filter, wrap);
+        sampler_desc.mip_filter = filter;
+        sampler_desc.lod_range = hal::image::Lod(0.0)..hal::image::Lod(mip_levels as f32);
+
+        let anisotropy_enabled = self.core.read().unwrap().device.enabled_features.contains(hal::Features::SAMPLER_ANISOTROPY);
+        if anisotropy_enabled {
+            sampler_desc.anisotropy_clamp = options.anisotropy;
+        }
+
+        sampler_desc
+    }
+
     fn calculate_stride<T>(alignment: u64) -> u64 {
         let data_stride = std::mem::size_of::<T>() as u64;
         if data_stride < alignment {
@@ -347,91 +530,35 @@ impl <B: hal::Backend> Allocator<B> for GfxAllocator<B> {
         format: hal::format::Format,
         usage: hal::image::Usage,
         aspects: hal::format::Aspects) -> Image<B> {
-        let mut image = run_with_device(&self.core, |device| {
-            unsafe {
-                device.create_image(
-                         hal::image::Kind::D2(width, height, 1, 1),
-                         1,
-                         format,
-                         hal::image::Tiling::Optimal,
-                         usage,
-                         hal::image::ViewCapabilities::empty()
-                    )
-                    .expect("failed to create image")
-            }
-
-        });
-
-        let image_req = run_with_device(&self.core, |device| {
-            unsafe { device.get_image_requirements(&image) }
-        });
-
-        let device_type = self.find_memory_type(image_req, hal::memory::Properties::DEVICE_LOCAL);
-
-        let image_memory = run_with_device(&self.core, |device| {
-            unsafe {
-                let memory = device
-                    .allocate_memory(device_type, image_req.size)
-                    .expect("failed to allocate image memory");
-
-                device
-                    .bind_image_memory(&memory, 0, &mut image)
-                    .expect("failed to bind memory to image");
-
-                memory
-            }
-        });
-
-        let image_view = run_with_device(&self.core, |device| {
-            unsafe {
-                device
-                    .create_image_view(
-                        &mut image,
-                        hal::image::ViewKind::D2,
-                        format,
-                        hal::format::Swizzle::NO,
-                        hal::image::SubresourceRange {
-                            aspects,
-                            levels: 0..1,
-                            layers: 0..1,
-                        }
-                    )
-                    .expect("failed to create image view")
-            }
-        });
-
-        Image::new(
-            Some(image),
-            Some(image_view),
-            Some(image_memory),
-        )
+        self.create_image(width, height, 1, format, usage, aspects)
     }
 
     fn alloc_texture(&mut self,
                      _usage: hal::buffer::Usage,
-                     img_path: &String,
-                     sampler_desc: &hal::image::SamplerDesc,
-                     image_desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> Texture<B> {
-        let image_desc_set = self.alloc_desc_set(DescriptorPoolType::Texture, image_desc_set_layout);
-
+                     texture: &crate::components::texture::Texture) -> Result<Texture<B>, String> {
         let row_alignment_mask = self.core.read().unwrap().backend.adapter.limits.optimal_buffer_copy_pitch_alignment as u32 - 1;
-        let texture_data = TextureData::load(img_path, row_alignment_mask);
+        let texture_data = match texture.pixels.as_ref() {
+            Some(pixels) => TextureData::from_rgba(pixels.width, pixels.height, &pixels.data, &texture.sampler, row_alignment_mask),
+            None => TextureData::load(&texture.path, &texture.sampler, row_alignment_mask)?,
+        };
 
-        let pixel_size = 1_usize;
-        let row_pitch = (texture_data.width * pixel_size as u32 + row_alignment_mask) & !row_alignment_mask;
-        let upload_size = (texture_data.height * row_pitch) as u64;
+        let upload_size = texture_data.data.len() as u64;
 
+        // the staging data is raw bytes, rows are already padded to the copy alignment
         let image_upload_buffer = self.alloc_buffer(
             &texture_data.data,
-            pixel_size as u64,
+            1,
             upload_size,
             hal::buffer::Usage::TRANSFER_SRC,
             hal::memory::Properties::CPU_VISIBLE | hal::memory::Properties::COHERENT
         );
 
-        let image = self.alloc_image(
+        let mip_levels = texture_data.levels.len() as hal::image::Level;
+
+        let image = self.create_image(
             texture_data.width,
             texture_data.height,
+            mip_levels,
             texture_data.format,
             hal::image::Usage::TRANSFER_DST | hal::image::Usage::SAMPLED,
             hal::format::Aspects::COLOR,
@@ -439,45 +566,22 @@ impl <B: hal::Backend> Allocator<B> for GfxAllocator<B> {
 
         self.transfer_image(&image,
                             image_upload_buffer,
-                            hal::image::Extent {
-                                width: texture_data.width,
-                                height: texture_data.height,
-                                depth: 1,
-                            },
-                            hal::image::Extent {
-                                width: row_pitch / (pixel_size as u32),
-                                height: texture_data.height,
-                                depth: 1,
-                            });
-
-        let sampler = run_with_device(&self.core, |device| {
-            let sampler = unsafe {
-                device.create_sampler(sampler_desc).expect("can't create sampler")
-            };
+                            &texture_data.levels,
+                            texture_data.pixel_size);
 
-            image_desc_set.write(
-                device,
-                vec![
-                    DescSetWrite {
-                        binding: 0,
-                        array_offset: 0,
-                        descriptors: hal::pso::Descriptor::CombinedImageSampler(
-                            image.image_view.as_ref().unwrap(),
-                            hal::image::Layout::ShaderReadOnlyOptimal,
-                            &sampler
-                        )
-                    }
-                ]
-            );
+        let sampler_desc = self.sampler_desc(&texture.sampler, mip_levels);
 
-            sampler
+        // textures get bound through the descriptor sets of the materials that use them
+        let sampler = run_with_device(&self.core, |device| {
+            unsafe {
+                device.create_sampler(&sampler_desc).expect("can't create sampler")
+            }
         });
 
-        Texture::new(
-            image_desc_set,
+        Ok(Texture::new(
             Some(sampler),
             image,
-        )
+        ))
     }
 
     fn alloc_desc_set_layout(&mut self, bindings: &[hal::pso::DescriptorSetLayoutBinding]) -> DescSetLayout<B> {
@@ -494,38 +598,120 @@ impl <B: hal::Backend> Allocator<B> for GfxAllocator<B> {
         }
     }
 
-    fn alloc_desc_set(&mut self, pool_type: DescriptorPoolType, desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> DescSet<B> {
-        let descriptor_pool = match pool_type {
-            DescriptorPoolType::Uniform => self.uniform_desc_pool.as_mut().unwrap(),
-            DescriptorPoolType::Texture => self.image_desc_pool.as_mut().unwrap(),
+    fn alloc_desc_set(&mut self, pool_type: DescriptorPoolType, desc_set_layout: &Arc<RwLock<DescSetLayout<B>>>) -> Result<DescSet<B>, String> {
+        let layout_lock = desc_set_layout.read().unwrap();
+        let layout = layout_lock.layout.as_ref().unwrap();
+
+        let allocated = unsafe {
+            match pool_type {
+                DescriptorPoolType::Uniform => self.uniform_desc_pool.as_mut().unwrap().allocate_set(layout),
+                DescriptorPoolType::Texture => self.image_desc_pools.last_mut().unwrap().allocate_set(layout),
+            }
         };
 
-        let descriptor_set = unsafe {
-            descriptor_pool
-                .allocate_set(desc_set_layout
-                    .read()
-                    .unwrap()
-                    .layout
-                    .as_ref()
-                    .unwrap()
-                )
-                .unwrap()
+        let descriptor_set = match (pool_type, allocated) {
+            (_, Ok(descriptor_set)) => descriptor_set,
+            (DescriptorPoolType::Texture, Err(hal::pso::AllocationError::OutOfPoolMemory))
+            | (DescriptorPoolType::Texture, Err(hal::pso::AllocationError::FragmentedPool)) => {
+                let image_desc_pool = run_with_device(&self.core, |device| unsafe { Self::create_image_desc_pool(device) })?;
+                self.image_desc_pools.push(image_desc_pool);
+
+                unsafe { self.image_desc_pools.last_mut().unwrap().allocate_set(layout) }
+                    .map_err(|e| format!("can't allocate descriptor set: {:?}", e))?
+            },
+            (_, Err(e)) => return Err(format!("can't allocate descriptor set: {:?}", e)),
         };
 
-        DescSet {
+        Ok(DescSet {
             descriptor_set,
             desc_set_layout: Arc::clone(desc_set_layout),
-        }
+        })
+    }
+
+    // Copies a 4 byte per pixel color image back to the cpu. The returned pixels are tightly packed rows
+    // in the image's own channel order; the image is left in `layout` when this returns.
+    fn read_image(&mut self, image: &B::Image, layout: hal::image::Layout, width: u32, height: u32) -> Vec<u8> {
+        let row_alignment_mask = self.core.read().unwrap().backend.adapter.limits.optimal_buffer_copy_pitch_alignment as u32 - 1;
+
+        let pixel_size = 4_u32;
+        let row_pitch = (width * pixel_size + row_alignment_mask) & !row_alignment_mask;
+        let download_size = (height * row_pitch) as u64;
+
+        let mut image_download_buffer = self.alloc_buffer(
+            &vec![0u8; download_size as usize],
+            1,
+            download_size,
+            hal::buffer::Usage::TRANSFER_DST,
+            hal::memory::Properties::CPU_VISIBLE | hal::memory::Properties::COHERENT
+        );
+
+        let queue_family = self.core.read().unwrap().device.queue_group.family;
+        run_with_device(&self.core, |device| {
+            unsafe {
+                let mut image_read_fence = device
+                    .create_fence(false)
+                    .expect("can't create fence");
+
+                let mut staging_pool = device
+                    .create_command_pool(
+                        queue_family,
+                        hal::pool::CommandPoolCreateFlags::empty(),
+                    )
+                    .expect("Can't create staging command pool");
+
+                let cmds = self.readback_image_cmd(&mut staging_pool,
+                                                   image,
+                                                   layout,
+                                                   &image_download_buffer,
+                                                   hal::image::Extent {
+                                                       width,
+                                                       height,
+                                                       depth: 1,
+                                                   },
+                                                   hal::image::Extent {
+                                                       width: row_pitch / pixel_size,
+                                                       height,
+                                                       depth: 1,
+                                                   });
+
+                self
+                    .core
+                    .write()
+                    .unwrap()
+                    .device
+                    .queue_group
+                    .queues[0]
+                    .submit_without_semaphores(Some(&cmds), Some(&mut image_read_fence));
+
+                device.wait_for_fence(&image_read_fence, !0).unwrap();
+
+                device.destroy_command_pool(staging_pool);
+                device.destroy_fence(image_read_fence);
+            }
+        });
+
+        let padded_pixels = image_download_buffer.read_data(&self.core, 0, download_size);
+
+        run_with_device(&self.core, |device| {
+            image_download_buffer.drop(device);
+        });
+
+        padded_pixels
+            .chunks(row_pitch as usize)
+            .flat_map(|row| row[..(width * pixel_size) as usize].iter().cloned())
+            .collect()
     }
 }
 
 impl <B: hal::Backend> Drop for GfxAllocator<B> {
     fn drop(&mut self) {
-        let image_desc_pool = self.image_desc_pool.take().unwrap();
+        let image_desc_pools = self.image_desc_pools.drain(..);
         let uniform_desc_pool = self.uniform_desc_pool.take().unwrap();
         run_with_device(&self.core, |device| {
             unsafe {
-                device.destroy_descriptor_pool(image_desc_pool);
+                for image_desc_pool in image_desc_pools {
+                    device.destroy_descriptor_pool(image_desc_pool);
+                }
                 device.destroy_descriptor_pool(uniform_desc_pool);
             }
         })
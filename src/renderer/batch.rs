@@ -0,0 +1,605 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use legion::Entity;
+
+use crate::components::material::Material;
+use crate::primitives::drawable::Drawable;
+use crate::primitives::uniform_buffer_object::ObjectUniformBufferObject;
+use crate::primitives::vertex::Vertex;
+
+const INITIAL_BATCH_CAPACITY: u32 = 16;
+
+// Matches the layout the gpu expects for indexed indirect draws.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub(crate) struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+// A run of instances that share the same geometry and material, drawn with a single indirect draw.
+// Each batch owns `instance_capacity` slots of the instance buffer starting at `first_instance` and
+// keeps its live instances packed at the front of them.
+pub(crate) struct DrawBatch {
+    pub material: Material,
+    pub mesh_key: String,
+    pub indices: Range<u32>,
+    pub first_instance: u32,
+    pub instance_capacity: u32,
+    pub instances: Vec<Entity>,
+}
+
+impl DrawBatch {
+    // first_instance is always 0 because the instance buffer gets bound at the batch's offset instead,
+    // a non zero first instance in indirect draws needs a device feature we can't count on
+    pub fn indirect_command(&self) -> DrawIndexedIndirectCommand {
+        DrawIndexedIndirectCommand {
+            index_count: self.indices.end - self.indices.start,
+            instance_count: self.instances.len() as u32,
+            first_index: self.indices.start,
+            vertex_offset: 0,
+            first_instance: 0,
+        }
+    }
+}
+
+// What the last sync touched, so the drawer only uploads and re-records what it has to.
+#[derive(Default)]
+pub(crate) struct BatchChanges {
+    pub new_vertices: Option<Range<usize>>,
+    pub new_indices: Option<Range<usize>>,
+    pub changed_batches: BTreeSet<usize>,
+
+    // batches were added or their instance regions moved, so the command buffers are out of date
+    pub needs_rerecord: bool,
+}
+
+impl BatchChanges {
+    pub fn is_empty(&self) -> bool {
+        self.new_vertices.is_none()
+            && self.new_indices.is_none()
+            && self.changed_batches.is_empty()
+            && !self.needs_rerecord
+    }
+}
+
+// Where a mesh's vertices and indices live in the shared buffers.
+#[derive(Clone)]
+struct Geometry {
+    vertices: Range<u32>,
+    indices: Range<u32>,
+}
+
+// The cpu side layout of everything the drawer draws. Geometry is only stored once per mesh key, and
+// drawables are tracked by entity so syncing a new set of drawables only touches the batches of the
+// entities that were added, removed, or moved to a different mesh or material.
+//
+// Batches that become empty are dropped at the end of the sync along with any geometry only they used,
+// which packs everything that's left and has the drawer upload all of it again.
+pub(crate) struct DrawableBatches {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub batches: Vec<DrawBatch>,
+    // the instance buffer as the gpu should see it, every batch's region starting at its first_instance
+    pub instance_data: Vec<ObjectUniformBufferObject>,
+
+    geometry: HashMap<String, Geometry>,
+    batch_lookup: HashMap<(Material, String), usize>,
+    locations: HashMap<Entity, (usize, usize)>,
+    // instance slots that changed since the drawer last uploaded them
+    dirty_instances: BTreeSet<usize>,
+}
+
+impl DrawableBatches {
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            batches: Vec::new(),
+            instance_data: Vec::new(),
+            geometry: HashMap::new(),
+            batch_lookup: HashMap::new(),
+            locations: HashMap::new(),
+            dirty_instances: BTreeSet::new(),
+        }
+    }
+
+    pub fn instance_capacity(&self) -> u32 {
+        self.batches
+            .last()
+            .map_or(0, |batch| batch.first_instance + batch.instance_capacity)
+    }
+
+    // the slot in the instance buffer this entity's model matrix goes in
+    pub fn instance_slot(&self, entity: &Entity) -> Option<u32> {
+        self.locations
+            .get(entity)
+            .map(|&(batch_index, slot)| self.batches[batch_index].first_instance + slot as u32)
+    }
+
+    // only marks the slot dirty if the matrix actually changed
+    pub fn write_instance(&mut self, entity: &Entity, ubo: ObjectUniformBufferObject) {
+        if let Some(slot) = self.instance_slot(entity) {
+            let slot = slot as usize;
+
+            if self.instance_data[slot] != ubo {
+                self.instance_data[slot] = ubo;
+                self.dirty_instances.insert(slot);
+            }
+        }
+    }
+
+    // the dirty slots merged into runs, clearing them
+    pub fn take_dirty_instances(&mut self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for slot in std::mem::replace(&mut self.dirty_instances, BTreeSet::new()) {
+            match ranges.last_mut() {
+                Some(range) if range.end == slot => range.end += 1,
+                _ => ranges.push(slot..(slot + 1)),
+            }
+        }
+
+        ranges
+    }
+
+    pub fn indirect_commands(&self) -> Vec<DrawIndexedIndirectCommand> {
+        self.batches
+            .iter()
+            .map(|batch| batch.indirect_command())
+            .collect()
+    }
+
+    pub fn sync(&mut self, drawables: &[Drawable]) -> BatchChanges {
+        let mut changes = BatchChanges::default();
+        let vertex_count = self.vertices.len();
+        let index_count = self.indices.len();
+
+        let visible = drawables
+            .iter()
+            .filter(|drawable| drawable.mesh.rendered)
+            .map(|drawable| (drawable.entity, drawable))
+            .collect::<HashMap<Entity, &Drawable>>();
+
+        let stale_entities = self.locations
+            .iter()
+            .filter(|(entity, location)| {
+                let batch = &self.batches[location.0];
+
+                match visible.get(*entity) {
+                    Some(drawable) => drawable.effective_material() != batch.material || drawable.mesh.key != batch.mesh_key,
+                    None => true,
+                }
+            })
+            .map(|(entity, _)| *entity)
+            .collect::<Vec<Entity>>();
+
+        for entity in stale_entities {
+            self.remove(entity, &mut changes);
+        }
+
+        for drawable in drawables.iter().filter(|drawable| drawable.mesh.rendered) {
+            if !self.locations.contains_key(&drawable.entity) {
+                self.add(drawable, &mut changes);
+            }
+        }
+
+        if self.vertices.len() > vertex_count {
+            changes.new_vertices = Some(vertex_count..self.vertices.len());
+        }
+
+        if self.indices.len() > index_count {
+            changes.new_indices = Some(index_count..self.indices.len());
+        }
+
+        if self.batches.iter().any(|batch| batch.instances.is_empty()) {
+            self.compact(&mut changes);
+        }
+
+        changes
+    }
+
+    fn remove(&mut self, entity: Entity, changes: &mut BatchChanges) {
+        let (batch_index, slot) = self.locations.remove(&entity).unwrap();
+        let batch = &mut self.batches[batch_index];
+
+        // the last instance fills the hole so the batch stays packed
+        batch.instances.swap_remove(slot);
+        if let Some(moved_entity) = batch.instances.get(slot) {
+            self.locations.insert(*moved_entity, (batch_index, slot));
+
+            let first_instance = batch.first_instance as usize;
+            self.instance_data[first_instance + slot] = self.instance_data[first_instance + batch.instances.len()];
+            self.dirty_instances.insert(first_instance + slot);
+        }
+
+        changes.changed_batches.insert(batch_index);
+    }
+
+    fn add(&mut self, drawable: &Drawable, changes: &mut BatchChanges) {
+        let batch_key = (drawable.effective_material(), drawable.mesh.key.clone());
+
+        let batch_index = match self.batch_lookup.get(&batch_key) {
+            Some(batch_index) => *batch_index,
+            None => {
+                let batch_index = self.push_batch(drawable);
+                self.batch_lookup.insert(batch_key, batch_index);
+                changes.needs_rerecord = true;
+                batch_index
+            }
+        };
+
+        if self.batches[batch_index].instances.len() as u32 == self.batches[batch_index].instance_capacity {
+            self.grow_batch(batch_index);
+            changes.needs_rerecord = true;
+        }
+
+        let batch = &mut self.batches[batch_index];
+        batch.instances.push(drawable.entity);
+        self.locations.insert(drawable.entity, (batch_index, batch.instances.len() - 1));
+
+        changes.changed_batches.insert(batch_index);
+    }
+
+    fn push_batch(&mut self, drawable: &Drawable) -> usize {
+        let vertices = &mut self.vertices;
+        let indices = &mut self.indices;
+
+        let index_range = self.geometry
+            .entry(drawable.mesh.key.clone())
+            .or_insert_with(|| {
+                let base_vertex = vertices.len() as u32;
+                let first_index = indices.len() as u32;
+
+                vertices.extend(drawable.mesh.vertices.iter().map(|v| *v));
+                indices.extend(drawable.mesh.indices.iter().map(|i| base_vertex + i));
+
+                Geometry {
+                    vertices: base_vertex..(vertices.len() as u32),
+                    indices: first_index..(indices.len() as u32),
+                }
+            })
+            .indices
+            .clone();
+
+        let first_instance = self.instance_capacity();
+        self.instance_data.resize((first_instance + INITIAL_BATCH_CAPACITY) as usize, ObjectUniformBufferObject::default());
+
+        self.batches.push(DrawBatch {
+            material: drawable.effective_material(),
+            mesh_key: drawable.mesh.key.clone(),
+            indices: index_range,
+            first_instance,
+            instance_capacity: INITIAL_BATCH_CAPACITY,
+            instances: Vec::new(),
+        });
+
+        self.batches.len() - 1
+    }
+
+    // Doubles a full batch and shifts the regions of every batch after it. Everything after the batch
+    // moves in the instance buffer, so it all has to be uploaded again.
+    fn grow_batch(&mut self, batch_index: usize) {
+        let batch = &mut self.batches[batch_index];
+        let grown_by = batch.instance_capacity;
+        let region_end = (batch.first_instance + batch.instance_capacity) as usize;
+        batch.instance_capacity += grown_by;
+
+        self.instance_data.splice(
+            region_end..region_end,
+            std::iter::repeat(ObjectUniformBufferObject::default()).take(grown_by as usize),
+        );
+
+        for batch in self.batches[(batch_index + 1)..].iter_mut() {
+            batch.first_instance += grown_by;
+        }
+
+        self.dirty_instances.extend(region_end..self.instance_data.len());
+    }
+
+    // Drops the empty batches and the geometry nothing else uses, packing everything that's left to the
+    // front. Every batch can end up somewhere else, so all of it has to be uploaded and recorded again.
+    fn compact(&mut self, changes: &mut BatchChanges) {
+        let old_batches = std::mem::replace(&mut self.batches, Vec::new());
+        let old_vertices = std::mem::replace(&mut self.vertices, Vec::new());
+        let old_indices = std::mem::replace(&mut self.indices, Vec::new());
+        let old_instance_data = std::mem::replace(&mut self.instance_data, Vec::new());
+        let old_geometry = std::mem::replace(&mut self.geometry, HashMap::new());
+        self.batch_lookup.clear();
+
+        for batch in old_batches.into_iter().filter(|batch| !batch.instances.is_empty()) {
+            let index_range = match self.geometry.get(&batch.mesh_key) {
+                Some(geometry) => geometry.indices.clone(),
+                None => {
+                    let old = &old_geometry[&batch.mesh_key];
+                    let base_vertex = self.vertices.len() as u32;
+                    let first_index = self.indices.len() as u32;
+
+                    self.vertices.extend_from_slice(&old_vertices[(old.vertices.start as usize)..(old.vertices.end as usize)]);
+                    self.indices.extend(old_indices[(old.indices.start as usize)..(old.indices.end as usize)]
+                        .iter()
+                        .map(|i| i - old.vertices.start + base_vertex));
+
+                    let geometry = Geometry {
+                        vertices: base_vertex..(self.vertices.len() as u32),
+                        indices: first_index..(self.indices.len() as u32),
+                    };
+                    self.geometry.insert(batch.mesh_key.clone(), geometry.clone());
+                    geometry.indices
+                }
+            };
+
+            let batch_index = self.batches.len();
+            let first_instance = self.instance_capacity();
+            let old_region = (batch.first_instance as usize)..((batch.first_instance + batch.instance_capacity) as usize);
+            self.instance_data.extend_from_slice(&old_instance_data[old_region]);
+
+            for (slot, entity) in batch.instances.iter().enumerate() {
+                self.locations.insert(*entity, (batch_index, slot));
+            }
+            self.batch_lookup.insert((batch.material.clone(), batch.mesh_key.clone()), batch_index);
+
+            self.batches.push(DrawBatch {
+                indices: index_range,
+                first_instance,
+                ..batch
+            });
+        }
+
+        changes.new_vertices = Some(0..self.vertices.len());
+        changes.new_indices = Some(0..self.indices.len());
+        changes.changed_batches = (0..self.batches.len()).collect();
+        changes.needs_rerecord = true;
+        self.dirty_instances = (0..self.instance_data.len()).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Matrix4, Vector3};
+    use legion::Universe;
+
+    use crate::components::global_transform::GlobalTransform;
+    use crate::components::mesh::Mesh;
+    use crate::components::texture::Texture;
+
+    fn entities(count: usize) -> Vec<Entity> {
+        let mut world = Universe::new(None).create_world();
+        world.insert_from((), (0..count).map(|i| (i,)).collect::<Vec<_>>()).to_vec()
+    }
+
+    // a quad, or a triangle when `vertex_count` is 3
+    fn mesh(key: &str, vertex_count: u32) -> Mesh {
+        let indices = if vertex_count == 3 { vec![0, 1, 2] } else { vec![0, 1, 2, 0, 2, 3] };
+
+        Mesh {
+            key: key.to_string(),
+            vertices: (0..vertex_count)
+                .map(|i| Vertex::new([i as f32, 0.0, 0.0], [1.0, 1.0, 1.0], [0.0, 0.0], [0.0, 0.0, 1.0]))
+                .collect(),
+            indices,
+            rendered: true,
+        }
+    }
+
+    fn drawable(entity: Entity, mesh: &Mesh, texture: Option<&str>) -> Drawable {
+        let mut drawable = Drawable::new(entity, mesh.clone(), GlobalTransform::identity());
+        drawable.texture = texture.map(Texture::new);
+        drawable
+    }
+
+    fn instance(x: f32) -> ObjectUniformBufferObject {
+        ObjectUniformBufferObject::new(Matrix4::from_translation(Vector3::new(x, 0.0, 0.0)))
+    }
+
+    #[test]
+    fn drawables_with_the_same_mesh_and_texture_share_a_batch() {
+        let entities = entities(3);
+        let quad = mesh("quad", 4);
+
+        let mut batches = DrawableBatches::new();
+        let changes = batches.sync(&entities.iter().map(|e| drawable(*e, &quad, None)).collect::<Vec<_>>());
+
+        assert_eq!(batches.batches.len(), 1);
+        assert_eq!(batches.batches[0].instances, entities);
+        assert_eq!(batches.vertices.len(), 4);
+        assert_eq!(changes.new_vertices, Some(0..4));
+        assert_eq!(changes.new_indices, Some(0..6));
+        assert!(changes.needs_rerecord);
+
+        let command = batches.indirect_commands()[0];
+        assert_eq!(command.index_count, 6);
+        assert_eq!(command.instance_count, 3);
+        assert_eq!(command.first_index, 0);
+        assert_eq!(command.vertex_offset, 0);
+        assert_eq!(command.first_instance, 0);
+    }
+
+    #[test]
+    fn each_mesh_gets_its_geometry_appended_once() {
+        let entities = entities(3);
+        let quad = mesh("quad", 4);
+        let triangle = mesh("triangle", 3);
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&[
+            drawable(entities[0], &quad, None),
+            drawable(entities[1], &triangle, None),
+            drawable(entities[2], &triangle, Some("brick.png")),
+        ]);
+
+        assert_eq!(batches.batches.len(), 3);
+        assert_eq!(batches.vertices.len(), 7);
+        assert_eq!(&batches.indices[6..], &[4, 5, 6]);
+
+        let commands = batches.indirect_commands();
+        assert_eq!((commands[1].first_index, commands[1].index_count), (6, 3));
+        assert_eq!((commands[2].first_index, commands[2].index_count), (6, 3));
+
+        let first_instances = batches.batches.iter().map(|batch| batch.first_instance).collect::<Vec<_>>();
+        assert_eq!(first_instances, vec![0, INITIAL_BATCH_CAPACITY, INITIAL_BATCH_CAPACITY * 2]);
+        assert_eq!(batches.instance_data.len() as u32, INITIAL_BATCH_CAPACITY * 3);
+    }
+
+    #[test]
+    fn syncing_the_same_drawables_again_changes_nothing() {
+        let entities = entities(2);
+        let quad = mesh("quad", 4);
+        let drawables = entities.iter().map(|e| drawable(*e, &quad, None)).collect::<Vec<_>>();
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&drawables);
+
+        assert!(batches.sync(&drawables).is_empty());
+    }
+
+    #[test]
+    fn removing_a_drawable_moves_the_last_instance_into_its_slot() {
+        let entities = entities(3);
+        let quad = mesh("quad", 4);
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&entities.iter().map(|e| drawable(*e, &quad, None)).collect::<Vec<_>>());
+        for (i, entity) in entities.iter().enumerate() {
+            batches.write_instance(entity, instance(i as f32));
+        }
+        batches.take_dirty_instances();
+
+        let changes = batches.sync(&[drawable(entities[0], &quad, None), drawable(entities[2], &quad, None)]);
+
+        assert!(!changes.needs_rerecord);
+        assert_eq!(changes.changed_batches, vec![0].into_iter().collect());
+        assert_eq!(batches.instance_slot(&entities[2]), Some(1));
+        assert_eq!(batches.instance_slot(&entities[1]), None);
+        assert_eq!(batches.instance_data[1], instance(2.0));
+        assert_eq!(batches.take_dirty_instances(), vec![1..2]);
+        assert_eq!(batches.indirect_commands()[0].instance_count, 2);
+    }
+
+    #[test]
+    fn changing_texture_moves_a_drawable_to_another_batch() {
+        let entities = entities(2);
+        let quad = mesh("quad", 4);
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&entities.iter().map(|e| drawable(*e, &quad, None)).collect::<Vec<_>>());
+
+        let changes = batches.sync(&[drawable(entities[0], &quad, None), drawable(entities[1], &quad, Some("brick.png"))]);
+
+        assert!(changes.needs_rerecord);
+        assert_eq!(changes.new_vertices, None);
+        assert_eq!(batches.batches.len(), 2);
+        assert_eq!(batches.batches[0].instances, vec![entities[0]]);
+        assert_eq!(batches.batches[1].instances, vec![entities[1]]);
+        assert_eq!(batches.instance_slot(&entities[1]), Some(INITIAL_BATCH_CAPACITY));
+    }
+
+    #[test]
+    fn a_full_batch_doubles_and_shifts_the_batches_after_it() {
+        let count = INITIAL_BATCH_CAPACITY as usize + 1;
+        let entities = entities(count + 1);
+        let quad = mesh("quad", 4);
+        let triangle = mesh("triangle", 3);
+        let last = entities[count];
+
+        let mut drawables = vec![drawable(entities[0], &quad, None), drawable(last, &triangle, None)];
+        let mut batches = DrawableBatches::new();
+        batches.sync(&drawables);
+        batches.write_instance(&last, instance(7.0));
+        batches.take_dirty_instances();
+
+        drawables.extend(entities[1..count].iter().map(|e| drawable(*e, &quad, None)));
+        let changes = batches.sync(&drawables);
+
+        let capacity = INITIAL_BATCH_CAPACITY * 2;
+        assert!(changes.needs_rerecord);
+        assert_eq!(batches.batches[0].instance_capacity, capacity);
+        assert_eq!(batches.batches[0].instances.len(), count);
+        assert_eq!(batches.batches[1].first_instance, capacity);
+        assert_eq!(batches.instance_capacity(), capacity + INITIAL_BATCH_CAPACITY);
+        assert_eq!(batches.instance_data.len() as u32, capacity + INITIAL_BATCH_CAPACITY);
+
+        // the other batch's instances moved along with its region
+        assert_eq!(batches.instance_slot(&last), Some(capacity));
+        assert_eq!(batches.instance_data[capacity as usize], instance(7.0));
+        assert_eq!(batches.take_dirty_instances(), vec![(INITIAL_BATCH_CAPACITY as usize)..(batches.instance_data.len())]);
+    }
+
+    #[test]
+    fn empty_batches_and_their_geometry_are_compacted_away() {
+        let entities = entities(3);
+        let quad = mesh("quad", 4);
+        let triangle = mesh("triangle", 3);
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&[
+            drawable(entities[0], &quad, None),
+            drawable(entities[1], &triangle, None),
+            drawable(entities[2], &triangle, None),
+        ]);
+        batches.write_instance(&entities[2], instance(3.0));
+        batches.take_dirty_instances();
+
+        let changes = batches.sync(&[drawable(entities[1], &triangle, None), drawable(entities[2], &triangle, None)]);
+
+        assert_eq!(batches.batches.len(), 1);
+        assert_eq!(batches.batches[0].mesh_key, "triangle");
+        assert_eq!(batches.batches[0].first_instance, 0);
+        assert_eq!(batches.vertices.len(), 3);
+        assert_eq!(batches.indices, vec![0, 1, 2]);
+        assert_eq!(batches.instance_capacity(), INITIAL_BATCH_CAPACITY);
+        assert_eq!(batches.instance_slot(&entities[2]), Some(1));
+        assert_eq!(batches.instance_data[1], instance(3.0));
+
+        assert!(changes.needs_rerecord);
+        assert_eq!(changes.new_vertices, Some(0..3));
+        assert_eq!(changes.new_indices, Some(0..3));
+        assert_eq!(changes.changed_batches, vec![0].into_iter().collect());
+        assert_eq!(batches.take_dirty_instances(), vec![0..(INITIAL_BATCH_CAPACITY as usize)]);
+
+        let command = batches.indirect_commands()[0];
+        assert_eq!((command.first_index, command.index_count, command.instance_count), (0, 3, 2));
+    }
+
+    #[test]
+    fn only_changed_instances_are_dirty_and_merged_into_runs() {
+        let entities = entities(4);
+        let quad = mesh("quad", 4);
+
+        let mut batches = DrawableBatches::new();
+        batches.sync(&entities.iter().map(|e| drawable(*e, &quad, None)).collect::<Vec<_>>());
+        batches.take_dirty_instances();
+
+        batches.write_instance(&entities[0], instance(1.0));
+        batches.write_instance(&entities[1], instance(1.0));
+        batches.write_instance(&entities[3], instance(1.0));
+        // the identity is already there
+        batches.write_instance(&entities[2], ObjectUniformBufferObject::default());
+
+        assert_eq!(batches.take_dirty_instances(), vec![0..2, 3..4]);
+        assert!(batches.take_dirty_instances().is_empty());
+
+        batches.write_instance(&entities[0], instance(1.0));
+        assert!(batches.take_dirty_instances().is_empty());
+    }
+
+    #[test]
+    fn meshes_that_arent_rendered_are_left_out() {
+        let entities = entities(1);
+        let mut hidden = mesh("quad", 4);
+        hidden.rendered = false;
+
+        let mut batches = DrawableBatches::new();
+        let changes = batches.sync(&[drawable(entities[0], &hidden, None)]);
+
+        assert!(changes.is_empty());
+        assert!(batches.batches.is_empty());
+        assert_eq!(batches.instance_slot(&entities[0]), None);
+    }
+}
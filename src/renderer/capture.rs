@@ -0,0 +1,68 @@
+use std::path::Path;
+
+// A frame copied back from the gpu. Pixels are always tightly packed RGBA8 rows, starting with the top row,
+// no matter what channel order the image it came from used.
+pub(crate) struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn new(width: u32, height: u32, format: hal::format::Format, mut pixels: Vec<u8>) -> Self {
+        // most swapchains hand us bgra images, swap them around so everything downstream can assume rgba
+        if format.base_format().0 == hal::format::SurfaceType::B8_G8_R8_A8 {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn load_png(path: &Path) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("failed to load {:?}: {}", path, e))?
+            .to_rgba();
+        let (width, height) = image.dimensions();
+
+        Ok(Self {
+            width,
+            height,
+            pixels: image.into_raw(),
+        })
+    }
+
+    // the mean absolute difference per channel, from 0 for identical frames to 255
+    pub fn difference(&self, other: &CapturedFrame) -> Result<f32, String> {
+        if self.width != other.width || self.height != other.height {
+            return Err(format!("can't compare a {}x{} frame to a {}x{} one", self.width, self.height, other.width, other.height));
+        }
+
+        let total: u64 = self.pixels
+            .iter()
+            .zip(other.pixels.iter())
+            .map(|(a, b)| (*a as i32 - *b as i32).abs() as u64)
+            .sum();
+
+        Ok(total as f32 / self.pixels.len().max(1) as f32)
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+
+        image::save_buffer(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::RGBA(8),
+        ).map_err(|e| e.to_string())
+    }
+}
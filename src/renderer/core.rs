@@ -20,7 +20,27 @@ impl RendererCore<back::Backend> {
 
             let device = GfxDevice::new(
                 backend.adapter.adapter.take().unwrap(),
-                backend.surface.read().unwrap().as_ref().unwrap(),
+                backend.surface.read().unwrap().as_ref().map(|surface| surface as &dyn hal::window::Surface<back::Backend>),
+            );
+
+            Self {
+                instance,
+                backend,
+                device,
+            }
+        }
+    }
+
+    // A core with no window or surface. Anything rendered with it has to go through a presenter that
+    // owns its own images, like the OffscreenPresenter.
+    #[cfg(feature = "headless")]
+    pub fn new_headless() -> Self {
+        unsafe {
+            let (mut backend, instance) = create_headless_backend();
+
+            let device = GfxDevice::new(
+                backend.adapter.adapter.take().unwrap(),
+                None,
             );
 
             Self {
@@ -41,7 +61,9 @@ pub(crate) fn run_with_device<T, B: hal::Backend>(core: &Arc<RwLock<RendererCore
 impl <B: hal::Backend> Drop for RendererCore<B> {
     fn drop(&mut self) {
         unsafe {
-            self.instance.destroy_surface(self.backend.surface.write().unwrap().take().unwrap())
+            if let Some(surface) = self.backend.surface.write().unwrap().take() {
+                self.instance.destroy_surface(surface)
+            }
         }
     }
 }
@@ -86,15 +108,18 @@ pub(crate) struct GfxDevice<B: hal::Backend> {
     pub physical_device: B::PhysicalDevice,
     pub queue_group: hal::queue::QueueGroup<B>,
     pub queue_family_id: Option<hal::queue::family::QueueFamilyId>,
+    pub enabled_features: hal::Features,
 }
 
 impl <B: hal::Backend> GfxDevice<B> {
-    unsafe fn new(adapter: hal::adapter::Adapter<B>, surface: &dyn hal::window::Surface<B>) -> Self {
+    unsafe fn new(adapter: hal::adapter::Adapter<B>, surface: Option<&dyn hal::window::Surface<B>>) -> Self {
         let family = adapter
             .queue_families
             .iter()
-            .find(|family|
-                surface.supports_queue_family(family) && family.queue_type().supports_graphics())
+            .find(|family| {
+                let supports_surface = surface.map_or(true, |surface| surface.supports_queue_family(family));
+                supports_surface && family.queue_type().supports_graphics()
+            })
             .unwrap();
 
         #[cfg(not(feature = "vulkan"))]
@@ -107,9 +132,12 @@ impl <B: hal::Backend> GfxDevice<B> {
             Some(back_queue_family.id())
         };
 
+        // anisotropic filtering is optional, samplers only ask for it when the device has it
+        let enabled_features = adapter.physical_device.features() & hal::Features::SAMPLER_ANISOTROPY;
+
         let mut gpu = adapter
             .physical_device
-            .open(&[(family, &[1.0])], hal::Features::empty())
+            .open(&[(family, &[1.0])], enabled_features)
             .unwrap();
 
         Self {
@@ -117,6 +145,7 @@ impl <B: hal::Backend> GfxDevice<B> {
             physical_device: adapter.physical_device,
             queue_group: gpu.queue_groups.pop().unwrap(),
             queue_family_id: family_id,
+            enabled_features,
         }
     }
 }
@@ -127,12 +156,14 @@ pub(crate) struct GfxBackend<B: hal::Backend> {
 
     #[cfg(any(feature = "vulkan", feature = "dx11", feature = "dx12", feature = "metal"))]
     #[allow(dead_code)]
-    pub window: winit::window::Window,
+    pub window: Option<winit::window::Window>,
 }
 
 impl <B: hal::Backend> GfxBackend<B> {
     pub fn window(&self) -> &winit::window::Window {
-        &self.window
+        self.window
+            .as_ref()
+            .expect("renderer core was created without a window")
     }
 }
 
@@ -175,7 +206,31 @@ fn create_backend(window_builder: winit::window::WindowBuilder, event_loop: &win
     let backend_state = GfxBackend {
         surface: Arc::new(RwLock::new(Some(surface))),
         adapter: GfxAdapter::new(&mut adapters),
-        window
+        window: Some(window),
+    };
+
+    (backend_state, instance)
+}
+
+#[cfg(all(feature="headless", not(any(feature="gl", feature="dx12", feature="vulkan", feature="metal"))))]
+fn create_headless_backend() -> (GfxBackend<back::Backend>, ()) {
+    panic!("You must specify one of the valid backends using --features=<backend>, with \"dx12\", \"vulkan\", and \"metal\" being valid headless backends.");
+}
+
+#[cfg(all(feature="headless", feature="gl"))]
+fn create_headless_backend() -> (GfxBackend<back::Backend>, ()) {
+    panic!("headless rendering is not supported on the gl backend since it needs a window to create a context");
+}
+
+#[cfg(all(feature="headless", any(feature="dx12", feature="vulkan", feature="metal")))]
+fn create_headless_backend() -> (GfxBackend<back::Backend>, back::Instance) {
+    let instance = back::Instance::create("matthew's spectacular rendering engine", 1).expect("failed to create an instance");
+    let mut adapters = instance.enumerate_adapters();
+
+    let backend_state = GfxBackend {
+        surface: Arc::new(RwLock::new(None)),
+        adapter: GfxAdapter::new(&mut adapters),
+        window: None,
     };
 
     (backend_state, instance)
@@ -1,85 +1,130 @@
 use std::sync::{Arc, RwLock};
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
-use crate::components::{mesh::Mesh, transform::Transform};
+use crate::components::camera::Camera;
+use crate::components::global_transform::GlobalTransform;
+use crate::components::transform::Transform;
 use crate::primitives::{drawable::Drawable, vertex::Vertex};
-use crate::primitives::uniform_buffer_object::{CameraUniformBufferObject, ObjectUniformBufferObject};
-use crate::renderer::allocator::{COLOR_RANGE, Allocator, GfxAllocator, DescriptorPoolType};
-use crate::renderer::types::{Image, Uniform, Buffer, DescSetLayout};
+use crate::primitives::two_d::quad::Quad;
+use crate::primitives::uniform_buffer_object::{CameraUniformBufferObject, ObjectUniformBufferObject, LightsUniformBufferObject, MaterialConstants, MAX_SHADOW_MAPS};
+use crate::components::material::Material;
+use crate::components::text::Text;
+use crate::components::texture::{ColorSpace, SamplerOptions, Texture, TexturePixels};
+use crate::renderer::allocator::{MATERIAL_TEXTURE_COUNT, Allocator, GfxAllocator, DescriptorPoolType};
+use crate::renderer::types::{Uniform, Buffer, DescSet, DescSetLayout, DescSetWrite};
 use crate::renderer::render_key::RenderKey;
+use crate::renderer::batch::{DrawableBatches, DrawBatch, DrawIndexedIndirectCommand};
+use crate::renderer::glyph_atlas::{GlyphAtlas, PlacedGlyph};
+use crate::renderer::graph::{ImageDesc, ImageSize, PassDesc, PassId, RenderGraph, RenderGraphBuilder, ResourceId};
 use crate::renderer::core::{RendererCore, run_with_device};
 use crate::utils::data_path;
 
-use cgmath::{Vector3, Matrix4};
+use cgmath::{Vector3, Matrix4, SquareMatrix};
 use cgmath::Angle;
 
 use itertools::Itertools;
 
+use legion::Entity;
+
 use hal::pool::CommandPool;
 use hal::command::CommandBuffer;
 use hal::pso::Viewport;
 use hal::device::Device;
 use hal::queue::CommandQueue;
-use crate::renderer::presenter::DIMS;
 use std::ops::DerefMut;
 
+const DEPTH_CLEAR: hal::command::ClearValue = hal::command::ClearValue {
+    depth_stencil: hal::command::ClearDepthStencil { depth: 1.0, stencil: 0 },
+};
+
+const REVERSED_DEPTH_CLEAR: hal::command::ClearValue = hal::command::ClearValue {
+    depth_stencil: hal::command::ClearDepthStencil { depth: 0.0, stencil: 0 },
+};
+
 pub(crate) trait Drawer<B: hal::Backend> : Send + Sync {
     fn draw(&mut self, image_index: usize, acquire_semaphore: Option<&B::Semaphore>, present_semaphore: Option<&B::Semaphore>);
     fn update_drawables(&mut self, drawables: Vec<Drawable>) -> Result<(), String>;
-    fn update_uniforms(&mut self, uniforms: Vec<ObjectUniformBufferObject>) -> Result<(), String>;
-    fn update_camera(&mut self, transform: Transform) -> Result<(), String>;
+    fn update_uniforms(&mut self, uniforms: Vec<(Entity, ObjectUniformBufferObject)>) -> Result<(), String>;
+    fn update_camera(&mut self, camera: Camera, transform: Transform, global: GlobalTransform) -> Result<(), String>;
+    fn update_lights(&mut self, lights: LightsUniformBufferObject) -> Result<(), String>;
+    fn update_ui(&mut self, quads: Vec<Quad>, texts: Vec<Text>) -> Result<(), String>;
+    fn rebuild_framebuffers(&mut self, viewport: Viewport, images: Vec<&B::Image>, image_format: hal::format::Format) -> Result<(), String>;
 }
 
 pub(crate) struct GfxDrawer<B: hal::Backend, A: Allocator<B>> {
     core: Arc<RwLock<RendererCore<B>>>,
     allocator: Arc<RwLock<A>>,
 
-    framebuffers: Framebuffers<B>,
-    render_pass: RenderPass<B>,
+    frames: Frames<B>,
+    graph: RenderGraph<B>,
+    main_pass: PassId,
+    depth: ResourceId,
+    reversed_z: bool,
     pipeline: Pipeline<B>,
     viewport: Viewport,
+    shadow_maps: ShadowMaps<B>,
+    ui: UiOverlay<B>,
 
-    texture_desc_set_layout: Arc<RwLock<DescSetLayout<B>>>,
+    material_desc_set_layout: Arc<RwLock<DescSetLayout<B>>>,
     textures: HashMap<RenderKey, crate::renderer::types::Texture<B>>,
+    // textures that failed to load, materials use the default for the slot instead
+    missing_textures: HashSet<RenderKey>,
+    materials: HashMap<RenderKey, DescSet<B>>,
 
     vertex_buffer: Option<Buffer<B>>,
     index_buffer: Option<Buffer<B>>,
+    instance_buffer: Option<Buffer<B>>,
+    indirect_buffer: Option<Buffer<B>>,
 
     camera_uniform: Uniform<B>,
-    object_uniform: Uniform<B>,
+    lights_uniform: Uniform<B>,
 
-    last_drawables: Option<Vec<Drawable>>,
+    batches: DrawableBatches,
 }
 
 impl <B: hal::Backend> GfxDrawer<B, GfxAllocator<B>> {
-    pub fn new(core: &Arc<RwLock<RendererCore<B>>>, allocator: &Arc<RwLock<GfxAllocator<B>>>, viewport: Viewport, images: Vec<B::Image>, image_format: hal::format::Format) -> Self {
-        let render_pass = RenderPass::new(
-            core,
+    pub fn new(core: &Arc<RwLock<RendererCore<B>>>, allocator: &Arc<RwLock<GfxAllocator<B>>>, viewport: Viewport, images: Vec<&B::Image>, image_format: hal::format::Format) -> Self {
+        let frame_count = std::cmp::max(images.len(), 1); // GL can have zero
+
+        // shadow maps first, then the scene sampling them
+        let mut graph = RenderGraphBuilder::new(
             image_format,
+            hal::command::ClearValue { color: hal::command::ClearColor { float32: [0.7, 0.2, 0.0, 1.0] } },
         );
 
-        let depth_image = allocator.write().unwrap().alloc_image(
-            viewport.rect.w as u32,
-            viewport.rect.h as u32,
-            hal::format::Format::D32SfloatS8Uint,
-            hal::image::Usage::DEPTH_STENCIL_ATTACHMENT,
-            hal::format::Aspects::DEPTH | hal::format::Aspects::STENCIL
-        );
+        let shadow_map_images = (0..MAX_SHADOW_MAPS)
+            .map(|shadow_map| graph.create_image(&format!("shadow_map_{}", shadow_map), ImageDesc {
+                format: SHADOW_MAP_FORMAT,
+                size: ImageSize::Fixed(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE),
+                clear: DEPTH_CLEAR,
+            }))
+            .collect::<Vec<ResourceId>>();
 
-        let framebuffers = unsafe {
-            Framebuffers::new(
-                &core,
-                hal::image::Extent {
-                    width: viewport.rect.w as u32,
-                    height: viewport.rect.h as u32,
-                    depth: viewport.depth.end as u32,
-                },
-                images,
-                image_format,
-                &render_pass,
-                depth_image,
-            )
-        };
+        let shadow_passes = shadow_map_images
+            .iter()
+            .enumerate()
+            .map(|(shadow_map, image)| graph.add_pass(PassDesc::new(&format!("shadow_{}", shadow_map)).depth(*image)))
+            .collect::<Vec<PassId>>();
+
+        let depth = graph.create_image("depth", ImageDesc {
+            format: hal::format::Format::D32SfloatS8Uint,
+            size: ImageSize::Backbuffer,
+            clear: DEPTH_CLEAR,
+        });
+
+        let main_pass = graph.add_pass(shadow_map_images
+            .iter()
+            .fold(PassDesc::new("main").color(graph.backbuffer()).depth(depth), |pass, image| pass.read(*image)));
+
+        // drawn over the scene, so it loads what the main pass left in the backbuffer
+        let ui_pass = graph.add_pass(PassDesc::new("ui").color(graph.backbuffer()));
+
+        let graph = graph
+            .build(core, allocator, &viewport, images)
+            .expect("Can't build render graph");
+
+        let frames = unsafe { Frames::new(core, frame_count) };
 
         let camera_uniform = Self::init_uniform(
             &mut allocator.write().unwrap(),
@@ -98,67 +143,123 @@ impl <B: hal::Backend> GfxDrawer<B, GfxAllocator<B>> {
             &[CameraUniformBufferObject::default()]
         );
 
-        let object_uniform = Self::init_uniform(
+        let lights_uniform = Self::init_uniform(
             &mut allocator.write().unwrap(),
             &vec![hal::pso::DescriptorSetLayoutBinding {
                 binding: 0,
                 ty: hal::pso::DescriptorType::Buffer {
                     ty: hal::pso::BufferDescriptorType::Uniform,
                     format: hal::pso::BufferDescriptorFormat::Structured {
-                        dynamic_offset: true,
+                        dynamic_offset: false,
                     }
                 },
                 count: 1,
-                stage_flags: hal::pso::ShaderStageFlags::VERTEX,
+                stage_flags: hal::pso::ShaderStageFlags::VERTEX | hal::pso::ShaderStageFlags::FRAGMENT,
                 immutable_samplers: false,
             }],
-            &[ObjectUniformBufferObject::default()],
+            &[LightsUniformBufferObject::default()]
         );
 
-        let texture_desc_set_layout = allocator.write().unwrap().alloc_desc_set_layout(
-            &vec![hal::pso::DescriptorSetLayoutBinding {
-                binding: 0,
-                ty: hal::pso::DescriptorType::Image {
-                    ty: hal::pso::ImageDescriptorType::Sampled {
-                        with_sampler: true,
-                    }
-                },
-                count: 1,
-                stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
-                immutable_samplers: false
-            }]);
+        let shadow_maps = ShadowMaps::new(
+            core,
+            allocator,
+            &graph,
+            shadow_passes,
+            &shadow_map_images,
+            lights_uniform.desc.as_ref().unwrap().desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
+        );
+
+        let ui = UiOverlay::new(core, allocator, &graph, ui_pass, frame_count);
+
+        // one combined image sampler per material map, see Material::textures for the order
+        let material_desc_set_layout = allocator.write().unwrap().alloc_desc_set_layout(
+            &(0..MATERIAL_TEXTURE_COUNT)
+                .map(|binding| hal::pso::DescriptorSetLayoutBinding {
+                    binding: binding as u32,
+                    ty: hal::pso::DescriptorType::Image {
+                        ty: hal::pso::ImageDescriptorType::Sampled {
+                            with_sampler: true,
+                        }
+                    },
+                    count: 1,
+                    stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false
+                })
+                .collect::<Vec<_>>());
 
         let pipeline = unsafe {
-            Pipeline::new(
-                core,
-                render_pass.render_pass.as_ref().unwrap(),
-                vec![
-                    camera_uniform.desc.as_ref().unwrap().desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
-                    object_uniform.desc.as_ref().unwrap().desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
-                    texture_desc_set_layout.layout.as_ref().unwrap()
-                ],
-                "shaders/standard.vert",
-                "shaders/standard.frag",
-            )
+            Self::scene_pipeline(core, graph.render_pass(main_pass), &camera_uniform, &material_desc_set_layout, &lights_uniform, &shadow_maps, false)
         };
 
         Self {
             core: Arc::clone(core),
             allocator: Arc::clone(allocator),
-            framebuffers,
-            render_pass,
+            frames,
+            graph,
+            main_pass,
+            depth,
+            reversed_z: false,
             pipeline,
             viewport,
-            texture_desc_set_layout: Arc::new(RwLock::new(texture_desc_set_layout)),
+            shadow_maps,
+            ui,
+            material_desc_set_layout: Arc::new(RwLock::new(material_desc_set_layout)),
             textures: HashMap::new(),
+            missing_textures: HashSet::new(),
+            materials: HashMap::new(),
             vertex_buffer: None,
             index_buffer: None,
+            instance_buffer: None,
+            indirect_buffer: None,
             camera_uniform,
-            object_uniform,
-            last_drawables: None
+            lights_uniform,
+            batches: DrawableBatches::new(),
         }
     }
 
+    unsafe fn scene_pipeline(core: &Arc<RwLock<RendererCore<B>>>,
+                             render_pass: &B::RenderPass,
+                             camera_uniform: &Uniform<B>,
+                             material_desc_set_layout: &DescSetLayout<B>,
+                             lights_uniform: &Uniform<B>,
+                             shadow_maps: &ShadowMaps<B>,
+                             reversed_z: bool) -> Pipeline<B> {
+        Pipeline::new(
+            core,
+            render_pass,
+            vec![
+                camera_uniform.desc.as_ref().unwrap().desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
+                material_desc_set_layout.layout.as_ref().unwrap(),
+                lights_uniform.desc.as_ref().unwrap().desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
+                shadow_maps.desc_set_layout.read().unwrap().layout.as_ref().unwrap(),
+            ],
+            &[(hal::pso::ShaderStageFlags::FRAGMENT, 0..std::mem::size_of::<MaterialConstants>() as u32)],
+            "shaders/standard.vert",
+            Some("shaders/standard.frag"),
+            PipelineKind::Scene { reversed_z },
+        )
+    }
+
+    // The depth test and the value the depth buffer gets cleared to both flip with reversed z, so the
+    // scene pipeline gets rebuilt and the command buffers recorded again.
+    unsafe fn set_reversed_z(&mut self, reversed_z: bool) {
+        run_with_device(&self.core, |device| device.wait_idle().unwrap());
+
+        self.pipeline = Self::scene_pipeline(
+            &self.core,
+            self.graph.render_pass(self.main_pass),
+            &self.camera_uniform,
+            &self.material_desc_set_layout.read().unwrap(),
+            &self.lights_uniform,
+            &self.shadow_maps,
+            reversed_z,
+        );
+        self.graph.set_clear(self.depth, if reversed_z { REVERSED_DEPTH_CLEAR } else { DEPTH_CLEAR });
+        self.reversed_z = reversed_z;
+
+        self.generate_cmd_buffers();
+    }
+
     // TODO -> is there a way to streamline uniform allocation so that it encapsulates DescSetLayouts and DescSets?
     fn init_uniform<T>(allocator: &mut GfxAllocator<B>, bindings: &[hal::pso::DescriptorSetLayoutBinding], data: &[T])-> Uniform<B>
         where T: Copy,
@@ -166,72 +267,166 @@ impl <B: hal::Backend> GfxDrawer<B, GfxAllocator<B>> {
     {
         // TODO -> this is all weird cause we allocate a new desc_set_layout but then wrap it.
         let desc_set_layout = allocator.alloc_desc_set_layout(bindings);
-        let desc_set = allocator.alloc_desc_set(DescriptorPoolType::Uniform, &Arc::new(RwLock::new(desc_set_layout)));
+        let desc_set = allocator
+            .alloc_desc_set(DescriptorPoolType::Uniform, &Arc::new(RwLock::new(desc_set_layout)))
+            .expect("Can't allocate uniform descriptor set");
         allocator.alloc_uniform(data, desc_set, 0)
     }
 
-    unsafe fn generate_vertex_and_index_buffers(&mut self, meshes: Vec<&Mesh>) {
-        let vertices = meshes
-            .iter()
-            .flat_map(|m| {
-                m.vertices.iter().map(|v| *v)
-            })
-            .collect::<Vec<Vertex>>();
+    // Uploads `data[changed]` into `buffer`. If the buffer is too small it is replaced by one with room to
+    // grow and all of `data` is uploaded instead, in which case this returns true since any command buffers
+    // recorded against the old buffer are now invalid.
+    unsafe fn upload_buffer<T>(core: &Arc<RwLock<RendererCore<B>>>,
+                               allocator: &Arc<RwLock<GfxAllocator<B>>>,
+                               buffer: &mut Option<Buffer<B>>,
+                               data: &[T],
+                               changed: Range<usize>,
+                               alignment: u64,
+                               usage: hal::buffer::Usage) -> bool
+        where T: Copy,
+              T: std::fmt::Debug
+    {
+        if data.is_empty() {
+            return false;
+        }
 
-        let mut current_index = 0;
-        let indices = meshes
-            .iter()
-            .flat_map(|m| {
-                let indices = m.indices
-                    .iter()
-                    .map(move |val| current_index + val);
+        let capacity = buffer
+            .as_ref()
+            .map_or(0, |buffer| (buffer.size / buffer.padded_stride) as usize);
 
-                current_index += m.vertices.len() as u32;
+        if data.len() <= capacity {
+            if changed.start < changed.end {
+                let buffer = buffer.as_mut().unwrap();
+                let offset = changed.start as u64 * buffer.padded_stride;
+                buffer.update_data(core, offset, &data[changed]);
+            }
 
-                return indices;
-            })
-            .collect::<Vec<u32>>();
+            return false;
+        }
 
-        let vertex_alignment = self.core.read().unwrap().backend.adapter.limits.min_vertex_input_binding_stride_alignment;
-        let vertex_buffer = self.allocator.write().unwrap().alloc_buffer(
-            &vertices,
-            vertex_alignment,
-            65536,
-            hal::buffer::Usage::VERTEX,
-            hal::memory::Properties::CPU_VISIBLE,
-        );
+        // the old buffer may still be referenced by in flight command buffers so we wait for the gpu first
+        if let Some(mut old_buffer) = buffer.take() {
+            run_with_device(core, |device| {
+                device.wait_idle().unwrap();
+                old_buffer.drop(device);
+            });
+        }
 
-        let index_buffer = self.allocator.write().unwrap().alloc_buffer(
-            &indices,
-            1,
-            65536,
-            hal::buffer::Usage::INDEX,
+        let min_size = (data.len() * 2 * std::mem::size_of::<T>()) as u64;
+
+        *buffer = Some(allocator.write().unwrap().alloc_buffer(
+            data,
+            alignment,
+            std::cmp::max(min_size, 65536),
+            usage,
             hal::memory::Properties::CPU_VISIBLE,
-        );
+        ));
+
+        true
+    }
+
+    // Writes each entity's model matrix into its batch slot and uploads only the slots that changed.
+    // Returns true if the instance buffer was replaced.
+    unsafe fn upload_instances(&mut self, uniforms: &[(Entity, ObjectUniformBufferObject)]) -> bool {
+        for (entity, ubo) in uniforms.iter() {
+            self.batches.write_instance(entity, *ubo);
+        }
+
+        let vertex_alignment = self.core.read().unwrap().backend.adapter.limits.min_vertex_input_binding_stride_alignment;
 
-        self.vertex_buffer = Some(vertex_buffer);
-        self.index_buffer = Some(index_buffer);
+        for changed in self.batches.take_dirty_instances() {
+            // a replaced buffer got all of the instances at once
+            if Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut self.instance_buffer,
+                &self.batches.instance_data,
+                changed,
+                vertex_alignment,
+                hal::buffer::Usage::VERTEX,
+            ) {
+                return true;
+            }
+        }
+
+        false
     }
 
+    // a texture that can't be loaded is only reported the first time, it isn't tried again
     unsafe fn generate_images(&mut self, textures: Vec<&crate::components::texture::Texture>) {
         let new_textures: Vec<&crate::components::texture::Texture> = textures
             .into_iter()
-            .filter(|t| !self.textures.contains_key(&RenderKey::from(*t)))
+            .filter(|t| !self.textures.contains_key(&RenderKey::from(*t)) && !self.missing_textures.contains(&RenderKey::from(*t)))
             .unique()
             .collect();
 
-        if new_textures.is_empty() {
-            return;
-        }
-
         for domain_texture in new_textures.into_iter() {
             let texture = self.allocator.write().unwrap().alloc_texture(
                 hal::buffer::Usage::TRANSFER_SRC,
-                &domain_texture.path,
-                &hal::image::SamplerDesc::new(hal::image::Filter::Linear, hal::image::WrapMode::Clamp),
-                &self.texture_desc_set_layout,
+                domain_texture,
             );
-            self.textures.insert(RenderKey::from(domain_texture), texture);
+
+            match texture {
+                Ok(texture) => {
+                    self.textures.insert(RenderKey::from(domain_texture), texture);
+                },
+                Err(e) => {
+                    log::error!("failed to load texture, drawing with a default one instead: {}", e);
+                    self.missing_textures.insert(RenderKey::from(domain_texture));
+                },
+            }
+        }
+    }
+
+    // Materials share a descriptor set as long as they use the same maps. The sets all point at the one
+    // layout, so they aren't dropped one by one.
+    unsafe fn generate_material_sets(&mut self, materials: &[Material]) {
+        for material in materials {
+            let material_key = RenderKey::from(material);
+            if self.materials.contains_key(&material_key) {
+                continue;
+            }
+
+            let desc_set = match self.allocator.write().unwrap().alloc_desc_set(
+                DescriptorPoolType::Texture,
+                &self.material_desc_set_layout,
+            ) {
+                Ok(desc_set) => desc_set,
+                Err(e) => {
+                    // the batches using it are skipped until a set can be allocated
+                    log::error!("failed to allocate a material descriptor set: {}", e);
+                    continue;
+                },
+            };
+
+            let material_textures = material_textures(material);
+            let textures = &self.textures;
+            run_with_device(&self.core, |device| {
+                desc_set.write(
+                    device,
+                    material_textures
+                        .iter()
+                        .enumerate()
+                        .map(|(binding, texture)| {
+                            let texture = textures
+                                .get(&RenderKey::from(texture))
+                                .or_else(|| textures.get(&RenderKey::from(&default_texture(binding))))
+                                .unwrap();
+                            DescSetWrite {
+                                binding: binding as u32,
+                                array_offset: 0,
+                                descriptors: hal::pso::Descriptor::CombinedImageSampler(
+                                    texture.image.image_view.as_ref().unwrap(),
+                                    hal::image::Layout::ShaderReadOnlyOptimal,
+                                    texture.sampler.as_ref().unwrap(),
+                                ),
+                            }
+                        })
+                        .collect(),
+                );
+            });
+
+            self.materials.insert(material_key, desc_set);
         }
     }
 
@@ -239,131 +434,271 @@ impl <B: hal::Backend> GfxDrawer<B, GfxAllocator<B>> {
     // Pitch must be in the range of [-90 ... 90] degrees and
     // yaw must be in the range of [0 ... 360] degrees.
     // Pitch and yaw variables must be expressed in radians.
-    pub fn update_camera_uniform_buffer_object(&self, dimensions: [f32;2], camera_transform: &Transform) -> CameraUniformBufferObject {
+    // The fps view only knows about the camera's own transform, so whatever it's parented to is undone on
+    // top of it. Without a parent the global transform is the camera's own and that part drops out.
+    pub fn update_camera_uniform_buffer_object(&self, dimensions: [f32;2], camera: &Camera, camera_transform: &Transform, camera_global: &GlobalTransform) -> CameraUniformBufferObject {
         let position = camera_transform.position;
         let rotation = cgmath::Euler::from(camera_transform.rotation);
 
-        let view = fps_view_matrix(position, rotation.y, rotation.x);
-
-        let mut proj = cgmath::perspective(
-            cgmath::Deg(45.0),
-            dimensions[0] / dimensions[1],
-            0.1,
-            1000.0
-        );
-
-        proj.y.y *= -1.0;
+        let parent = camera_global.0 * camera_transform.matrix().invert().unwrap_or_else(Matrix4::identity);
+        let view = fps_view_matrix(position, rotation.y, rotation.x) * parent.invert().unwrap_or_else(Matrix4::identity);
+        let proj = camera.projection_matrix(dimensions[0] / dimensions[1]);
 
         CameraUniformBufferObject::new(view, proj)
     }
 
-    unsafe fn generate_cmd_buffers(&mut self , meshes_by_texture: BTreeMap<Option<crate::components::texture::Texture>, Vec<&Mesh>>) {
-        let framebuffers = self.framebuffers
-            .framebuffers
-            .as_ref()
-            .unwrap();
-
-        let command_pools = self.framebuffers
+    unsafe fn generate_cmd_buffers(&mut self) {
+        let command_pools = self.frames
             .command_pools
             .as_mut()
             .unwrap();
 
-        let num_buffers = framebuffers.len();
+        // the previous command buffers go back to their pools once the gpu is done with them
+        if let Some(old_command_buffers) = self.frames.command_buffers.take() {
+            run_with_device(&self.core, |device| device.wait_idle().unwrap());
 
-        // TODO -> assert all sizes are same and all options are "Some"
+            for (command_pool, command_buffer) in command_pools.iter_mut().zip(old_command_buffers) {
+                command_pool.free(Some(command_buffer));
+            }
+        }
 
-        let mut command_buffers: Vec<B::CommandBuffer> = Vec::new();
+        let mut command_buffers = command_pools
+            .iter_mut()
+            .map(|command_pool| command_pool.allocate_one(hal::command::Level::Primary))
+            .collect::<Vec<B::CommandBuffer>>();
 
-        for current_buffer_index in 0..num_buffers {
-            let framebuffer = &framebuffers[current_buffer_index];
-            let command_pool = &mut command_pools[current_buffer_index];
+        for (frame, cmd_buffer) in command_buffers.iter_mut().enumerate() {
+            self.record_cmd_buffer(frame, cmd_buffer);
+        }
 
-            // Rendering
-            let mut cmd_buffer = command_pool.allocate_one(hal::command::Level::Primary);
-            cmd_buffer.begin_primary(hal::command::CommandBufferFlags::SIMULTANEOUS_USE);
+        self.frames.command_buffers = Some(command_buffers);
+    }
 
-            cmd_buffer.set_viewports(0, &[self.viewport.clone()]);
-            cmd_buffer.set_scissors(0, &[self.viewport.rect]);
+    // re-records a single frame, whose fence has to have signalled already
+    unsafe fn regenerate_cmd_buffer(&mut self, frame: usize) {
+        if self.frames.command_buffers.is_none() {
+            return;
+        }
 
-            cmd_buffer.bind_graphics_pipeline(&self.pipeline.pipeline.as_ref().unwrap());
-            cmd_buffer.bind_vertex_buffers(0, Some((self.vertex_buffer.as_ref().unwrap().get_buffer(), hal::buffer::SubRange {
-                offset: 0,
-                size: None
-            })));
-            cmd_buffer.bind_index_buffer(hal::buffer::IndexBufferView {
-                buffer: self.index_buffer.as_ref().unwrap().get_buffer(),
-                range: hal::buffer::SubRange {
-                    offset: 0,
-                    size: None,
-                },
-                index_type: hal::IndexType::U32
-            });
+        let mut cmd_buffer = self.frames.command_pools.as_mut().unwrap()[frame].allocate_one(hal::command::Level::Primary);
+        self.record_cmd_buffer(frame, &mut cmd_buffer);
+
+        let old_cmd_buffer = std::mem::replace(&mut self.frames.command_buffers.as_mut().unwrap()[frame], cmd_buffer);
+        self.frames.command_pools.as_mut().unwrap()[frame].free(Some(old_cmd_buffer));
+    }
+
+    unsafe fn record_cmd_buffer(&self, frame: usize, cmd_buffer: &mut B::CommandBuffer) {
+        cmd_buffer.begin_primary(hal::command::CommandBufferFlags::SIMULTANEOUS_USE);
+        self.graph.record(cmd_buffer, frame, |pass, cmd_buffer| self.record_pass(pass, frame, cmd_buffer));
+        cmd_buffer.finish();
+    }
+
+    // what each pass of the graph draws, the graph itself takes care of clearing
+    unsafe fn record_pass(&self, pass: PassId, frame: usize, cmd_buffer: &mut B::CommandBuffer) {
+        if pass == self.ui.pass {
+            self.record_ui_pass(frame, cmd_buffer);
+            return;
+        }
+
+        // nothing to draw yet
+        if self.batches.batches.is_empty() {
+            return;
+        }
+
+        if pass == self.main_pass {
+            self.record_main_pass(cmd_buffer);
+        } else if let Some(shadow_map) = self.shadow_maps.passes.iter().position(|p| *p == pass) {
+            if shadow_map < self.shadow_maps.in_use {
+                self.record_shadow_pass(shadow_map, cmd_buffer);
+            }
+        }
+    }
+
+    unsafe fn record_shadow_pass(&self, shadow_map: usize, cmd_buffer: &mut B::CommandBuffer) {
+        let shadow_layout = self.shadow_maps.pipeline.pipeline_layout.as_ref().unwrap();
+
+        cmd_buffer.bind_graphics_pipeline(&self.shadow_maps.pipeline.pipeline.as_ref().unwrap());
+        bind_geometry(cmd_buffer, self.vertex_buffer.as_ref().unwrap(), self.index_buffer.as_ref().unwrap());
+
+        cmd_buffer.bind_graphics_descriptor_sets(
+            shadow_layout,
+            0,
+            vec![ &self.lights_uniform.desc.as_ref().unwrap().descriptor_set ],
+            &[],
+        );
+
+        cmd_buffer.push_graphics_constants(
+            shadow_layout,
+            hal::pso::ShaderStageFlags::VERTEX,
+            0,
+            &[shadow_map as u32],
+        );
+
+        draw_batches(
+            cmd_buffer,
+            &self.batches,
+            self.instance_buffer.as_ref().unwrap(),
+            self.indirect_buffer.as_ref().unwrap(),
+            |_, _| true,
+        );
+    }
+
+    // the index count comes from the frame's indirect buffer, so new text only has to be uploaded
+    unsafe fn record_ui_pass(&self, frame: usize, cmd_buffer: &mut B::CommandBuffer) {
+        let ui_frame = &self.ui.frames[frame];
+        let (vertex_buffer, index_buffer, indirect_buffer) = match (&ui_frame.vertex_buffer, &ui_frame.index_buffer, &ui_frame.indirect_buffer) {
+            (Some(vertex_buffer), Some(index_buffer), Some(indirect_buffer)) => (vertex_buffer, index_buffer, indirect_buffer),
+            _ => return,
+        };
 
-            cmd_buffer.begin_render_pass(
-                self.render_pass.render_pass.as_ref().unwrap(),
-                &framebuffer,
-                self.viewport.rect,
-                &[
-                    hal::command::ClearValue { color: hal::command::ClearColor { float32: [0.7, 0.2, 0.0, 1.0] } },
-                    hal::command::ClearValue { depth_stencil: hal::command::ClearDepthStencil {depth: 1.0, stencil: 0} }
-                ],
-                hal::command::SubpassContents::Inline
+        cmd_buffer.bind_graphics_pipeline(&self.ui.pipeline.pipeline.as_ref().unwrap());
+        bind_geometry(cmd_buffer, vertex_buffer, index_buffer);
+
+        cmd_buffer.bind_graphics_descriptor_sets(
+            self.ui.pipeline.pipeline_layout.as_ref().unwrap(),
+            0,
+            vec![ &ui_frame.desc_set.descriptor_set ],
+            &[],
+        );
+
+        cmd_buffer.draw_indexed_indirect(
+            indirect_buffer.get_buffer(),
+            0,
+            1,
+            std::mem::size_of::<DrawIndexedIndirectCommand>() as u32,
+        );
+    }
+
+    // Brings a frame's overlay buffers and atlas texture up to date with the latest `update_ui`. Only
+    // called once the frame's fence has signalled, so nothing the gpu still uses gets touched. Returns true
+    // if the frame has to be re-recorded.
+    unsafe fn sync_ui_frame(&mut self, frame: usize) -> bool {
+        let vertex_alignment = self.core.read().unwrap().backend.adapter.limits.min_vertex_input_binding_stride_alignment;
+        let ui = &mut self.ui;
+        let mut needs_rerecord = false;
+
+        if ui.frames[frame].geometry != Some(ui.geometry) {
+            let ui_frame = &mut ui.frames[frame];
+            let command = [DrawIndexedIndirectCommand {
+                index_count: ui.indices.len() as u32,
+                instance_count: 1,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            }];
+
+            needs_rerecord |= Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut ui_frame.vertex_buffer,
+                &ui.vertices,
+                0..ui.vertices.len(),
+                vertex_alignment,
+                hal::buffer::Usage::VERTEX,
             );
 
-            let mut current_mesh_index = 0;
+            needs_rerecord |= Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut ui_frame.index_buffer,
+                &ui.indices,
+                0..ui.indices.len(),
+                1,
+                hal::buffer::Usage::INDEX,
+            );
 
-            for (maybe_texture, meshes) in meshes_by_texture.iter() {
-                let texture_key = RenderKey::from(maybe_texture);
-                let texture_image = self.textures.get(&texture_key).unwrap();
+            needs_rerecord |= Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut ui_frame.indirect_buffer,
+                &command,
+                0..1,
+                4,
+                hal::buffer::Usage::INDIRECT,
+            );
 
-                cmd_buffer.bind_graphics_descriptor_sets(
-                    &self.pipeline.pipeline_layout.as_ref().unwrap(),
-                    2,
-                    vec![ &texture_image.desc_set.descriptor_set ],
-                    &[],
-                );
+            ui_frame.geometry = Some(ui.geometry);
+        }
 
-                for (i, mesh) in meshes.iter().enumerate() {
-                    if !mesh.rendered {
-                        continue;
-                    }
+        if ui.frames[frame].texture != ui.latest_texture() {
+            ui.point_at_latest_texture(frame);
+            ui.drop_unused_textures();
+            needs_rerecord = true;
+        }
+
+        needs_rerecord
+    }
+
+    unsafe fn record_main_pass(&self, cmd_buffer: &mut B::CommandBuffer) {
+        let pipeline_layout = self.pipeline.pipeline_layout.as_ref().unwrap();
 
-                    let dynamic_offset = i as u64 * self.object_uniform.buffer.as_ref().unwrap().padded_stride;
+        cmd_buffer.bind_graphics_pipeline(&self.pipeline.pipeline.as_ref().unwrap());
+        bind_geometry(cmd_buffer, self.vertex_buffer.as_ref().unwrap(), self.index_buffer.as_ref().unwrap());
+
+        cmd_buffer.bind_graphics_descriptor_sets(
+            pipeline_layout,
+            0,
+            vec![ &self.camera_uniform.desc.as_ref().unwrap().descriptor_set ],
+            &[],
+        );
+
+        cmd_buffer.bind_graphics_descriptor_sets(
+            pipeline_layout,
+            2,
+            vec![
+                &self.lights_uniform.desc.as_ref().unwrap().descriptor_set,
+                &self.shadow_maps.desc_set.descriptor_set,
+            ],
+            &[],
+        );
+
+        let mut bound_material = None;
+
+        draw_batches(
+            cmd_buffer,
+            &self.batches,
+            self.instance_buffer.as_ref().unwrap(),
+            self.indirect_buffer.as_ref().unwrap(),
+            |cmd_buffer, batch| {
+                let material_key = RenderKey::from(&batch.material);
+                if bound_material.as_ref() != Some(&material_key) {
+                    let material_set = match self.materials.get(&material_key) {
+                        Some(material_set) => material_set,
+                        None => return false,
+                    };
 
                     cmd_buffer.bind_graphics_descriptor_sets(
-                        &self.pipeline.pipeline_layout.as_ref().unwrap(),
-                        0,
-                        vec![
-                            &self.camera_uniform.desc.as_ref().unwrap().descriptor_set,
-                            &self.object_uniform.desc.as_ref().unwrap().descriptor_set,
-                        ],
-                        &[dynamic_offset as u32],
+                        pipeline_layout,
+                        1,
+                        vec![ &material_set.descriptor_set ],
+                        &[],
                     );
 
-                    let num_indices = mesh.indices.len() as u32;
-
-                    cmd_buffer.draw_indexed(current_mesh_index..(current_mesh_index + num_indices), 0, 0..1);
-                    current_mesh_index += num_indices;
+                    bound_material = Some(material_key);
                 }
-            }
-
-            cmd_buffer.end_render_pass();
-            cmd_buffer.finish();
 
-            command_buffers.push(cmd_buffer);
-        }
+                cmd_buffer.push_graphics_constants(
+                    pipeline_layout,
+                    hal::pso::ShaderStageFlags::FRAGMENT,
+                    0,
+                    &MaterialConstants::new(&batch.material).words(),
+                );
 
-        self.framebuffers.command_buffers = Some(command_buffers);
+                true
+            },
+        );
     }
 }
 
 impl <B: hal::Backend, A: Allocator<B>> Drop for GfxDrawer<B, A> {
     fn drop(&mut self) {
-        let mut desc_set_layout_writable = self.texture_desc_set_layout.write().unwrap();
+        let mut desc_set_layout_writable = self.material_desc_set_layout.write().unwrap();
         let vertex_buffer = self.vertex_buffer.take();
-        let index_buffer = self.vertex_buffer.take();
+        let index_buffer = self.index_buffer.take();
+        let instance_buffer = self.instance_buffer.take();
+        let indirect_buffer = self.indirect_buffer.take();
         let camera_uniform = &mut self.camera_uniform;
-        let object_uniform = &mut self.object_uniform;
+        let lights_uniform = &mut self.lights_uniform;
         let textures = self.textures.values_mut();
         run_with_device(&self.core, |device| {
             desc_set_layout_writable.deref_mut().drop(device);
@@ -378,8 +713,18 @@ impl <B: hal::Backend, A: Allocator<B>> Drop for GfxDrawer<B, A> {
                 None => (),
             }
 
+            match instance_buffer {
+                Some(mut ib) => ib.drop(device),
+                None => (),
+            }
+
+            match indirect_buffer {
+                Some(mut ib) => ib.drop(device),
+                None => (),
+            }
+
             camera_uniform.drop(device);
-            object_uniform.drop(device);
+            lights_uniform.drop(device);
             for texture in textures {
                 texture.drop(device);
             }
@@ -387,6 +732,92 @@ impl <B: hal::Backend, A: Allocator<B>> Drop for GfxDrawer<B, A> {
     }
 }
 
+// binds the geometry every batch draws from
+unsafe fn bind_geometry<B: hal::Backend>(cmd_buffer: &mut B::CommandBuffer, vertex_buffer: &Buffer<B>, index_buffer: &Buffer<B>) {
+    cmd_buffer.bind_vertex_buffers(0, vec![
+        (vertex_buffer.get_buffer(), hal::buffer::SubRange {
+            offset: 0,
+            size: None
+        }),
+    ]);
+    cmd_buffer.bind_index_buffer(hal::buffer::IndexBufferView {
+        buffer: index_buffer.get_buffer(),
+        range: hal::buffer::SubRange {
+            offset: 0,
+            size: None,
+        },
+        index_type: hal::IndexType::U32
+    });
+}
+
+// One indirect draw per batch, `bind_batch` binds whatever else the pass needs to change between them.
+// Instance counts live in the indirect buffer, so adding or removing entities within a batch's capacity
+// doesn't need a re-record.
+unsafe fn draw_batches<B, F>(cmd_buffer: &mut B::CommandBuffer,
+                             batches: &DrawableBatches,
+                             instance_buffer: &Buffer<B>,
+                             indirect_buffer: &Buffer<B>,
+                             mut bind_batch: F)
+    where B: hal::Backend,
+          F: FnMut(&mut B::CommandBuffer, &DrawBatch) -> bool
+{
+    let instance_stride = std::mem::size_of::<ObjectUniformBufferObject>() as u64;
+    let indirect_stride = std::mem::size_of::<DrawIndexedIndirectCommand>() as u32;
+
+    for (batch_index, batch) in batches.batches.iter().enumerate() {
+        // false when the batch can't be drawn this frame
+        if !bind_batch(cmd_buffer, batch) {
+            continue;
+        }
+
+        cmd_buffer.bind_vertex_buffers(1, vec![
+            (instance_buffer.get_buffer(), hal::buffer::SubRange {
+                offset: batch.first_instance as u64 * instance_stride,
+                size: None
+            }),
+        ]);
+
+        cmd_buffer.draw_indexed_indirect(
+            indirect_buffer.get_buffer(),
+            batch_index as u64 * indirect_stride as u64,
+            1,
+            indirect_stride,
+        );
+    }
+}
+
+// The maps a material draws with, with 1x1 stand-ins for the ones it doesn't have. White leaves the
+// factors as they are, and the normal stand-in points straight out of the surface.
+fn material_textures(material: &Material) -> Vec<Texture> {
+    material
+        .textures()
+        .iter()
+        .enumerate()
+        .map(|(slot, texture)| match texture {
+            Some(texture) => (*texture).clone(),
+            None => default_texture(slot),
+        })
+        .collect()
+}
+
+fn default_texture(slot: usize) -> Texture {
+    let (name, pixel, color_space) = match slot {
+        0 | 4 => ("default#white_srgb", [255, 255, 255, 255], ColorSpace::Srgb),
+        2 => ("default#flat_normal", [128, 128, 255, 255], ColorSpace::Linear),
+        _ => ("default#white_linear", [255, 255, 255, 255], ColorSpace::Linear),
+    };
+
+    Texture::from_pixels(name, TexturePixels {
+        width: 1,
+        height: 1,
+        data: pixel.to_vec(),
+    }).with_sampler(SamplerOptions {
+        color_space,
+        mipmaps: false,
+        ..SamplerOptions::default()
+    })
+}
+
 pub fn fps_view_matrix(eye: Vector3<f32>, pitch_rad: cgmath::Rad<f32>, yaw_rad: cgmath::Rad<f32>) -> Matrix4<f32> {
     let cos_pitch = pitch_rad.cos();
     let sin_pitch = pitch_rad.sin();
@@ -411,7 +842,7 @@ pub fn fps_view_matrix(eye: Vector3<f32>, pitch_rad: cgmath::Rad<f32>, yaw_rad:
 impl <B: hal::Backend> Drawer<B> for GfxDrawer<B, GfxAllocator<B>> {
     fn draw(&mut self, image_index: usize, acquire_semaphore: Option<&B::Semaphore>, present_semaphore: Option<&B::Semaphore>) {
         unsafe {
-            let (framebuffer_fence, command_buffer) = self.framebuffers.get_frame_data(Some(image_index)).unwrap();
+            let (framebuffer_fence, _) = self.frames.get_frame_data(Some(image_index)).unwrap();
 
             run_with_device(&self.core, |device| {
                 device
@@ -423,6 +854,12 @@ impl <B: hal::Backend> Drawer<B> for GfxDrawer<B, GfxAllocator<B>> {
                     .unwrap();
             });
 
+            if self.sync_ui_frame(image_index) {
+                self.regenerate_cmd_buffer(image_index);
+            }
+
+            let (framebuffer_fence, command_buffer) = self.frames.get_frame_data(Some(image_index)).unwrap();
+
             match (acquire_semaphore, present_semaphore) {
                 (None, None) => {
                     self
@@ -455,138 +892,216 @@ impl <B: hal::Backend> Drawer<B> for GfxDrawer<B, GfxAllocator<B>> {
         }
     }
 
+    // Only the parts of the gpu buffers touched by the sync are uploaded, and the command buffers are only
+    // re-recorded when batches are added, move, or a buffer had to be reallocated.
     fn update_drawables(&mut self, drawables: Vec<Drawable>) -> Result<(), String> {
         unsafe {
-            let uniforms = drawables
+            let materials = drawables
+                .iter()
+                .map(|d| d.effective_material())
+                .unique()
+                .collect::<Vec<Material>>();
+            // the defaults are always there to fall back on
+            let textures = materials
                 .iter()
-                .map(|d| d.transform.to_ubo())
-                .collect::<Vec<ObjectUniformBufferObject>>();
+                .flat_map(material_textures)
+                .chain((0..MATERIAL_TEXTURE_COUNT).map(default_texture))
+                .collect::<Vec<Texture>>();
 
-            self.update_uniforms(uniforms);
+            self.generate_images(textures.iter().collect());
+            self.generate_material_sets(&materials);
 
-            self.generate_images(
-                drawables
-                    .iter()
-                    .filter(|d| d.texture.is_some())
-                    .map(|d| d.texture.as_ref().unwrap())
-                    .collect());
+            let changes = self.batches.sync(&drawables);
+            let mut needs_rerecord = changes.needs_rerecord || self.frames.command_buffers.is_none();
 
-            self.generate_vertex_and_index_buffers(
-                drawables
-                    .iter()
-                    .map(|d| &d.mesh)
-                    .collect::<Vec<&Mesh>>()
+            if changes.is_empty() && !needs_rerecord {
+                return Ok(());
+            }
+
+            let vertex_alignment = self.core.read().unwrap().backend.adapter.limits.min_vertex_input_binding_stride_alignment;
+
+            needs_rerecord |= Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut self.vertex_buffer,
+                &self.batches.vertices,
+                changes.new_vertices.clone().unwrap_or(0..0),
+                vertex_alignment,
+                hal::buffer::Usage::VERTEX,
+            );
+
+            needs_rerecord |= Self::upload_buffer(
+                &self.core,
+                &self.allocator,
+                &mut self.index_buffer,
+                &self.batches.indices,
+                changes.new_indices.clone().unwrap_or(0..0),
+                1,
+                hal::buffer::Usage::INDEX,
             );
 
-            let meshes_by_texture = drawables
+            let indirect_commands = self.batches.indirect_commands();
+            for batch_index in changes.changed_batches.iter() {
+                needs_rerecord |= Self::upload_buffer(
+                    &self.core,
+                    &self.allocator,
+                    &mut self.indirect_buffer,
+                    &indirect_commands,
+                    *batch_index..(batch_index + 1),
+                    4,
+                    hal::buffer::Usage::INDIRECT,
+                );
+            }
+
+            let uniforms = drawables
                 .iter()
-                .fold(BTreeMap::<Option<crate::components::texture::Texture>, Vec<&Mesh>>::new(), |mut map, drawable| {
-                    let meshes_by_texture = map.entry(drawable.texture.clone()).or_insert(Vec::new());
-                    meshes_by_texture.push(&drawable.mesh);
-                    map
-                });
+                .map(|d| (d.entity, d.transform.to_ubo()))
+                .collect::<Vec<(Entity, ObjectUniformBufferObject)>>();
+
+            needs_rerecord |= self.upload_instances(&uniforms);
 
-            self.generate_cmd_buffers(meshes_by_texture);
-            self.last_drawables = Some(drawables);
+            if needs_rerecord {
+                self.generate_cmd_buffers();
+            }
 
             Ok(())
         }
     }
 
-    fn update_uniforms(&mut self, uniforms: Vec<ObjectUniformBufferObject>) -> Result<(), String> {
+    // uniforms are matched to their instance slot by entity, so this never needs a re-record
+    fn update_uniforms(&mut self, uniforms: Vec<(Entity, ObjectUniformBufferObject)>) -> Result<(), String> {
+        unsafe {
+            if self.upload_instances(&uniforms) {
+                self.generate_cmd_buffers();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_camera(&mut self, camera: Camera, transform: Transform, global: GlobalTransform) -> Result<(), String> {
+        if camera.reversed_z != self.reversed_z {
+            unsafe {
+                self.set_reversed_z(camera.reversed_z);
+            }
+        }
+
+        let dims = [self.viewport.rect.w as f32, self.viewport.rect.h as f32];
+        let new_ubo = self.update_camera_uniform_buffer_object(dims, &camera, &transform, &global);
         self
-            .object_uniform
+            .camera_uniform
             .buffer
             .as_mut()
             .unwrap()
-            .update_data(&self.core, 0, &uniforms);
+            .update_data(&self.core, 0, &[new_ubo]);
 
         Ok(())
     }
 
-    fn update_camera(&mut self, transform: Transform) -> Result<(), String> {
-        let dims = [DIMS.width as f32, DIMS.height as f32];
-        let new_ubo = self.update_camera_uniform_buffer_object(dims, &transform);
+    fn update_lights(&mut self, lights: LightsUniformBufferObject) -> Result<(), String> {
         self
-            .camera_uniform
+            .lights_uniform
             .buffer
             .as_mut()
             .unwrap()
-            .update_data(&self.core, 0, &[new_ubo]);
+            .update_data(&self.core, 0, &[lights]);
+
+        // the shadow passes are part of the command buffers, so they only change when the number of
+        // shadow casting lights does
+        if lights.shadow_map_count() != self.shadow_maps.in_use {
+            self.shadow_maps.in_use = lights.shadow_map_count();
+            unsafe {
+                self.generate_cmd_buffers();
+            }
+        }
 
         Ok(())
     }
-}
 
-struct RenderPass<B: hal::Backend> {
-    core: Arc<RwLock<RendererCore<B>>>,
-    render_pass: Option<B::RenderPass>,
-}
+    // Quads come in already laid out, text is laid out here. Both are in pixels and get moved into clip
+    // space with the current viewport. Quads go first so text ends up on top of them. Nothing is uploaded
+    // here, each frame picks the new geometry and atlas up the next time it's drawn.
+    fn update_ui(&mut self, quads: Vec<Quad>, texts: Vec<Text>) -> Result<(), String> {
+        let labels = quads.iter().flat_map(|quad| quad.texts()).collect::<Vec<Text>>();
+        let atlas = &mut self.ui.atlas;
+        let placed = texts
+            .iter()
+            .chain(labels.iter())
+            .filter(|text| text.rendered)
+            .map(|text| atlas.layout(text))
+            .collect::<Result<Vec<Vec<PlacedGlyph>>, String>>()?;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let white = self.ui.atlas.white_tex_coord();
+        let screen = (self.viewport.rect.w as u32, self.viewport.rect.h as u32);
+
+        for quad in quads.iter() {
+            let (quad_vertices, quad_indices) = quad.geometry();
+            let base_vertex = vertices.len() as u32;
+
+            indices.extend(quad_indices.iter().map(|index| base_vertex + index));
+            vertices.extend(quad_vertices.into_iter().map(|mut vertex| {
+                vertex.in_tex_coord = white;
+                vertex.normalize(screen.0, screen.1);
+                vertex
+            }));
+        }
 
-impl<B: hal::Backend> RenderPass<B> {
-    fn new(core: &Arc<RwLock<RendererCore<B>>>, swapchain_format: hal::format::Format) -> Self {
-        run_with_device(core, |device| {
-            let color_attachment = hal::pass::Attachment {
-                format: Some(swapchain_format),
-                samples: 1,
-                ops: hal::pass::AttachmentOps::new(
-                    hal::pass::AttachmentLoadOp::Clear,
-                    hal::pass::AttachmentStoreOp::Store,
-                ),
-                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                layouts: hal::image::Layout::Undefined..hal::image::Layout::Present,
-            };
+        for glyphs in placed.iter() {
+            self.ui.atlas.append_geometry(glyphs, screen, &mut vertices, &mut indices);
+        }
 
-            let depth_format = hal::format::Format::D32SfloatS8Uint;
-            let depth_attachment = hal::pass::Attachment {
-                format: Some(depth_format),
-                samples: 1,
-                ops: hal::pass::AttachmentOps::new(
-                    hal::pass::AttachmentLoadOp::Clear,
-                    hal::pass::AttachmentStoreOp::DontCare,
-                ),
-                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                layouts: hal::image::Layout::Undefined..hal::image::Layout::DepthStencilAttachmentOptimal,
-            };
+        if self.ui.atlas.take_dirty() {
+            let texture = self.allocator.write().unwrap().alloc_texture(
+                hal::buffer::Usage::TRANSFER_SRC,
+                &self.ui.atlas.texture(),
+            )?;
+            self.ui.add_texture(texture);
+        }
 
-            let subpass = hal::pass::SubpassDesc {
-                colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: Some(&(1, hal::image::Layout::DepthStencilAttachmentOptimal)),
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
+        self.ui.set_geometry(vertices, indices);
 
-            let dependency = hal::pass::SubpassDependency {
-                passes: None..Some(0),
-                stages: hal::pso::PipelineStage::COLOR_ATTACHMENT_OUTPUT..hal::pso::PipelineStage::COLOR_ATTACHMENT_OUTPUT,
-                accesses: hal::image::Access::empty()..(hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE),
-                flags: hal::memory::Dependencies::empty(),
-            };
+        Ok(())
+    }
 
-            let render_pass = unsafe {
-                device.create_render_pass(&[color_attachment, depth_attachment], &[subpass], &[dependency])
-            }.expect("Can't create render pass");
+    // The render passes and every pipeline drawing into them are built for the format the swapchain
+    // started out with, so a surface that switches formats can't be drawn to anymore.
+    fn rebuild_framebuffers(&mut self, viewport: Viewport, images: Vec<&B::Image>, image_format: hal::format::Format) -> Result<(), String> {
+        let backbuffer_format = self.graph.backbuffer_format();
+        if image_format != backbuffer_format {
+            return Err(format!("the swapchain format changed from {:?} to {:?}, the renderer has to be restarted to use it", backbuffer_format, image_format));
+        }
 
-            Self {
-                core: Arc::clone(core),
-                render_pass: Some(render_pass),
-            }
-        })
-    }
-}
+        run_with_device(&self.core, |device| device.wait_idle())
+            .map_err(|e| e.to_string())?;
 
-impl<B: hal::Backend> Drop for RenderPass<B> {
-    fn drop(&mut self) {
-        let device_lock = &self.core.read().unwrap().device.device;
-        let device = device_lock.write().unwrap();
+        let frame_count = std::cmp::max(images.len(), 1);
+        self.graph.resize(&self.allocator, &viewport, images);
+        self.frames = unsafe { Frames::new(&self.core, frame_count) };
+        self.ui.set_frame_count(&self.allocator, frame_count);
+        self.viewport = viewport;
 
+        // command buffers are recorded against a specific set of framebuffers
         unsafe {
-            device.destroy_render_pass(self.render_pass.take().unwrap());
+            self.generate_cmd_buffers();
         }
+
+        Ok(())
     }
 }
 
+// what a pipeline draws, which decides how its vertices come in and whether it depth tests
+#[derive(Clone, Copy, PartialEq)]
+enum PipelineKind {
+    // instanced geometry tested against the scene's depth
+    Scene { reversed_z: bool },
+    // instanced geometry that only writes depth
+    Shadow,
+    // screen space geometry drawn over everything else
+    Overlay,
+}
+
 struct Pipeline<B: hal::Backend> {
     core: Arc<RwLock<RendererCore<B>>>,
     pipeline: Option<B::GraphicsPipeline>,
@@ -598,14 +1113,16 @@ impl<B: hal::Backend> Pipeline<B> {
         core: &Arc<RwLock<RendererCore<B>>>,
         render_pass: &B::RenderPass,
         descriptor_set_layouts: Vec<&B::DescriptorSetLayout>,
+        push_constants: &[(hal::pso::ShaderStageFlags, Range<u32>)],
         vertex_shader: &str,
-        fragment_shader: &str
+        fragment_shader: Option<&str>,
+        kind: PipelineKind,
     ) -> Self {
         let pipeline_layout = run_with_device(&core, |device| {
             device
                 .create_pipeline_layout(
                     descriptor_set_layouts,
-                    &[(hal::pso::ShaderStageFlags::VERTEX, 0..8)],
+                    push_constants,
                 )
                 .expect("Can't create pipeline layout")
         });
@@ -620,7 +1137,7 @@ impl<B: hal::Backend> Pipeline<B> {
         };
 
         let vs_module = load_shader(vertex_shader, glsl_to_spirv::ShaderType::Vertex);
-        let fs_module = load_shader(fragment_shader, glsl_to_spirv::ShaderType::Fragment);
+        let fs_module = fragment_shader.map(|fragment_shader| load_shader(fragment_shader, glsl_to_spirv::ShaderType::Fragment));
 
         let pipeline = {
             let (vs_entry, fs_entry) = (
@@ -629,11 +1146,11 @@ impl<B: hal::Backend> Pipeline<B> {
                     module: &vs_module,
                     specialization: hal::pso::Specialization::default(),
                 },
-                hal::pso::EntryPoint {
+                fs_module.as_ref().map(|fs_module| hal::pso::EntryPoint {
                     entry: "main",
-                    module: &fs_module,
+                    module: fs_module,
                     specialization: hal::pso::Specialization::default(),
-                }
+                })
             );
 
             let shader_entries = hal::pso::GraphicsShaderSet {
@@ -641,7 +1158,7 @@ impl<B: hal::Backend> Pipeline<B> {
                 hull: None,
                 domain: None,
                 geometry: None,
-                fragment: Some(fs_entry),
+                fragment: fs_entry,
             };
 
             let subpass = hal::pass::Subpass {
@@ -657,10 +1174,21 @@ impl<B: hal::Backend> Pipeline<B> {
                 subpass,
             );
 
-            pipeline_desc.blender.targets.push(hal::pso::ColorBlendDesc {
-                mask: hal::pso::ColorMask::ALL,
-                blend: Some(hal::pso::BlendState::ALPHA),
-            });
+            if fs_module.is_some() {
+                pipeline_desc.blender.targets.push(hal::pso::ColorBlendDesc {
+                    mask: hal::pso::ColorMask::ALL,
+                    blend: Some(hal::pso::BlendState::ALPHA),
+                });
+            }
+
+            // the bias keeps surfaces from shadowing themselves
+            if kind == PipelineKind::Shadow {
+                pipeline_desc.rasterizer.depth_bias = Some(hal::pso::State::Static(hal::pso::DepthBias {
+                    const_factor: 1.25,
+                    clamp: 0.0,
+                    slope_factor: 1.75,
+                }));
+            }
 
             pipeline_desc.vertex_buffers.push(hal::pso::VertexBufferDesc {
                 binding: 0,
@@ -695,14 +1223,50 @@ impl<B: hal::Backend> Pipeline<B> {
                 },
             });
 
-            pipeline_desc.depth_stencil = hal::pso::DepthStencilDesc {
-                depth: Some(hal::pso::DepthTest {
-                    fun: hal::pso::Comparison::Less,
-                    write: true
-                }),
-                depth_bounds: false,
-                stencil: None,
-            };
+            pipeline_desc.attributes.push(hal::pso::AttributeDesc {
+                location: 3,
+                binding: 0,
+                element: hal::pso::Element {
+                    format: hal::format::Format::Rgb32Sfloat,
+                    offset: 32,
+                },
+            });
+
+            // the overlay has neither instances nor a depth attachment
+            if kind != PipelineKind::Overlay {
+                // per instance model matrix, a mat4 takes up one location per column
+                pipeline_desc.vertex_buffers.push(hal::pso::VertexBufferDesc {
+                    binding: 1,
+                    stride: std::mem::size_of::<ObjectUniformBufferObject>() as u32,
+                    rate: hal::pso::VertexInputRate::Instance(1),
+                });
+
+                for column in 0..4 {
+                    pipeline_desc.attributes.push(hal::pso::AttributeDesc {
+                        location: 4 + column,
+                        binding: 1,
+                        element: hal::pso::Element {
+                            format: hal::format::Format::Rgba32Sfloat,
+                            offset: column * 16,
+                        },
+                    });
+                }
+
+                // with reversed z closer means bigger
+                let depth_test = match kind {
+                    PipelineKind::Scene { reversed_z: true } => hal::pso::Comparison::Greater,
+                    _ => hal::pso::Comparison::Less,
+                };
+
+                pipeline_desc.depth_stencil = hal::pso::DepthStencilDesc {
+                    depth: Some(hal::pso::DepthTest {
+                        fun: depth_test,
+                        write: true
+                    }),
+                    depth_bounds: false,
+                    stencil: None,
+                };
+            }
 
             let pipeline = run_with_device(&core, |device| {
                 device.create_graphics_pipeline(&pipeline_desc, None)
@@ -710,7 +1274,9 @@ impl<B: hal::Backend> Pipeline<B> {
 
             run_with_device(&core, |device| {
                 device.destroy_shader_module(vs_module);
-                device.destroy_shader_module(fs_module);
+                if let Some(fs_module) = fs_module {
+                    device.destroy_shader_module(fs_module);
+                }
             });
 
             pipeline
@@ -735,75 +1301,22 @@ impl<B: hal::Backend> Drop for Pipeline<B> {
     }
 }
 
-struct Framebuffers<B: hal::Backend> {
+// The per swapchain image half of a frame, the fences and command buffers that record the render graph
+// into that image's framebuffers.
+struct Frames<B: hal::Backend> {
     core: Arc<RwLock<RendererCore<B>>>,
-    framebuffers: Option<Vec<B::Framebuffer>>,
-    framebuffer_fences: Option<Vec<B::Fence>>,
+    fences: Option<Vec<B::Fence>>,
     command_pools: Option<Vec<B::CommandPool>>,
     command_buffers: Option<Vec<B::CommandBuffer>>,
-    frame_images: Option<Vec<(B::Image, B::ImageView)>>,
-    depth_image: Image<B>,
 }
 
-impl<B: hal::Backend> Framebuffers<B> {
-    unsafe fn new(
-        core: &Arc<RwLock<RendererCore<B>>>,
-        extent: hal::image::Extent,
-        images: Vec<B::Image>,
-        image_format: hal::format::Format,
-        render_pass: &RenderPass<B>,
-        depth_image: Image<B>
-    ) -> Self
-    {
-        let (frame_images, framebuffers) = {
-            let pairs = images
-                .into_iter()
-                .map(|image| {
-                    let image_view = run_with_device(core, |device| {
-                        device
-                            .create_image_view(
-                                &image,
-                                hal::image::ViewKind::D2,
-                                image_format,
-                                hal::format::Swizzle::NO,
-                                COLOR_RANGE.clone(),
-                            )
-                            .unwrap()
-                    });
-
-                    (image, image_view)
-                })
-                .collect::<Vec<_>>();
-
-            let fbos = pairs
-                .iter()
-                .map(|&(_, ref image_view)| {
-                    run_with_device(core, |device| {
-                        device
-                            .create_framebuffer(
-                                render_pass.render_pass.as_ref().unwrap(),
-                                vec![image_view, depth_image.image_view.as_ref().unwrap()],
-                                extent,
-                            )
-                            .unwrap()
-                    })
-                })
-                .collect();
-
-            (pairs, fbos)
-        };
-
-        let iter_count = if frame_images.len() != 0 {
-            frame_images.len()
-        } else {
-            1 // GL can have zero
-        };
-
+impl<B: hal::Backend> Frames<B> {
+    unsafe fn new(core: &Arc<RwLock<RendererCore<B>>>, frame_count: usize) -> Self {
         let mut fences: Vec<B::Fence> = vec![];
         let mut command_pools: Vec<B::CommandPool> = vec![];
 
         run_with_device(core, |device| {
-            for _ in 0..iter_count {
+            for _ in 0..frame_count {
                 fences.push(device.create_fence(true).unwrap());
                 command_pools.push(device
                                        .create_command_pool(
@@ -817,12 +1330,9 @@ impl<B: hal::Backend> Framebuffers<B> {
 
         Self {
             core: Arc::clone(core),
-            frame_images: Some(frame_images),
-            framebuffers: Some(framebuffers),
-            framebuffer_fences: Some(fences),
+            fences: Some(fences),
             command_pools: Some(command_pools),
             command_buffers: None,
-            depth_image,
         }
     }
 
@@ -836,7 +1346,7 @@ impl<B: hal::Backend> Framebuffers<B> {
     {
         if let Some(fid) = frame_id {
             Some((
-                &mut self.framebuffer_fences.as_mut().unwrap()[fid],
+                &mut self.fences.as_mut().unwrap()[fid],
                 &mut self.command_buffers.as_mut().unwrap()[fid]
             ))
         } else {
@@ -845,13 +1355,13 @@ impl<B: hal::Backend> Framebuffers<B> {
     }
 }
 
-impl<B: hal::Backend> Drop for Framebuffers<B> {
+impl<B: hal::Backend> Drop for Frames<B> {
     fn drop(&mut self) {
         let device_lock = &mut self.core.write().unwrap().device.device;
-        let mut device = device_lock.write().unwrap();
+        let device = device_lock.write().unwrap();
 
         unsafe {
-            for fence in self.framebuffer_fences.take().unwrap() {
+            for fence in self.fences.take().unwrap() {
                 device.wait_for_fence(&fence, !0).unwrap();
                 device.destroy_fence(fence);
             }
@@ -859,17 +1369,337 @@ impl<B: hal::Backend> Drop for Framebuffers<B> {
             for command_pool in self.command_pools.take().unwrap() {
                 device.destroy_command_pool(command_pool);
             }
+        }
+    }
+}
+
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_MAP_FORMAT: hal::format::Format = hal::format::Format::D32Sfloat;
+
+// Depth maps rendered from the point of view of the shadow casting lights before the main pass, one
+// graph pass each. There are always MAX_SHADOW_MAPS of them so nothing has to be rebuilt when lights
+// come and go, the ones that aren't in use are only cleared.
+struct ShadowMaps<B: hal::Backend> {
+    core: Arc<RwLock<RendererCore<B>>>,
+    passes: Vec<PassId>,
+    pipeline: Pipeline<B>,
+    sampler: Option<B::Sampler>,
+    desc_set_layout: Arc<RwLock<DescSetLayout<B>>>,
+    desc_set: DescSet<B>,
+
+    // how many maps the current lights render into, the command buffers are recorded for this many
+    in_use: usize,
+}
+
+impl<B: hal::Backend> ShadowMaps<B> {
+    // the shadow passes read the light matrices out of the same uniform the main pass lights with
+    fn new(core: &Arc<RwLock<RendererCore<B>>>,
+           allocator: &Arc<RwLock<GfxAllocator<B>>>,
+           graph: &RenderGraph<B>,
+           passes: Vec<PassId>,
+           images: &[ResourceId],
+           lights_layout: &B::DescriptorSetLayout) -> Self {
+        // every shadow pass has the same attachment, so one pipeline works for all of them
+        let pipeline = unsafe {
+            Pipeline::new(
+                core,
+                graph.render_pass(passes[0]),
+                vec![lights_layout],
+                &[(hal::pso::ShaderStageFlags::VERTEX, 0..4)],
+                "shaders/shadow.vert",
+                None,
+                PipelineKind::Shadow,
+            )
+        };
 
-            for framebuffer in self.framebuffers.take().unwrap() {
-                device.destroy_framebuffer(framebuffer);
+        // linear filtering on a comparison sampler already blends the 4 nearest texels
+        let mut sampler_desc = hal::image::SamplerDesc::new(hal::image::Filter::Linear, hal::image::WrapMode::Clamp);
+        sampler_desc.comparison = Some(hal::pso::Comparison::LessEqual);
+
+        let sampler = run_with_device(core, |device| unsafe {
+            device.create_sampler(&sampler_desc).expect("Can't create shadow sampler")
+        });
+
+        let desc_set_layout = Arc::new(RwLock::new(allocator.write().unwrap().alloc_desc_set_layout(
+            &[hal::pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: hal::pso::DescriptorType::Image {
+                    ty: hal::pso::ImageDescriptorType::Sampled {
+                        with_sampler: true,
+                    }
+                },
+                count: MAX_SHADOW_MAPS,
+                stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }])));
+
+        let desc_set = allocator
+            .write()
+            .unwrap()
+            .alloc_desc_set(DescriptorPoolType::Texture, &desc_set_layout)
+            .expect("Can't allocate shadow map descriptor set");
+
+        // the maps have a fixed size, so the graph never recreates them and these stay valid
+        run_with_device(core, |device| {
+            desc_set.write(
+                device,
+                images
+                    .iter()
+                    .enumerate()
+                    .map(|(shadow_map, image)| DescSetWrite {
+                        binding: 0,
+                        array_offset: shadow_map,
+                        descriptors: hal::pso::Descriptor::CombinedImageSampler(
+                            graph.image_view(*image),
+                            hal::image::Layout::ShaderReadOnlyOptimal,
+                            &sampler,
+                        ),
+                    })
+                    .collect(),
+            );
+        });
+
+        Self {
+            core: Arc::clone(core),
+            passes,
+            pipeline,
+            sampler: Some(sampler),
+            desc_set_layout,
+            desc_set,
+            in_use: 0,
+        }
+    }
+}
+
+impl<B: hal::Backend> Drop for ShadowMaps<B> {
+    fn drop(&mut self) {
+        let device_lock = &self.core.read().unwrap().device.device;
+        let mut device = device_lock.write().unwrap();
+
+        unsafe {
+            device.destroy_sampler(self.sampler.take().unwrap());
+            self.desc_set_layout.write().unwrap().drop(device.deref_mut());
+        }
+    }
+}
+
+const UI_FONT: &str = "fonts/mplus-1p-regular.ttf";
+
+// Screen space geometry drawn in its own pass after the scene. Everything the overlay draws samples the
+// glyph atlas, plain quads just point at its white corner so they show their vertex colors.
+//
+// The latest geometry and atlas are kept here and every frame in flight has its own buffers and
+// descriptor set, which only catch up once that frame's fence has signalled. That way a new glyph or a
+// changed label never has to wait for the gpu, and only the frame being caught up is re-recorded.
+struct UiOverlay<B: hal::Backend> {
+    core: Arc<RwLock<RendererCore<B>>>,
+    pass: PassId,
+    pipeline: Pipeline<B>,
+    atlas: GlyphAtlas,
+    desc_set_layout: Arc<RwLock<DescSetLayout<B>>>,
+
+    // atlas textures by generation, the last one is current and the others are dropped once no frame
+    // points at them anymore
+    textures: Vec<(u64, crate::renderer::types::Texture<B>)>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    geometry: u64,
+
+    frames: Vec<UiFrame<B>>,
+    // sets of frames that went away when the swapchain shrank, descriptor sets can't be freed one by one
+    spare_desc_sets: Vec<DescSet<B>>,
+}
+
+// what a single frame in flight draws the overlay with
+struct UiFrame<B: hal::Backend> {
+    desc_set: DescSet<B>,
+    texture: u64,
+    // never uploaded if None
+    geometry: Option<u64>,
+
+    vertex_buffer: Option<Buffer<B>>,
+    index_buffer: Option<Buffer<B>>,
+    indirect_buffer: Option<Buffer<B>>,
+}
+
+impl<B: hal::Backend> UiFrame<B> {
+    fn drop_buffers(&mut self, device: &mut B::Device) {
+        for buffer in [&mut self.vertex_buffer, &mut self.index_buffer, &mut self.indirect_buffer].iter_mut() {
+            if let Some(mut buffer) = buffer.take() {
+                buffer.drop(device);
             }
+        }
+    }
+}
+
+impl<B: hal::Backend> UiOverlay<B> {
+    fn new(core: &Arc<RwLock<RendererCore<B>>>,
+           allocator: &Arc<RwLock<GfxAllocator<B>>>,
+           graph: &RenderGraph<B>,
+           pass: PassId,
+           frame_count: usize) -> Self {
+        let desc_set_layout = Arc::new(RwLock::new(allocator.write().unwrap().alloc_desc_set_layout(
+            &[hal::pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: hal::pso::DescriptorType::Image {
+                    ty: hal::pso::ImageDescriptorType::Sampled {
+                        with_sampler: true,
+                    }
+                },
+                count: 1,
+                stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }])));
+
+        let pipeline = unsafe {
+            Pipeline::new(
+                core,
+                graph.render_pass(pass),
+                vec![desc_set_layout.read().unwrap().layout.as_ref().unwrap()],
+                &[],
+                "shaders/ui.vert",
+                Some("shaders/ui.frag"),
+                PipelineKind::Overlay,
+            )
+        };
 
-            for (_, rtv) in self.frame_images.take().unwrap() {
-                device.destroy_image_view(rtv);
+        let font_data = std::fs::read(data_path(UI_FONT)).expect("Can't read ui font");
+        let mut atlas = GlyphAtlas::new(font_data).expect("Can't load ui font");
+
+        let texture = allocator
+            .write()
+            .unwrap()
+            .alloc_texture(hal::buffer::Usage::TRANSFER_SRC, &atlas.texture())
+            .expect("Can't create glyph atlas texture");
+        atlas.take_dirty();
+
+        let mut overlay = Self {
+            core: Arc::clone(core),
+            pass,
+            pipeline,
+            atlas,
+            desc_set_layout,
+            textures: vec![(0, texture)],
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            geometry: 0,
+            frames: Vec::new(),
+            spare_desc_sets: Vec::new(),
+        };
+
+        overlay.set_frame_count(allocator, frame_count);
+        overlay
+    }
+
+    // the caller has to make sure the gpu is done with every frame
+    fn set_frame_count(&mut self, allocator: &Arc<RwLock<GfxAllocator<B>>>, frame_count: usize) {
+        let mut removed = self.frames.split_off(std::cmp::min(frame_count, self.frames.len()));
+
+        run_with_device(&self.core, |device| {
+            for frame in removed.iter_mut() {
+                frame.drop_buffers(device);
             }
+        });
+
+        self.spare_desc_sets.extend(removed.into_iter().map(|frame| frame.desc_set));
+
+        while self.frames.len() < frame_count {
+            let desc_set = match self.spare_desc_sets.pop() {
+                Some(desc_set) => desc_set,
+                None => allocator
+                    .write()
+                    .unwrap()
+                    .alloc_desc_set(DescriptorPoolType::Texture, &self.desc_set_layout)
+                    .expect("Can't allocate ui descriptor set"),
+            };
+
+            self.frames.push(UiFrame {
+                desc_set,
+                texture: 0,
+                geometry: None,
+                vertex_buffer: None,
+                index_buffer: None,
+                indirect_buffer: None,
+            });
 
-            self.depth_image.drop(device.deref_mut());
+            self.point_at_latest_texture(self.frames.len() - 1);
         }
+
+        self.drop_unused_textures();
+    }
+
+    fn set_geometry(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        if vertices != self.vertices || indices != self.indices {
+            self.vertices = vertices;
+            self.indices = indices;
+            self.geometry += 1;
+        }
+    }
+
+    fn add_texture(&mut self, texture: crate::renderer::types::Texture<B>) {
+        let generation = self.latest_texture() + 1;
+        self.textures.push((generation, texture));
+    }
+
+    fn latest_texture(&self) -> u64 {
+        self.textures.last().unwrap().0
+    }
+
+    // the frame's command buffer can't be pending, writing its set invalidates it
+    fn point_at_latest_texture(&mut self, frame: usize) {
+        let (generation, texture) = self.textures.last().unwrap();
+        let ui_frame = &mut self.frames[frame];
+        ui_frame.texture = *generation;
+
+        let desc_set = &ui_frame.desc_set;
+        run_with_device(&self.core, |device| {
+            desc_set.write(
+                device,
+                vec![DescSetWrite {
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: hal::pso::Descriptor::CombinedImageSampler(
+                        texture.image.image_view.as_ref().unwrap(),
+                        hal::image::Layout::ShaderReadOnlyOptimal,
+                        texture.sampler.as_ref().unwrap(),
+                    ),
+                }],
+            );
+        });
+    }
+
+    // Only ever called right after a frame moved off a texture, and that frame's fence has signalled,
+    // so nothing still reads what gets dropped here.
+    fn drop_unused_textures(&mut self) {
+        let latest = self.latest_texture();
+        let in_use = self.frames.iter().map(|frame| frame.texture).collect::<HashSet<u64>>();
+
+        let (textures, unused) = self.textures
+            .drain(..)
+            .partition::<Vec<(u64, crate::renderer::types::Texture<B>)>, _>(|(generation, _)| *generation == latest || in_use.contains(generation));
+        self.textures = textures;
+
+        run_with_device(&self.core, |device| {
+            for (_, mut texture) in unused {
+                texture.drop(device);
+            }
+        });
     }
 }
 
+impl<B: hal::Backend> Drop for UiOverlay<B> {
+    fn drop(&mut self) {
+        let device_lock = &self.core.read().unwrap().device.device;
+        let mut device = device_lock.write().unwrap();
+
+        for (_, mut texture) in self.textures.drain(..) {
+            texture.drop(device.deref_mut());
+        }
+
+        for frame in self.frames.iter_mut() {
+            frame.drop_buffers(device.deref_mut());
+        }
+
+        self.desc_set_layout.write().unwrap().drop(device.deref_mut());
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use rusttype::{point, Font, GlyphId, Scale};
+
+use crate::components::text::{Text, TextAlign};
+use crate::components::texture::{ColorSpace, FilterMode, SamplerOptions, Texture, TexturePixels, WrapMode};
+use crate::primitives::vertex::Vertex;
+
+const ATLAS_WIDTH: u32 = 1024;
+const INITIAL_ATLAS_HEIGHT: u32 = 256;
+const MAX_ATLAS_HEIGHT: u32 = 4096;
+
+// empty texels right of and below every glyph so linear filtering doesn't pull in its neighbours
+const GLYPH_PADDING: u32 = 1;
+
+// a solid block in the top left corner for ui geometry that isn't text
+const WHITE_SIZE: u32 = 4;
+
+#[derive(Clone, Copy, Debug)]
+struct AtlasRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CachedGlyph {
+    // glyphs like spaces don't have any pixels
+    region: Option<AtlasRegion>,
+    // from the pen position on the baseline to the top left of the bitmap
+    offset: [i32; 2],
+}
+
+// a glyph laid out in window pixels
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PlacedGlyph {
+    rect: [f32; 4],
+    region: AtlasRegion,
+    color: [f32; 3],
+}
+
+// Rasterizes glyphs the first time they are drawn at a size and packs them into rows of a single rgba
+// texture, white with the coverage in alpha. The atlas grows downwards when it runs out of room, the
+// drawer re-uploads it whenever `take_dirty` says it changed.
+pub(crate) struct GlyphAtlas {
+    font: Font<'static>,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    glyphs: HashMap<(GlyphId, u32), CachedGlyph>,
+
+    // glyphs are placed left to right along the current row, which is as tall as its tallest glyph
+    cursor: (u32, u32),
+    row_height: u32,
+
+    dirty: bool,
+}
+
+impl GlyphAtlas {
+    pub fn new(font_data: Vec<u8>) -> Result<Self, String> {
+        let font = Font::try_from_vec(font_data).ok_or_else(|| String::from("can't parse font"))?;
+
+        let mut pixels = vec![0; (ATLAS_WIDTH * INITIAL_ATLAS_HEIGHT * 4) as usize];
+        for y in 0..WHITE_SIZE {
+            let start = (y * ATLAS_WIDTH * 4) as usize;
+            for texel in pixels[start..start + (WHITE_SIZE * 4) as usize].iter_mut() {
+                *texel = 255;
+            }
+        }
+
+        Ok(Self {
+            font,
+            width: ATLAS_WIDTH,
+            height: INITIAL_ATLAS_HEIGHT,
+            pixels,
+            glyphs: HashMap::new(),
+            cursor: (WHITE_SIZE + GLYPH_PADDING, 0),
+            row_height: WHITE_SIZE + GLYPH_PADDING,
+            dirty: true,
+        })
+    }
+
+    pub fn texture(&self) -> Texture {
+        Texture::from_pixels("glyph_atlas", TexturePixels {
+            width: self.width,
+            height: self.height,
+            data: self.pixels.clone(),
+        }).with_sampler(SamplerOptions {
+            filter: FilterMode::Linear,
+            wrap: WrapMode::Clamp,
+            anisotropy: None,
+            color_space: ColorSpace::Linear,
+            mipmaps: false,
+        })
+    }
+
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    // the middle of the white block, so filtering never reaches the glyphs around it
+    pub fn white_tex_coord(&self) -> [f32; 2] {
+        let center = WHITE_SIZE as f32 / 2.0;
+        [center / self.width as f32, center / self.height as f32]
+    }
+
+    // Breaks the text into lines, then places every glyph along them with kerning applied. Glyphs are
+    // snapped to whole pixels so they stay sharp.
+    pub fn layout(&mut self, text: &Text) -> Result<Vec<PlacedGlyph>, String> {
+        let size = text.size.round().max(1.0) as u32;
+        let scale = Scale::uniform(size as f32);
+        let v_metrics = self.font.v_metrics(scale);
+        let line_height = (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap).ceil();
+
+        let mut placed = Vec::new();
+
+        for (line_index, line) in self.break_lines(&text.content, scale, text.max_width).iter().enumerate() {
+            let line_x = match text.align {
+                TextAlign::Left => text.position[0],
+                TextAlign::Center => text.position[0] - self.line_width(line, scale) / 2.0,
+                TextAlign::Right => text.position[0] - self.line_width(line, scale),
+            };
+            let baseline = (text.position[1] + v_metrics.ascent + line_index as f32 * line_height).round();
+
+            let mut pen_x = line_x;
+            let mut previous = None;
+
+            for c in line.chars() {
+                let glyph = self.font.glyph(c).scaled(scale);
+                let id = glyph.id();
+
+                if let Some(previous) = previous {
+                    pen_x += self.font.pair_kerning(scale, previous, id);
+                }
+
+                let cached = self.glyph(id, size)?;
+                if let Some(region) = cached.region {
+                    let x = pen_x.round() + cached.offset[0] as f32;
+                    let y = baseline + cached.offset[1] as f32;
+
+                    placed.push(PlacedGlyph {
+                        rect: [x, y, x + region.width as f32, y + region.height as f32],
+                        region,
+                        color: text.color,
+                    });
+                }
+
+                pen_x += glyph.h_metrics().advance_width;
+                previous = Some(id);
+            }
+        }
+
+        Ok(placed)
+    }
+
+    // Appends a quad per glyph in normalized device coordinates. Only call this once everything drawn
+    // this frame has been laid out, a glyph added later can grow the atlas and move every tex coord.
+    pub fn append_geometry(&self, glyphs: &[PlacedGlyph], screen: (u32, u32), vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+        for glyph in glyphs {
+            let base_vertex = vertices.len() as u32;
+            let [left, top, right, bottom] = glyph.rect;
+            let u0 = glyph.region.x as f32 / self.width as f32;
+            let v0 = glyph.region.y as f32 / self.height as f32;
+            let u1 = (glyph.region.x + glyph.region.width) as f32 / self.width as f32;
+            let v1 = (glyph.region.y + glyph.region.height) as f32 / self.height as f32;
+
+            let corners = [
+                ([left, top], [u0, v0]),
+                ([right, top], [u1, v0]),
+                ([right, bottom], [u1, v1]),
+                ([left, bottom], [u0, v1]),
+            ];
+
+            for (position, tex_coord) in corners.iter() {
+                let mut vertex = Vertex::new([position[0], position[1], 0.0], glyph.color, *tex_coord, [0.0, 0.0, 1.0]);
+                vertex.normalize(screen.0, screen.1);
+                vertices.push(vertex);
+            }
+
+            indices.extend([0, 1, 2, 2, 3, 0].iter().map(|index| base_vertex + index));
+        }
+    }
+
+    // explicit line breaks always split, spaces only when the line would get wider than `max_width`
+    fn break_lines(&self, content: &str, scale: Scale, max_width: Option<f32>) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for paragraph in content.split('\n') {
+            let max_width = match max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                },
+            };
+
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+
+                // a single word wider than the limit gets a line to itself
+                if !line.is_empty() && self.line_width(&candidate, scale) > max_width {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    fn line_width(&self, line: &str, scale: Scale) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+
+        for c in line.chars() {
+            let glyph = self.font.glyph(c).scaled(scale);
+
+            if let Some(previous) = previous {
+                width += self.font.pair_kerning(scale, previous, glyph.id());
+            }
+
+            width += glyph.h_metrics().advance_width;
+            previous = Some(glyph.id());
+        }
+
+        width
+    }
+
+    fn glyph(&mut self, id: GlyphId, size: u32) -> Result<CachedGlyph, String> {
+        if let Some(cached) = self.glyphs.get(&(id, size)) {
+            return Ok(*cached);
+        }
+
+        let glyph = self.font
+            .glyph(id)
+            .scaled(Scale::uniform(size as f32))
+            .positioned(point(0.0, 0.0));
+
+        let cached = match glyph.pixel_bounding_box() {
+            Some(bounds) => {
+                let region = self.allocate(bounds.width() as u32, bounds.height() as u32)?;
+                let width = self.width;
+                let pixels = &mut self.pixels;
+
+                glyph.draw(|x, y, coverage| {
+                    let index = (((region.y + y) * width + region.x + x) * 4) as usize;
+                    pixels[index..index + 4].copy_from_slice(&[255, 255, 255, (coverage * 255.0).round() as u8]);
+                });
+
+                self.dirty = true;
+
+                CachedGlyph {
+                    region: Some(region),
+                    offset: [bounds.min.x, bounds.min.y],
+                }
+            },
+            None => CachedGlyph {
+                region: None,
+                offset: [0, 0],
+            },
+        };
+
+        self.glyphs.insert((id, size), cached);
+        Ok(cached)
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Result<AtlasRegion, String> {
+        let (padded_width, padded_height) = (width + GLYPH_PADDING, height + GLYPH_PADDING);
+
+        if padded_width > self.width {
+            return Err(format!("glyph of {}x{} is wider than the atlas", width, height));
+        }
+
+        if self.cursor.0 + padded_width > self.width {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+
+        // rows are kept the same width so the existing pixels don't move
+        while self.cursor.1 + padded_height > self.height {
+            if self.height * 2 > MAX_ATLAS_HEIGHT {
+                return Err(String::from("glyph atlas is full"));
+            }
+
+            self.height *= 2;
+            self.pixels.resize((self.width * self.height * 4) as usize, 0);
+        }
+
+        let region = AtlasRegion {
+            x: self.cursor.0,
+            y: self.cursor.1,
+            width,
+            height,
+        };
+
+        self.cursor.0 += padded_width;
+        self.row_height = std::cmp::max(self.row_height, padded_height);
+
+        Ok(region)
+    }
+}
@@ -0,0 +1,776 @@
+use std::sync::{Arc, RwLock};
+
+use hal::command::CommandBuffer;
+use hal::device::Device;
+
+use crate::renderer::allocator::{Allocator, COLOR_RANGE};
+use crate::renderer::core::{RendererCore, run_with_device};
+use crate::renderer::types::Image;
+
+// A frame described as passes and the images they write as attachments or sample in their shaders.
+// The graph works out the order the passes have to run in, creates their render passes and
+// framebuffers along with every image besides the backbuffer, and puts barriers between a pass
+// writing an image and a later pass sampling it. What a pass actually draws is up to whoever records
+// the graph.
+
+pub(crate) type ResourceId = usize;
+pub(crate) type PassId = usize;
+
+// the images being presented, the graph only ever gets views into them
+const BACKBUFFER: ResourceId = 0;
+
+fn attachment_stages() -> hal::pso::PipelineStage {
+    hal::pso::PipelineStage::COLOR_ATTACHMENT_OUTPUT
+        | hal::pso::PipelineStage::EARLY_FRAGMENT_TESTS
+        | hal::pso::PipelineStage::LATE_FRAGMENT_TESTS
+}
+
+fn sampling_stages() -> hal::pso::PipelineStage {
+    hal::pso::PipelineStage::VERTEX_SHADER | hal::pso::PipelineStage::FRAGMENT_SHADER
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ImageSize {
+    // follows the size of the backbuffer and gets recreated when it's resized
+    Backbuffer,
+    Fixed(u32, u32),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ImageDesc {
+    pub format: hal::format::Format,
+    pub size: ImageSize,
+    pub clear: hal::command::ClearValue,
+}
+
+impl ImageDesc {
+    fn is_depth(&self) -> bool {
+        self.format.surface_desc().aspects.contains(hal::format::Aspects::DEPTH)
+    }
+}
+
+pub(crate) struct PassDesc {
+    name: String,
+    colors: Vec<ResourceId>,
+    depth: Option<ResourceId>,
+    reads: Vec<ResourceId>,
+}
+
+impl PassDesc {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            colors: Vec::new(),
+            depth: None,
+            reads: Vec::new(),
+        }
+    }
+
+    pub fn color(mut self, resource: ResourceId) -> Self {
+        self.colors.push(resource);
+        self
+    }
+
+    pub fn depth(mut self, resource: ResourceId) -> Self {
+        self.depth = Some(resource);
+        self
+    }
+
+    // images the pass samples in its shaders
+    pub fn read(mut self, resource: ResourceId) -> Self {
+        self.reads.push(resource);
+        self
+    }
+
+    // colors first, then depth, the same order the render pass gets them in
+    fn attachments(&self) -> Vec<ResourceId> {
+        self.colors.iter().cloned().chain(self.depth).collect()
+    }
+
+    fn writes(&self, resource: ResourceId) -> bool {
+        self.colors.contains(&resource) || self.depth == Some(resource)
+    }
+}
+
+struct ResourceDesc {
+    name: String,
+    desc: ImageDesc,
+}
+
+pub(crate) struct RenderGraphBuilder {
+    resources: Vec<ResourceDesc>,
+    passes: Vec<PassDesc>,
+}
+
+impl RenderGraphBuilder {
+    pub fn new(backbuffer_format: hal::format::Format, backbuffer_clear: hal::command::ClearValue) -> Self {
+        Self {
+            resources: vec![ResourceDesc {
+                name: String::from("backbuffer"),
+                desc: ImageDesc {
+                    format: backbuffer_format,
+                    size: ImageSize::Backbuffer,
+                    clear: backbuffer_clear,
+                },
+            }],
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn backbuffer(&self) -> ResourceId {
+        BACKBUFFER
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ResourceId {
+        self.resources.push(ResourceDesc {
+            name: String::from(name),
+            desc,
+        });
+
+        self.resources.len() - 1
+    }
+
+    pub fn add_pass(&mut self, pass: PassDesc) -> PassId {
+        self.passes.push(pass);
+        self.passes.len() - 1
+    }
+
+    pub fn build<B: hal::Backend, A: Allocator<B>>(self,
+                                                   core: &Arc<RwLock<RendererCore<B>>>,
+                                                   allocator: &Arc<RwLock<A>>,
+                                                   viewport: &hal::pso::Viewport,
+                                                   images: Vec<&B::Image>) -> Result<RenderGraph<B>, String> {
+        self.validate()?;
+        let order = self.order()?;
+
+        let resources = self.resources
+            .iter()
+            .enumerate()
+            .map(|(resource, desc)| GraphImage {
+                desc: desc.desc,
+                usage: self.usage(resource),
+                image: None,
+            })
+            .collect::<Vec<GraphImage<B>>>();
+
+        let passes = self.compile(core, &order);
+
+        let mut graph = RenderGraph {
+            core: Arc::clone(core),
+            resources,
+            passes,
+            order,
+            backbuffer_views: Vec::new(),
+        };
+
+        unsafe {
+            graph.create_targets(allocator, viewport, images);
+        }
+
+        Ok(graph)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for pass in self.passes.iter() {
+            let attachments = pass.attachments();
+
+            if let Some(resource) = attachments.iter().chain(pass.reads.iter()).find(|r| **r >= self.resources.len()) {
+                return Err(format!("pass {} uses unknown image {}", pass.name, resource));
+            }
+
+            if attachments.is_empty() {
+                return Err(format!("pass {} doesn't write anything", pass.name));
+            }
+
+            if let Some(resource) = pass.colors.iter().find(|r| self.resources[**r].desc.is_depth()) {
+                return Err(format!("pass {} uses depth image {} as a color attachment", pass.name, self.resources[*resource].name));
+            }
+
+            if let Some(resource) = pass.depth.iter().find(|r| !self.resources[**r].desc.is_depth()) {
+                return Err(format!("pass {} uses {} as a depth attachment", pass.name, self.resources[*resource].name));
+            }
+
+            // framebuffers need every attachment to be the same size
+            let size = self.resources[attachments[0]].desc.size;
+            if attachments.iter().any(|r| self.resources[*r].desc.size != size) {
+                return Err(format!("attachments of pass {} have different sizes", pass.name));
+            }
+
+            for resource in pass.reads.iter() {
+                let name = &self.resources[*resource].name;
+
+                if *resource == BACKBUFFER {
+                    return Err(format!("pass {} can't sample the backbuffer", pass.name));
+                }
+
+                if pass.writes(*resource) {
+                    return Err(format!("pass {} reads and writes {}", pass.name, name));
+                }
+
+                if !self.passes.iter().any(|p| p.writes(*resource)) {
+                    return Err(format!("pass {} reads {} but nothing writes it", pass.name, name));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // A pass runs after every pass that writes an image it reads, and after passes added before it that
+    // write the same attachments. Ties go to whichever pass was added first.
+    fn order(&self) -> Result<Vec<PassId>, String> {
+        let dependencies = self.passes
+            .iter()
+            .enumerate()
+            .map(|(pass_id, pass)| {
+                self.passes
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_id, other)| {
+                        let writes_read = pass.reads.iter().any(|r| other.writes(*r));
+                        let writes_before = *other_id < pass_id && pass.attachments().iter().any(|r| other.writes(*r));
+                        *other_id != pass_id && (writes_read || writes_before)
+                    })
+                    .map(|(other_id, _)| other_id)
+                    .collect::<Vec<PassId>>()
+            })
+            .collect::<Vec<Vec<PassId>>>();
+
+        let mut scheduled = vec![false; self.passes.len()];
+        let mut order = Vec::new();
+
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|pass_id| !scheduled[*pass_id] && dependencies[*pass_id].iter().all(|d| scheduled[*d]));
+
+            match next {
+                Some(pass_id) => {
+                    scheduled[pass_id] = true;
+                    order.push(pass_id);
+                },
+                None => {
+                    let stuck = (0..self.passes.len())
+                        .filter(|pass_id| !scheduled[*pass_id])
+                        .map(|pass_id| self.passes[pass_id].name.clone())
+                        .collect::<Vec<String>>();
+
+                    return Err(format!("render graph has a cycle between passes {}", stuck.join(", ")));
+                },
+            }
+        }
+
+        Ok(order)
+    }
+
+    fn usage(&self, resource: ResourceId) -> hal::image::Usage {
+        let mut usage = hal::image::Usage::empty();
+
+        for pass in self.passes.iter() {
+            if pass.colors.contains(&resource) {
+                usage |= hal::image::Usage::COLOR_ATTACHMENT;
+            }
+
+            if pass.depth == Some(resource) {
+                usage |= hal::image::Usage::DEPTH_STENCIL_ATTACHMENT;
+            }
+
+            if pass.reads.contains(&resource) {
+                usage |= hal::image::Usage::SAMPLED;
+            }
+        }
+
+        usage
+    }
+
+    // Walks the passes in order keeping track of the layout each image is left in, which decides the load
+    // and store ops of every attachment and where sampled images need a barrier.
+    fn compile<B: hal::Backend>(&self, core: &Arc<RwLock<RendererCore<B>>>, order: &[PassId]) -> Vec<CompiledPass<B>> {
+        let mut layouts = vec![hal::image::Layout::Undefined; self.resources.len()];
+        let mut written = vec![false; self.resources.len()];
+        let mut compiled = self.passes.iter().map(|_| None).collect::<Vec<Option<CompiledPass<B>>>>();
+
+        for (position, pass_id) in order.iter().enumerate() {
+            let pass = &self.passes[*pass_id];
+            let used_later = |resource: ResourceId| {
+                order[position + 1..]
+                    .iter()
+                    .any(|later| self.passes[*later].writes(resource) || self.passes[*later].reads.contains(&resource))
+            };
+
+            let barriers = pass.reads
+                .iter()
+                .filter(|resource| layouts[**resource] != hal::image::Layout::ShaderReadOnlyOptimal)
+                .map(|resource| {
+                    let from = layouts[*resource];
+                    layouts[*resource] = hal::image::Layout::ShaderReadOnlyOptimal;
+                    ReadBarrier { resource: *resource, from }
+                })
+                .collect::<Vec<ReadBarrier>>();
+
+            let attachments = pass.attachments();
+            let attachment_descs = attachments
+                .iter()
+                .map(|resource| {
+                    let desc = &self.resources[*resource].desc;
+                    let attachment_layout = if desc.is_depth() {
+                        hal::image::Layout::DepthStencilAttachmentOptimal
+                    } else {
+                        hal::image::Layout::ColorAttachmentOptimal
+                    };
+
+                    let load = if written[*resource] { hal::pass::AttachmentLoadOp::Load } else { hal::pass::AttachmentLoadOp::Clear };
+                    let store = if used_later(*resource) || *resource == BACKBUFFER {
+                        hal::pass::AttachmentStoreOp::Store
+                    } else {
+                        hal::pass::AttachmentStoreOp::DontCare
+                    };
+
+                    let initial_layout = layouts[*resource];
+                    let final_layout = if *resource == BACKBUFFER && !used_later(*resource) {
+                        hal::image::Layout::Present
+                    } else {
+                        attachment_layout
+                    };
+
+                    layouts[*resource] = final_layout;
+                    written[*resource] = true;
+
+                    hal::pass::Attachment {
+                        format: Some(desc.format),
+                        samples: 1,
+                        ops: hal::pass::AttachmentOps::new(load, store),
+                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                        layouts: initial_layout..final_layout,
+                    }
+                })
+                .collect::<Vec<hal::pass::Attachment>>();
+
+            let color_refs = (0..pass.colors.len())
+                .map(|index| (index, hal::image::Layout::ColorAttachmentOptimal))
+                .collect::<Vec<hal::pass::AttachmentRef>>();
+            let depth_ref = pass.depth.map(|_| (pass.colors.len(), hal::image::Layout::DepthStencilAttachmentOptimal));
+
+            let subpass = hal::pass::SubpassDesc {
+                colors: &color_refs,
+                depth_stencil: depth_ref.as_ref(),
+                inputs: &[],
+                resolves: &[],
+                preserves: &[],
+            };
+
+            // whatever wrote the attachments before, or sampled them last frame, has to be done first
+            let dependency = hal::pass::SubpassDependency {
+                passes: None..Some(0),
+                stages: (attachment_stages() | sampling_stages())..attachment_stages(),
+                accesses: (hal::image::Access::COLOR_ATTACHMENT_WRITE | hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                    ..(hal::image::Access::COLOR_ATTACHMENT_READ
+                        | hal::image::Access::COLOR_ATTACHMENT_WRITE
+                        | hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
+                        | hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE),
+                flags: hal::memory::Dependencies::empty(),
+            };
+
+            let render_pass = run_with_device(core, |device| unsafe {
+                device.create_render_pass(&attachment_descs, &[subpass], &[dependency])
+            }).expect("Can't create render pass");
+
+            compiled[*pass_id] = Some(CompiledPass {
+                render_pass: Some(render_pass),
+                clear_values: attachments.iter().map(|r| self.resources[*r].desc.clear).collect(),
+                attachments,
+                barriers,
+                framebuffers: Vec::new(),
+                extent: hal::image::Extent { width: 0, height: 0, depth: 1 },
+            });
+        }
+
+        compiled.into_iter().map(|pass| pass.unwrap()).collect()
+    }
+}
+
+struct GraphImage<B: hal::Backend> {
+    desc: ImageDesc,
+    usage: hal::image::Usage,
+    image: Option<Image<B>>,
+}
+
+// a sampled image has to be moved out of the layout the pass that wrote it left it in
+struct ReadBarrier {
+    resource: ResourceId,
+    from: hal::image::Layout,
+}
+
+struct CompiledPass<B: hal::Backend> {
+    render_pass: Option<B::RenderPass>,
+    attachments: Vec<ResourceId>,
+    clear_values: Vec<hal::command::ClearValue>,
+    barriers: Vec<ReadBarrier>,
+
+    // one per backbuffer image if the pass draws to it, otherwise just one
+    framebuffers: Vec<B::Framebuffer>,
+    extent: hal::image::Extent,
+}
+
+pub(crate) struct RenderGraph<B: hal::Backend> {
+    core: Arc<RwLock<RendererCore<B>>>,
+    resources: Vec<GraphImage<B>>,
+    passes: Vec<CompiledPass<B>>,
+    order: Vec<PassId>,
+    backbuffer_views: Vec<B::ImageView>,
+}
+
+impl<B: hal::Backend> RenderGraph<B> {
+    // pipelines for a pass are created against this
+    pub fn render_pass(&self, pass: PassId) -> &B::RenderPass {
+        self.passes[pass].render_pass.as_ref().unwrap()
+    }
+
+    // what the render passes writing to the backbuffer were created for
+    pub fn backbuffer_format(&self) -> hal::format::Format {
+        self.resources[BACKBUFFER].desc.format
+    }
+
+    pub fn viewport(&self, pass: PassId) -> hal::pso::Viewport {
+        let extent = self.passes[pass].extent;
+
+        hal::pso::Viewport {
+            rect: hal::pso::Rect {
+                x: 0,
+                y: 0,
+                w: extent.width as i16,
+                h: extent.height as i16,
+            },
+            depth: 0.0..1.0,
+        }
+    }
+
+    // only takes effect in command buffers recorded after this
+    pub fn set_clear(&mut self, resource: ResourceId, clear: hal::command::ClearValue) {
+        self.resources[resource].desc.clear = clear;
+
+        for pass in self.passes.iter_mut() {
+            for (attachment, clear_value) in pass.attachments.iter().zip(pass.clear_values.iter_mut()) {
+                if *attachment == resource {
+                    *clear_value = clear;
+                }
+            }
+        }
+    }
+
+    // Images sized to the backbuffer are recreated when it's resized, so descriptor sets pointing at
+    // them have to be rewritten after a resize.
+    pub fn image_view(&self, resource: ResourceId) -> &B::ImageView {
+        self.resources[resource].image.as_ref().unwrap().image_view.as_ref().unwrap()
+    }
+
+    // Records every pass in order for the given backbuffer image. The graph begins and ends the render
+    // passes, `record_pass` only has to bind and draw.
+    pub unsafe fn record<F>(&self, cmd_buffer: &mut B::CommandBuffer, frame: usize, mut record_pass: F)
+        where F: FnMut(PassId, &mut B::CommandBuffer)
+    {
+        for pass_id in self.order.iter() {
+            let pass = &self.passes[*pass_id];
+
+            // passes drawing to the backbuffer have a framebuffer per backbuffer image, GL can have none
+            let framebuffer = match pass.framebuffers.len() {
+                0 => continue,
+                1 => &pass.framebuffers[0],
+                _ => &pass.framebuffers[frame],
+            };
+
+            for barrier in pass.barriers.iter() {
+                let graph_image = &self.resources[barrier.resource];
+                let (stage, access, aspects) = if graph_image.desc.is_depth() {
+                    (hal::pso::PipelineStage::LATE_FRAGMENT_TESTS, hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE, graph_image.desc.format.surface_desc().aspects)
+                } else {
+                    (hal::pso::PipelineStage::COLOR_ATTACHMENT_OUTPUT, hal::image::Access::COLOR_ATTACHMENT_WRITE, hal::format::Aspects::COLOR)
+                };
+
+                cmd_buffer.pipeline_barrier(
+                    stage..sampling_stages(),
+                    hal::memory::Dependencies::empty(),
+                    &[hal::memory::Barrier::Image {
+                        states: (access, barrier.from)..(hal::image::Access::SHADER_READ, hal::image::Layout::ShaderReadOnlyOptimal),
+                        target: graph_image.image.as_ref().unwrap().image.as_ref().unwrap(),
+                        families: None,
+                        range: hal::image::SubresourceRange {
+                            aspects,
+                            levels: 0..1,
+                            layers: 0..1,
+                        },
+                    }],
+                );
+            }
+
+            let viewport = self.viewport(*pass_id);
+
+            cmd_buffer.set_viewports(0, &[viewport.clone()]);
+            cmd_buffer.set_scissors(0, &[viewport.rect]);
+
+            cmd_buffer.begin_render_pass(
+                pass.render_pass.as_ref().unwrap(),
+                framebuffer,
+                viewport.rect,
+                &pass.clear_values,
+                hal::command::SubpassContents::Inline
+            );
+
+            record_pass(*pass_id, cmd_buffer);
+
+            cmd_buffer.end_render_pass();
+        }
+    }
+
+    // the caller has to make sure the gpu is done with the old framebuffers
+    pub fn resize<A: Allocator<B>>(&mut self, allocator: &Arc<RwLock<A>>, viewport: &hal::pso::Viewport, images: Vec<&B::Image>) {
+        self.destroy_targets(false);
+
+        unsafe {
+            self.create_targets(allocator, viewport, images);
+        }
+    }
+
+    unsafe fn create_targets<A: Allocator<B>>(&mut self, allocator: &Arc<RwLock<A>>, viewport: &hal::pso::Viewport, images: Vec<&B::Image>) {
+        let backbuffer_format = self.resources[BACKBUFFER].desc.format;
+        let backbuffer_extent = (viewport.rect.w as u32, viewport.rect.h as u32);
+
+        self.backbuffer_views = images
+            .into_iter()
+            .map(|image| {
+                run_with_device(&self.core, |device| {
+                    device
+                        .create_image_view(
+                            image,
+                            hal::image::ViewKind::D2,
+                            backbuffer_format,
+                            hal::format::Swizzle::NO,
+                            COLOR_RANGE.clone(),
+                        )
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        for graph_image in self.resources.iter_mut().skip(1) {
+            if graph_image.image.is_some() {
+                continue;
+            }
+
+            let (width, height) = match graph_image.desc.size {
+                ImageSize::Backbuffer => backbuffer_extent,
+                ImageSize::Fixed(width, height) => (width, height),
+            };
+
+            graph_image.image = Some(allocator.write().unwrap().alloc_image(
+                width,
+                height,
+                graph_image.desc.format,
+                graph_image.usage,
+                graph_image.desc.format.surface_desc().aspects,
+            ));
+        }
+
+        let core = &self.core;
+        let resources = &self.resources;
+        let backbuffer_views = &self.backbuffer_views;
+
+        for pass in self.passes.iter_mut() {
+            let (width, height) = match resources[pass.attachments[0]].desc.size {
+                ImageSize::Backbuffer => backbuffer_extent,
+                ImageSize::Fixed(width, height) => (width, height),
+            };
+            pass.extent = hal::image::Extent { width, height, depth: 1 };
+
+            let framebuffer_count = if pass.attachments.contains(&BACKBUFFER) { backbuffer_views.len() } else { 1 };
+            let render_pass = pass.render_pass.as_ref().unwrap();
+            let attachments = &pass.attachments;
+            let extent = pass.extent;
+
+            pass.framebuffers = (0..framebuffer_count)
+                .map(|frame| {
+                    let views = attachments
+                        .iter()
+                        .map(|resource| match *resource {
+                            BACKBUFFER => &backbuffer_views[frame],
+                            _ => resources[*resource].image.as_ref().unwrap().image_view.as_ref().unwrap(),
+                        })
+                        .collect::<Vec<&B::ImageView>>();
+
+                    run_with_device(core, |device| {
+                        device
+                            .create_framebuffer(render_pass, views, extent)
+                            .expect("Can't create framebuffer")
+                    })
+                })
+                .collect();
+        }
+    }
+
+    // fixed size images survive a resize so whatever points at them stays valid
+    fn destroy_targets(&mut self, destroy_fixed: bool) {
+        let passes = &mut self.passes;
+        let backbuffer_views = &mut self.backbuffer_views;
+        let resources = &mut self.resources;
+
+        run_with_device(&self.core, |device| unsafe {
+            for pass in passes.iter_mut() {
+                for framebuffer in pass.framebuffers.drain(..) {
+                    device.destroy_framebuffer(framebuffer);
+                }
+            }
+
+            for view in backbuffer_views.drain(..) {
+                device.destroy_image_view(view);
+            }
+
+            for graph_image in resources.iter_mut() {
+                if destroy_fixed || graph_image.desc.size == ImageSize::Backbuffer {
+                    if let Some(mut image) = graph_image.image.take() {
+                        image.drop(device);
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl<B: hal::Backend> Drop for RenderGraph<B> {
+    fn drop(&mut self) {
+        self.destroy_targets(true);
+
+        let passes = &mut self.passes;
+        run_with_device(&self.core, |device| unsafe {
+            for pass in passes.iter_mut() {
+                device.destroy_render_pass(pass.render_pass.take().unwrap());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLEAR: hal::command::ClearValue = hal::command::ClearValue {
+        color: hal::command::ClearColor { float32: [0.0, 0.0, 0.0, 1.0] },
+    };
+
+    fn builder() -> RenderGraphBuilder {
+        RenderGraphBuilder::new(hal::format::Format::Bgra8Srgb, CLEAR)
+    }
+
+    fn image(format: hal::format::Format, size: ImageSize) -> ImageDesc {
+        ImageDesc { format, size, clear: CLEAR }
+    }
+
+    fn color(size: ImageSize) -> ImageDesc {
+        image(hal::format::Format::Rgba8Srgb, size)
+    }
+
+    fn depth(size: ImageSize) -> ImageDesc {
+        image(hal::format::Format::D32Sfloat, size)
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let mut graph = builder();
+        let shadow = graph.create_image("shadow", depth(ImageSize::Fixed(1024, 1024)));
+        let scene_depth = graph.create_image("depth", depth(ImageSize::Backbuffer));
+
+        let main = graph.add_pass(PassDesc::new("main").color(graph.backbuffer()).depth(scene_depth).read(shadow));
+        let shadows = graph.add_pass(PassDesc::new("shadows").depth(shadow));
+
+        assert_eq!(graph.validate(), Ok(()));
+        assert_eq!(graph.order(), Ok(vec![shadows, main]));
+    }
+
+    #[test]
+    fn shared_attachments_keep_the_order_passes_were_added_in() {
+        let mut graph = builder();
+        let main = graph.add_pass(PassDesc::new("main").color(graph.backbuffer()));
+        let ui = graph.add_pass(PassDesc::new("ui").color(graph.backbuffer()));
+
+        assert_eq!(graph.order(), Ok(vec![main, ui]));
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut graph = builder();
+        let first = graph.create_image("first", color(ImageSize::Backbuffer));
+        let second = graph.create_image("second", color(ImageSize::Backbuffer));
+
+        graph.add_pass(PassDesc::new("a").color(first).read(second));
+        graph.add_pass(PassDesc::new("b").color(second).read(first));
+        graph.add_pass(PassDesc::new("present").color(graph.backbuffer()).read(second));
+
+        assert_eq!(graph.validate(), Ok(()));
+        assert_eq!(graph.order(), Err(String::from("render graph has a cycle between passes a, b, present")));
+    }
+
+    #[test]
+    fn attachments_have_to_be_the_same_size() {
+        let mut graph = builder();
+        let shadow = graph.create_image("shadow", depth(ImageSize::Fixed(1024, 1024)));
+        graph.add_pass(PassDesc::new("main").color(graph.backbuffer()).depth(shadow));
+
+        assert_eq!(graph.validate(), Err(String::from("attachments of pass main have different sizes")));
+    }
+
+    #[test]
+    fn fixed_sizes_have_to_match_exactly() {
+        let mut graph = builder();
+        let small = graph.create_image("small", color(ImageSize::Fixed(512, 512)));
+        let large = graph.create_image("large", depth(ImageSize::Fixed(1024, 1024)));
+        graph.add_pass(PassDesc::new("offscreen").color(small).depth(large));
+
+        assert!(graph.validate().is_err());
+
+        let mut graph = builder();
+        let small = graph.create_image("small", color(ImageSize::Fixed(512, 512)));
+        let matching = graph.create_image("matching", depth(ImageSize::Fixed(512, 512)));
+        graph.add_pass(PassDesc::new("offscreen").color(small).depth(matching));
+
+        assert_eq!(graph.validate(), Ok(()));
+    }
+
+    #[test]
+    fn attachments_have_to_match_their_format() {
+        let mut graph = builder();
+        let scene_depth = graph.create_image("depth", depth(ImageSize::Backbuffer));
+        graph.add_pass(PassDesc::new("main").color(scene_depth));
+
+        assert_eq!(graph.validate(), Err(String::from("pass main uses depth image depth as a color attachment")));
+
+        let mut graph = builder();
+        graph.add_pass(PassDesc::new("main").depth(graph.backbuffer()));
+
+        assert_eq!(graph.validate(), Err(String::from("pass main uses backbuffer as a depth attachment")));
+    }
+
+    #[test]
+    fn invalid_passes_are_rejected() {
+        let mut graph = builder();
+        graph.add_pass(PassDesc::new("empty"));
+        assert_eq!(graph.validate(), Err(String::from("pass empty doesn't write anything")));
+
+        let mut graph = builder();
+        graph.add_pass(PassDesc::new("main").color(7));
+        assert_eq!(graph.validate(), Err(String::from("pass main uses unknown image 7")));
+
+        let mut graph = builder();
+        let lut = graph.create_image("lut", color(ImageSize::Fixed(16, 16)));
+        graph.add_pass(PassDesc::new("main").color(graph.backbuffer()).read(lut));
+        assert_eq!(graph.validate(), Err(String::from("pass main reads lut but nothing writes it")));
+
+        let mut graph = builder();
+        let blur = graph.create_image("blur", color(ImageSize::Backbuffer));
+        graph.add_pass(PassDesc::new("blur").color(blur).read(blur));
+        assert_eq!(graph.validate(), Err(String::from("pass blur reads and writes blur")));
+
+        let mut graph = builder();
+        let post = graph.create_image("post", color(ImageSize::Backbuffer));
+        graph.add_pass(PassDesc::new("main").color(graph.backbuffer()));
+        graph.add_pass(PassDesc::new("post").color(post).read(graph.backbuffer()));
+        assert_eq!(graph.validate(), Err(String::from("pass post can't sample the backbuffer")));
+    }
+}
@@ -4,4 +4,8 @@ pub mod allocator;
 pub mod drawer;
 pub mod presenter;
 pub mod core;
-pub mod types;
\ No newline at end of file
+pub mod types;
+pub mod capture;
+pub mod batch;
+pub mod graph;
+pub mod glyph_atlas;
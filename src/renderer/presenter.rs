@@ -5,20 +5,28 @@ use std::ffi::c_void;
 use hal::window::{Extent2D, Surface};
 use hal::device::Device;
 use ash::vk;
-use crate::renderer::core::RendererCore;
+use crate::renderer::core::{RendererCore, run_with_device};
 use crate::renderer::allocator::{Allocator, GfxAllocator};
+#[cfg(feature = "headless")]
+use crate::renderer::types::Image;
+use crate::renderer::capture::CapturedFrame;
 
 pub const DIMS: Extent2D = Extent2D { width: 1024, height: 768 };
 const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+#[cfg(feature = "headless")]
+const OFFSCREEN_IMAGE_COUNT: usize = 2;
 
 type ImageIndex = u32;
 
 pub(crate) trait Presenter<B: hal::Backend> : Send + Sync {
-    fn images(&mut self) -> (Vec<B::Image>, hal::format::Format);
+    fn images(&self) -> (Vec<&B::Image>, hal::format::Format);
     fn semaphores(&mut self) -> (Option<&B::Semaphore>, Option<&B::Semaphore>);
     fn acquire_image(&mut self) -> Result<u32, String>;
     fn present(&mut self) -> Result<(), String>;
     fn viewport(&self) -> hal::pso::Viewport;
+    fn needs_recreation(&self) -> bool;
+    fn recreate_swapchain(&mut self) -> Result<(), String>;
+    fn capture_frame(&mut self) -> Result<CapturedFrame, String>;
 }
 
 pub struct VulkanXrSessionCreateInfo {
@@ -247,9 +255,9 @@ impl <B: hal::Backend> XrPresenter<B, GfxAllocator<B>> {
 }
 
 impl Presenter<gfx_backend_vulkan::Backend> for XrPresenter<gfx_backend_vulkan::Backend, GfxAllocator<gfx_backend_vulkan::Backend>> {
-    fn images(&mut self) -> (Vec<gfx_backend_vulkan::native::Image>, hal::format::Format) {
+    fn images(&self) -> (Vec<&gfx_backend_vulkan::native::Image>, hal::format::Format) {
         (
-            self.vulkan_xr_session.swapchain_images.take().unwrap(),
+            self.vulkan_xr_session.swapchain_images.as_ref().unwrap().iter().collect(),
             self.vulkan_xr_session.swapchain_format.clone()
         )
     }
@@ -346,6 +354,19 @@ impl Presenter<gfx_backend_vulkan::Backend> for XrPresenter<gfx_backend_vulkan::
     fn viewport(&self) -> hal::pso::Viewport {
         self.viewport.clone()
     }
+
+    // the runtime owns the xr swapchain and its resolution never follows the desktop window
+    fn needs_recreation(&self) -> bool {
+        false
+    }
+
+    fn recreate_swapchain(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn capture_frame(&mut self) -> Result<CapturedFrame, String> {
+        Err(String::from("frame capture is not supported for xr sessions"))
+    }
 }
 
 pub(crate) struct MonitorPresenter<B: hal::Backend, A: Allocator<B>> {
@@ -355,11 +376,12 @@ pub(crate) struct MonitorPresenter<B: hal::Backend, A: Allocator<B>> {
 
     acquired_image: Option<ImageIndex>,
     viewport: hal::pso::Viewport,
+    needs_recreation: bool,
 }
 
 impl <B: hal::Backend> MonitorPresenter<B, GfxAllocator<B>> {
     pub fn new(core: &Arc<RwLock<RendererCore<B>>>, allocator: &Arc<RwLock<GfxAllocator<B>>>) -> Self {
-        let swapchain = SxeSwapchain::new(core);
+        let swapchain = SxeSwapchain::new(core, Self::window_extent(core));
         let viewport = Self::create_viewport(&swapchain);
         Self {
             core: Arc::clone(core),
@@ -367,6 +389,16 @@ impl <B: hal::Backend> MonitorPresenter<B, GfxAllocator<B>> {
             swapchain,
             acquired_image: None,
             viewport,
+            needs_recreation: false,
+        }
+    }
+
+    fn window_extent(core: &Arc<RwLock<RendererCore<B>>>) -> Extent2D {
+        let window_size = core.read().unwrap().backend.window().inner_size();
+
+        Extent2D {
+            width: window_size.width,
+            height: window_size.height,
         }
     }
 
@@ -384,9 +416,9 @@ impl <B: hal::Backend> MonitorPresenter<B, GfxAllocator<B>> {
 }
 
 impl <B: hal::Backend> Presenter<B> for MonitorPresenter<B, GfxAllocator<B>> {
-    fn images(&mut self) -> (Vec<B::Image>, hal::format::Format) {
+    fn images(&self) -> (Vec<&B::Image>, hal::format::Format) {
         (
-            self.swapchain.backbuffer.take().unwrap(),
+            self.swapchain.backbuffer.as_ref().unwrap().iter().collect(),
             self.swapchain.format.clone()
         )
     }
@@ -399,13 +431,23 @@ impl <B: hal::Backend> Presenter<B> for MonitorPresenter<B, GfxAllocator<B>> {
     }
 
     fn acquire_image(&mut self) -> Result<u32, String> {
-        use hal::window::Swapchain;
-
         if let Some(image_index) = self.acquired_image {
             return Err(format!("image {} already acquired without presenting", image_index));
         }
 
-        let (image_index, _maybe_suboptimal) = self.swapchain.acquire_image()?;
+        let (image_index, maybe_suboptimal) = match self.swapchain.acquire_image() {
+            Ok(acquired) => acquired,
+            Err(hal::window::AcquireError::OutOfDate) => {
+                self.needs_recreation = true;
+                return Err(String::from("swapchain is out of date"));
+            },
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // a suboptimal image can still be presented, so we finish this frame and rebuild before the next one
+        if maybe_suboptimal.is_some() {
+            self.needs_recreation = true;
+        }
 
         self.acquired_image = Some(image_index);
 
@@ -426,12 +468,223 @@ impl <B: hal::Backend> Presenter<B> for MonitorPresenter<B, GfxAllocator<B>> {
             .queue_group
             .queues[0];
 
-        self.swapchain.present(queue, image_index)
+        match self.swapchain.present(queue, image_index) {
+            Ok(None) => Ok(()),
+            Ok(Some(_suboptimal)) | Err(hal::window::PresentError::OutOfDate) => {
+                self.needs_recreation = true;
+                Ok(())
+            },
+            Err(e) => Err(e.to_string()),
+        }
     }
 
     fn viewport(&self) -> hal::pso::Viewport {
         self.viewport.clone()
     }
+
+    fn needs_recreation(&self) -> bool {
+        self.needs_recreation
+    }
+
+    fn recreate_swapchain(&mut self) -> Result<(), String> {
+        let dimensions = Self::window_extent(&self.core);
+
+        // a minimized window reports a zero sized surface, which we can't build a swapchain for
+        if dimensions.width == 0 || dimensions.height == 0 {
+            return Err(String::from("window has no drawable area"));
+        }
+
+        self.swapchain.recreate(dimensions)?;
+        self.viewport = Self::create_viewport(&self.swapchain);
+        self.acquired_image = None;
+        self.needs_recreation = false;
+
+        Ok(())
+    }
+
+    // swapchain images can't be touched once they're handed back to the presentation engine, so this
+    // has to be called after drawing and before presenting
+    fn capture_frame(&mut self) -> Result<CapturedFrame, String> {
+        let image_index = self
+            .acquired_image
+            .ok_or(String::from("frames can only be captured between acquiring and presenting an image"))?;
+
+        if !self.swapchain.image_usage.contains(hal::image::Usage::TRANSFER_SRC) {
+            return Err(String::from("the surface doesn't allow copying from swapchain images, so frames can't be captured"));
+        }
+
+        run_with_device(&self.core, |device| device.wait_idle())
+            .map_err(|e| e.to_string())?;
+
+        let image = &self.swapchain.backbuffer.as_ref().unwrap()[image_index as usize];
+        let pixels = self.allocator.write().unwrap().read_image(
+            image,
+            hal::image::Layout::Present,
+            self.swapchain.extent.width,
+            self.swapchain.extent.height,
+        );
+
+        Ok(CapturedFrame::new(
+            self.swapchain.extent.width,
+            self.swapchain.extent.height,
+            self.swapchain.format,
+            pixels,
+        ))
+    }
+}
+
+#[cfg(feature = "headless")]
+pub(crate) struct OffscreenPresenter<B: hal::Backend, A: Allocator<B>> {
+    core: Arc<RwLock<RendererCore<B>>>,
+    allocator: Arc<RwLock<A>>,
+    color_targets: Vec<Image<B>>,
+    format: hal::format::Format,
+    extent: Extent2D,
+
+    next_image: ImageIndex,
+    acquired_image: Option<ImageIndex>,
+    last_presented_image: Option<ImageIndex>,
+    viewport: hal::pso::Viewport,
+}
+
+#[cfg(feature = "headless")]
+impl <B: hal::Backend> OffscreenPresenter<B, GfxAllocator<B>> {
+    pub fn new(core: &Arc<RwLock<RendererCore<B>>>, allocator: &Arc<RwLock<GfxAllocator<B>>>, extent: Extent2D) -> Self {
+        let format = hal::format::Format::Rgba8Srgb;
+
+        let color_targets = (0..OFFSCREEN_IMAGE_COUNT)
+            .map(|_| {
+                allocator.write().unwrap().alloc_image(
+                    extent.width,
+                    extent.height,
+                    format,
+                    hal::image::Usage::COLOR_ATTACHMENT | hal::image::Usage::TRANSFER_SRC,
+                    hal::format::Aspects::COLOR,
+                )
+            })
+            .collect();
+
+        Self {
+            core: Arc::clone(core),
+            allocator: Arc::clone(allocator),
+            color_targets,
+            format,
+            extent,
+            next_image: 0,
+            acquired_image: None,
+            last_presented_image: None,
+            viewport: Self::create_viewport(extent),
+        }
+    }
+
+    fn create_viewport(extent: Extent2D) -> hal::pso::Viewport {
+        hal::pso::Viewport {
+            rect: hal::pso::Rect {
+                x: 0,
+                y: 0,
+                w: extent.width as _,
+                h: extent.height as _,
+            },
+            depth: 0.0..1.0,
+        }
+    }
+
+    // Returns the frame currently being drawn, or the last presented one if there isn't one, as tightly
+    // packed RGBA8 rows starting with the top row.
+    pub fn read_pixels(&mut self) -> Result<Vec<u8>, String> {
+        let image_index = self
+            .acquired_image
+            .or(self.last_presented_image)
+            .ok_or(String::from("no frame has been drawn yet"))?;
+
+        // the drawer submits without semaphores so there is nothing finer grained to wait on
+        run_with_device(&self.core, |device| device.wait_idle())
+            .map_err(|e| e.to_string())?;
+
+        // the drawer's render pass leaves its color attachment in the present layout
+        let image = self.color_targets[image_index as usize].image.as_ref().unwrap();
+        Ok(self.allocator.write().unwrap().read_image(
+            image,
+            hal::image::Layout::Present,
+            self.extent.width,
+            self.extent.height,
+        ))
+    }
+}
+
+#[cfg(feature = "headless")]
+impl <B: hal::Backend> Presenter<B> for OffscreenPresenter<B, GfxAllocator<B>> {
+    fn images(&self) -> (Vec<&B::Image>, hal::format::Format) {
+        (
+            self.color_targets.iter().map(|target| target.image.as_ref().unwrap()).collect(),
+            self.format.clone()
+        )
+    }
+
+    fn semaphores(&mut self) -> (Option<&B::Semaphore>, Option<&B::Semaphore>) {
+        (None, None)
+    }
+
+    fn acquire_image(&mut self) -> Result<u32, String> {
+        if let Some(image_index) = self.acquired_image {
+            return Err(format!("image {} already acquired without presenting", image_index));
+        }
+
+        let image_index = self.next_image;
+        self.next_image = (self.next_image + 1) % self.color_targets.len() as ImageIndex;
+        self.acquired_image = Some(image_index);
+
+        Ok(image_index)
+    }
+
+    fn present(&mut self) -> Result<(), String> {
+        let image_index = self
+            .acquired_image
+            .take()
+            .ok_or(String::from("no image acquired to present to"))?;
+
+        self.last_presented_image = Some(image_index);
+
+        Ok(())
+    }
+
+    fn viewport(&self) -> hal::pso::Viewport {
+        self.viewport.clone()
+    }
+
+    // there is no surface to fall out of date with, the targets keep the size they were created with
+    fn needs_recreation(&self) -> bool {
+        false
+    }
+
+    fn recreate_swapchain(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn capture_frame(&mut self) -> Result<CapturedFrame, String> {
+        let pixels = self.read_pixels()?;
+
+        Ok(CapturedFrame::new(
+            self.extent.width,
+            self.extent.height,
+            self.format,
+            pixels,
+        ))
+    }
+}
+
+#[cfg(feature = "headless")]
+impl <B: hal::Backend, A: Allocator<B>> Drop for OffscreenPresenter<B, A> {
+    fn drop(&mut self) {
+        let color_targets = &mut self.color_targets;
+        run_with_device(&self.core, |device| {
+            device.wait_idle().unwrap();
+
+            for target in color_targets.iter_mut() {
+                target.drop(device);
+            }
+        });
+    }
 }
 
 pub(crate) struct SxeSwapchain<B: hal::Backend> {
@@ -440,13 +693,37 @@ pub(crate) struct SxeSwapchain<B: hal::Backend> {
     pub backbuffer: Option<Vec<B::Image>>,
     pub format: hal::format::Format,
     pub extent: hal::image::Extent,
+    // frames can only be captured if this has TRANSFER_SRC
+    pub image_usage: hal::image::Usage,
     pub present_semaphores: Vec<B::Semaphore>,
     pub acquire_semaphores: Vec<B::Semaphore>,
     pub current_sem_index: usize,
 }
 
 impl<B: hal::Backend> SxeSwapchain<B> {
-    fn new(core: &Arc<RwLock<RendererCore<B>>>) -> Self {
+    fn new(core: &Arc<RwLock<RendererCore<B>>>, dimensions: Extent2D) -> Self {
+        let (swapchain, backbuffer, format, extent, image_usage) = Self::create_swapchain(core, dimensions, None)
+            .expect("Can't create swapchain");
+
+        let mut swapchain_state = Self {
+            core: Arc::clone(core),
+            swapchain: Some(swapchain),
+            backbuffer: Some(backbuffer),
+            format,
+            extent,
+            image_usage,
+            present_semaphores: vec![],
+            acquire_semaphores: vec![],
+            current_sem_index: 0,
+        };
+
+        swapchain_state.create_semaphores();
+        swapchain_state
+    }
+
+    fn create_swapchain(core: &Arc<RwLock<RendererCore<B>>>, dimensions: Extent2D, old_swapchain: Option<B::Swapchain>)
+        -> Result<(B::Swapchain, Vec<B::Image>, hal::format::Format, hal::image::Extent, hal::image::Usage), String>
+    {
         let caps = core
             .read()
             .unwrap()
@@ -477,9 +754,15 @@ impl<B: hal::Backend> SxeSwapchain<B> {
                 .unwrap_or(formats[0])
         });
 
-        let swap_config = hal::window::SwapchainConfig::from_caps(&caps, format, DIMS);
+        let mut swap_config = hal::window::SwapchainConfig::from_caps(&caps, format, dimensions);
+
+        // lets frames be copied back to the cpu for screenshots
+        if caps.usage.contains(hal::image::Usage::TRANSFER_SRC) {
+            swap_config.image_usage |= hal::image::Usage::TRANSFER_SRC;
+        }
 
         let extent = swap_config.extent.to_extent();
+        let image_usage = swap_config.image_usage;
 
         let (swapchain, backbuffer) = unsafe {
             let surface_arc = Arc::clone(&core
@@ -497,33 +780,72 @@ impl<B: hal::Backend> SxeSwapchain<B> {
                 .device
                 .read()
                 .unwrap()
-                .create_swapchain(writable_surface.as_mut().unwrap(), swap_config, None)
-        }.expect("Can't create swapchain");
+                .create_swapchain(writable_surface.as_mut().unwrap(), swap_config, old_swapchain)
+        }.map_err(|e| e.to_string())?;
+
+        Ok((swapchain, backbuffer, format, extent, image_usage))
+    }
 
+    // Rebuilds the swapchain against the current surface size. The old swapchain is handed to the
+    // backend so it can be retired, and the previous backbuffer is replaced; callers need to rebuild
+    // anything that was created from the old images. The old swapchain is gone even if creating the
+    // new one fails, so until a later recreate works there's no swapchain to acquire from.
+    fn recreate(&mut self, dimensions: Extent2D) -> Result<(), String> {
+        self.core
+            .read()
+            .unwrap()
+            .device
+            .device
+            .read()
+            .unwrap()
+            .wait_idle()
+            .map_err(|e| e.to_string())?;
+
+        // its images go with it
+        self.backbuffer = None;
+
+        let (swapchain, backbuffer, format, extent, image_usage) = Self::create_swapchain(&self.core, dimensions, self.swapchain.take())?;
+
+        self.swapchain = Some(swapchain);
+        self.backbuffer = Some(backbuffer);
+        self.format = format;
+        self.extent = extent;
+        self.image_usage = image_usage;
+
+        // the image count can change between swapchains so the semaphores are rebuilt to match
+        self.destroy_semaphores();
+        self.create_semaphores();
+
+        Ok(())
+    }
+
+    fn create_semaphores(&mut self) {
         // TODO -> this is duplicated in Drawer::new
-        let iter_count = if backbuffer.len() != 0 {
-            backbuffer.len()
-        } else {
-            1 // GL can have zero
+        let iter_count = match self.backbuffer.as_ref() {
+            Some(backbuffer) if backbuffer.len() != 0 => backbuffer.len(),
+            _ => 1, // GL can have zero
         };
 
-        let mut acquire_semaphores = vec![];
-        let mut present_semaphores = vec![];
-
         for _ in 0..iter_count {
-            acquire_semaphores.push(core.read().unwrap().device.device.read().unwrap().create_semaphore().unwrap());
-            present_semaphores.push(core.read().unwrap().device.device.read().unwrap().create_semaphore().unwrap());
+            self.acquire_semaphores.push(self.core.read().unwrap().device.device.read().unwrap().create_semaphore().unwrap());
+            self.present_semaphores.push(self.core.read().unwrap().device.device.read().unwrap().create_semaphore().unwrap());
         }
 
-        Self {
-            core: Arc::clone(core),
-            swapchain: Some(swapchain),
-            backbuffer: Some(backbuffer),
-            format,
-            extent,
-            present_semaphores,
-            acquire_semaphores,
-            current_sem_index: 0,
+        self.current_sem_index = 0;
+    }
+
+    fn destroy_semaphores(&mut self) {
+        let device_lock = &self.core.read().unwrap().device.device;
+        let device = device_lock.read().unwrap();
+
+        unsafe {
+            for acquire_semaphore in self.acquire_semaphores.drain(..) {
+                device.destroy_semaphore(acquire_semaphore);
+            }
+
+            for present_semaphore in self.present_semaphores.drain(..) {
+                device.destroy_semaphore(present_semaphore);
+            }
         }
     }
 
@@ -535,57 +857,42 @@ impl<B: hal::Backend> SxeSwapchain<B> {
         }
     }
 
-    pub fn acquire_image(&mut self) -> Result<(u32, Option<hal::window::Suboptimal>), String> {
+    pub fn acquire_image(&mut self) -> Result<(u32, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         use hal::window::Swapchain;
 
+        // a failed recreate left us without one, which the presenter treats like any other out of date swapchain
+        let swapchain = self.swapchain.as_mut().ok_or(hal::window::AcquireError::OutOfDate)?;
+
         self.next_sem_index();
         let acquire_semaphore = &self.acquire_semaphores[self.current_sem_index];
 
         unsafe {
-            self
-                .swapchain
-                .as_mut()
-                .unwrap()
-                .acquire_image(!0, Some(acquire_semaphore), None)
-                .map_err(|e| e.to_string())
+            swapchain.acquire_image(!0, Some(acquire_semaphore), None)
         }
     }
 
-    pub fn present(&mut self, queue: &mut B::CommandQueue, image_index: u32) -> Result<(), String> {
+    pub fn present(&mut self, queue: &mut B::CommandQueue, image_index: u32) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError> {
         use hal::window::Swapchain;
 
+        let swapchain = self.swapchain.as_ref().ok_or(hal::window::PresentError::OutOfDate)?;
         let present_semaphore = &self.present_semaphores[self.current_sem_index];
 
         unsafe {
-            self
-                .swapchain
-                .as_ref()
-                .unwrap()
-                .present(
-                    queue,
-                    image_index,
-                    Some(&*present_semaphore)
-                )
-                .map(|v| ())
-                .map_err(|e| e.to_string())
+            swapchain.present(queue, image_index, Some(&*present_semaphore))
         }
     }
 }
 
 impl <B: hal::Backend> Drop for SxeSwapchain<B> {
     fn drop(&mut self) {
+        self.destroy_semaphores();
+
         unsafe {
             let device_lock = &mut self.core.write().unwrap().device.device;
             let device = device_lock.write().unwrap();
 
-            device.destroy_swapchain(self.swapchain.take().unwrap());
-
-            for acquire_semaphore in self.acquire_semaphores.drain(..) {
-                device.destroy_semaphore(acquire_semaphore);
-            }
-
-            for present_semaphore in self.present_semaphores.drain(..) {
-                device.destroy_semaphore(present_semaphore);
+            if let Some(swapchain) = self.swapchain.take() {
+                device.destroy_swapchain(swapchain);
             }
         }
     }
@@ -22,7 +22,7 @@ impl std::fmt::Display for RenderKey {
 impl From<&Option<crate::components::texture::Texture>> for RenderKey {
     fn from(texture: &Option<crate::components::texture::Texture>) -> Self {
         let tex_path = match texture {
-            Some(tex) => tex.path.clone(),
+            Some(tex) => tex.render_name(),
             None => String::from("NULL_TEX"),
         };
 
@@ -32,7 +32,13 @@ impl From<&Option<crate::components::texture::Texture>> for RenderKey {
 
 impl From<&crate::components::texture::Texture> for RenderKey {
     fn from(texture: &crate::components::texture::Texture) -> Self {
-        RenderKey::new(ModuleName::BackendRenderer, ResourceType::Texture(texture.path.clone()))
+        RenderKey::new(ModuleName::BackendRenderer, ResourceType::Texture(texture.render_name()))
+    }
+}
+
+impl From<&crate::components::material::Material> for RenderKey {
+    fn from(material: &crate::components::material::Material) -> Self {
+        RenderKey::new(ModuleName::BackendRenderer, ResourceType::Material(material.textures_render_name()))
     }
 }
 
@@ -54,12 +60,14 @@ impl std::fmt::Display for ModuleName {
 #[derive(Eq)]
 pub enum ResourceType {
     Texture(String),
+    Material(String),
 }
 
 impl std::fmt::Display for ResourceType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let to_print = match self {
             ResourceType::Texture(path) => format!("texture-{}", path),
+            ResourceType::Material(textures) => format!("material-{}", textures),
         };
 
         write!(f, "{}", to_print)
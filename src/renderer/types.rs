@@ -2,8 +2,8 @@ use std::sync::{Arc, RwLock};
 use crate::utils::{any_as_u8_slice, data_path};
 use crate::renderer::core::RendererCore;
 use hal::device::Device;
-use std::fs::File;
-use std::io::BufReader;
+use image::Pixel;
+use crate::components::texture::{ColorSpace, SamplerOptions};
 use std::ops::DerefMut;
 
 pub(crate) struct Buffer<B: hal::Backend> {
@@ -70,6 +70,27 @@ impl<B: hal::Backend> Buffer<B> {
             device.unmap_memory(self.buffer_memory.as_ref().unwrap());
         }
     }
+
+    pub fn read_data(&self, core: &Arc<RwLock<RendererCore<B>>>, offset: u64, size: u64) -> Vec<u8> {
+        let device_lock = &core.read().unwrap().device.device;
+        let device = device_lock.read().unwrap();
+
+        assert!(offset + size <= self.size);
+
+        unsafe {
+            let mem_segment = hal::memory::Segment {
+                offset,
+                size: Some(size),
+            };
+            let mapping = device.map_memory(self.buffer_memory.as_ref().unwrap(), mem_segment).unwrap();
+
+            let data = std::slice::from_raw_parts(mapping as *const u8, size as usize).to_vec();
+
+            device.unmap_memory(self.buffer_memory.as_ref().unwrap());
+
+            data
+        }
+    }
 }
 
 pub(crate) struct Uniform<B: hal::Backend> {
@@ -121,19 +142,16 @@ impl<B: hal::Backend> Image<B> {
 
 
 pub(crate) struct Texture<B: hal::Backend> {
-    pub desc_set: DescSet<B>,
     pub sampler: Option<B::Sampler>,
     pub image: Image<B>,
 }
 
 impl <B: hal::Backend> Texture<B> {
-    pub fn new(desc_set: DescSet<B>,
-               sampler: Option<B::Sampler>,
+    pub fn new(sampler: Option<B::Sampler>,
                image: Image<B>)
                -> Self
     {
         Self {
-            desc_set,
             sampler,
             image,
         }
@@ -147,47 +165,246 @@ impl <B: hal::Backend> Texture<B> {
     }
 }
 
+pub(crate) struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    // where the level starts in the texture data and the padded size of each of its rows, in bytes
+    pub offset: u64,
+    pub row_pitch: u32,
+}
+
 pub(crate) struct TextureData {
     pub width: u32,
     pub height: u32,
     pub data: Vec<u8>,
     pub format: hal::format::Format,
+    pub pixel_size: usize,
+    pub levels: Vec<MipLevel>,
 }
 
 impl TextureData {
-    pub fn load(img_path: &str, row_alignment_mask: u32) -> Self {
-        let img_file = File::open(data_path(img_path)).unwrap();
-        let img_reader = BufReader::new(img_file);
-        let img = image::load(img_reader, image::JPEG)
-            .unwrap()
-            .to_rgba();
-
-        let (width, height) = img.dimensions();
-
-        // TODO -> duplicated in ImageState::new
-        let image_stride = 4_usize;
-        let row_pitch = (width * image_stride as u32 + row_alignment_mask) & !row_alignment_mask;
-
-        let size = (width * height) as usize * image_stride;
-        let mut data: Vec<u8> = vec![0u8; size];
-
-        for y in 0..height as usize {
-            let row = &(*img)[y * (width as usize) * image_stride..(y + 1) * (width as usize) * image_stride];
-            let start = y * row_pitch as usize;
-            let count = width as usize * image_stride;
-            let range = start..(start + count);
-            data.splice(range, row.iter().map(|x| *x));
+    pub fn load(img_path: &str, options: &SamplerOptions, row_alignment_mask: u32) -> Result<Self, String> {
+        let bytes = std::fs::read(data_path(img_path))
+            .map_err(|e| format!("failed to read texture {}: {}", img_path, e))?;
+
+        match image_format(img_path, &bytes)? {
+            // hdr images stay as floats instead of getting tone mapped down to 8 bits
+            image::ImageFormat::HDR => {
+                let decoder = image::hdr::HDRDecoder::new(&bytes[..])
+                    .map_err(|e| format!("failed to decode texture {}: {}", img_path, e))?;
+                let metadata = decoder.metadata();
+                let pixels = decoder
+                    .read_image_hdr()
+                    .map_err(|e| format!("failed to decode texture {}: {}", img_path, e))?;
+
+                let data = pixels
+                    .iter()
+                    .flat_map(|pixel| {
+                        let rgb = pixel.channels();
+                        vec![rgb[0], rgb[1], rgb[2], 1.0]
+                    })
+                    .flat_map(|channel| channel.to_ne_bytes().to_vec())
+                    .collect::<Vec<u8>>();
+
+                Ok(Self::from_pixels(metadata.width, metadata.height, &data, PixelKind::Float32, options.mipmaps, row_alignment_mask))
+            },
+            format => {
+                let img = image::load_from_memory_with_format(&bytes, format)
+                    .map_err(|e| format!("failed to decode texture {}: {}", img_path, e))?
+                    .to_rgba();
+
+                let (width, height) = img.dimensions();
+
+                Ok(Self::from_rgba(width, height, &*img, options, row_alignment_mask))
+            }
+        }
+    }
+
+    // `pixels` are tightly packed rgba8 rows
+    pub fn from_rgba(width: u32, height: u32, pixels: &[u8], options: &SamplerOptions, row_alignment_mask: u32) -> Self {
+        let kind = match options.color_space {
+            ColorSpace::Srgb => PixelKind::Srgb8,
+            ColorSpace::Linear => PixelKind::Unorm8,
+        };
+
+        Self::from_pixels(width, height, pixels, kind, options.mipmaps, row_alignment_mask)
+    }
+
+    // Builds the mip chain with a box filter and lays every level out one after the other, with each row
+    // and level starting on the alignment the device wants for buffer copies.
+    fn from_pixels(width: u32, height: u32, pixels: &[u8], kind: PixelKind, mipmaps: bool, row_alignment_mask: u32) -> Self {
+        let pixel_size = kind.size();
+        let offset_alignment_mask = std::cmp::max(row_alignment_mask as u64 + 1, pixel_size as u64) - 1;
+
+        let level_count = if mipmaps {
+            32 - std::cmp::max(width, height).leading_zeros()
+        } else {
+            1
+        };
+
+        let mut data = Vec::new();
+        let mut levels = Vec::new();
+
+        let mut level_pixels = pixels.to_vec();
+        let (mut level_width, mut level_height) = (width, height);
+
+        for level in 0..level_count {
+            if level > 0 {
+                level_pixels = downsample(&level_pixels, level_width, level_height, kind);
+                level_width = std::cmp::max(level_width / 2, 1);
+                level_height = std::cmp::max(level_height / 2, 1);
+            }
+
+            let row_size = level_width as usize * pixel_size;
+            let row_pitch = (row_size as u32 + row_alignment_mask) & !row_alignment_mask;
+            let offset = (data.len() as u64 + offset_alignment_mask) & !offset_alignment_mask;
+
+            data.resize(offset as usize + level_height as usize * row_pitch as usize, 0u8);
+
+            for y in 0..level_height as usize {
+                let row = &level_pixels[y * row_size..(y + 1) * row_size];
+                let start = offset as usize + y * row_pitch as usize;
+                data[start..(start + row_size)].copy_from_slice(row);
+            }
+
+            levels.push(MipLevel {
+                width: level_width,
+                height: level_height,
+                offset,
+                row_pitch,
+            });
         }
 
         Self {
             width,
             height,
             data,
-            format: hal::format::Format::Rgba8Srgb,
+            format: kind.format(),
+            pixel_size,
+            levels,
         }
     }
 }
 
+#[derive(Clone, Copy)]
+enum PixelKind {
+    Srgb8,
+    Unorm8,
+    Float32,
+}
+
+impl PixelKind {
+    fn size(&self) -> usize {
+        match self {
+            PixelKind::Srgb8 | PixelKind::Unorm8 => 4,
+            PixelKind::Float32 => 16,
+        }
+    }
+
+    fn format(&self) -> hal::format::Format {
+        match self {
+            PixelKind::Srgb8 => hal::format::Format::Rgba8Srgb,
+            PixelKind::Unorm8 => hal::format::Format::Rgba8Unorm,
+            PixelKind::Float32 => hal::format::Format::Rgba32Sfloat,
+        }
+    }
+
+    // srgb colors are converted to linear so averaging them doesn't darken the smaller levels
+    fn read(&self, pixels: &[u8], index: usize) -> [f32; 4] {
+        let mut texel = [0.0; 4];
+        let start = index * self.size();
+
+        for channel in 0..4 {
+            texel[channel] = match self {
+                PixelKind::Srgb8 if channel < 3 => srgb_to_linear(pixels[start + channel] as f32 / 255.0),
+                PixelKind::Srgb8 | PixelKind::Unorm8 => pixels[start + channel] as f32 / 255.0,
+                PixelKind::Float32 => {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(&pixels[start + channel * 4..start + channel * 4 + 4]);
+                    f32::from_ne_bytes(bytes)
+                }
+            };
+        }
+
+        texel
+    }
+
+    fn write(&self, pixels: &mut Vec<u8>, texel: [f32; 4]) {
+        for channel in 0..4 {
+            match self {
+                PixelKind::Srgb8 if channel < 3 => pixels.push((linear_to_srgb(texel[channel]) * 255.0).round() as u8),
+                PixelKind::Srgb8 | PixelKind::Unorm8 => pixels.push((texel[channel] * 255.0).round() as u8),
+                PixelKind::Float32 => pixels.extend_from_slice(&texel[channel].to_ne_bytes()),
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// halves a level by averaging 2x2 blocks, odd edges reuse their last row or column
+fn downsample(pixels: &[u8], width: u32, height: u32, kind: PixelKind) -> Vec<u8> {
+    let new_width = std::cmp::max(width / 2, 1);
+    let new_height = std::cmp::max(height / 2, 1);
+    let mut downsampled = Vec::with_capacity((new_width * new_height) as usize * kind.size());
+
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let xs = [std::cmp::min(x * 2, width - 1), std::cmp::min(x * 2 + 1, width - 1)];
+            let ys = [std::cmp::min(y * 2, height - 1), std::cmp::min(y * 2 + 1, height - 1)];
+
+            let mut sum = [0.0; 4];
+            for &sample_y in ys.iter() {
+                for &sample_x in xs.iter() {
+                    let texel = kind.read(pixels, (sample_y * width + sample_x) as usize);
+                    for channel in 0..4 {
+                        sum[channel] += texel[channel];
+                    }
+                }
+            }
+
+            kind.write(&mut downsampled, [sum[0] / 4.0, sum[1] / 4.0, sum[2] / 4.0, sum[3] / 4.0]);
+        }
+    }
+
+    downsampled
+}
+
+// the format is sniffed from the contents first, tga has no magic number so the extension is the fallback
+fn image_format(img_path: &str, bytes: &[u8]) -> Result<image::ImageFormat, String> {
+    if let Ok(format) = image::guess_format(bytes) {
+        return Ok(format);
+    }
+
+    let extension = std::path::Path::new(img_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_ref().map(|extension| extension.as_str()) {
+        Some("tga") => Ok(image::ImageFormat::TGA),
+        Some("png") => Ok(image::ImageFormat::PNG),
+        Some("jpg") | Some("jpeg") => Ok(image::ImageFormat::JPEG),
+        Some("bmp") => Ok(image::ImageFormat::BMP),
+        Some("hdr") => Ok(image::ImageFormat::HDR),
+        _ => Err(format!("can't tell what format texture {} is in", img_path)),
+    }
+}
+
 pub(crate) struct DescSetWrite<WI> {
     pub binding: hal::pso::DescriptorBinding,
     pub array_offset: hal::pso::DescriptorArrayIndex,
@@ -0,0 +1,89 @@
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use cgmath::{InnerSpace, Vector3, Zero};
+
+use crate::components::camera::Camera;
+use crate::components::input_map::InputMap;
+use crate::components::input_state::InputState;
+use crate::components::transform::Transform;
+use crate::systems::schedule::{Access, System};
+use crate::timing::Time;
+
+use legion::World;
+use legion::query::{Read, Write, IntoQuery, Query};
+
+// in units per second
+const CAMERA_SPEED: f32 = 10.0;
+
+// Flies the camera around with the movement actions, scaled by how long the last frame took so the
+// speed doesn't depend on the frame rate or on key repeat, and turns it with the look axes.
+pub struct CameraController {
+    pub time: Arc<RwLock<Time>>,
+}
+
+impl CameraController {
+    pub fn new(time: &Arc<RwLock<Time>>) -> Self {
+        Self {
+            time: Arc::clone(time),
+        }
+    }
+
+}
+
+impl System for CameraController {
+    fn name(&self) -> &str {
+        "camera_controller"
+    }
+
+    fn access(&self) -> Access {
+        Access::new()
+            .reads::<InputState>()
+            .reads::<InputMap>()
+            .reads::<Camera>()
+            .writes::<Transform>()
+    }
+
+    fn run(&mut self, world: &World) {
+        let input = match <Read<InputState>>::query().iter(world).next() {
+            Some(input) => input.clone(),
+            None => return,
+        };
+        let input_map = match <Read<InputMap>>::query().iter(world).next() {
+            Some(input_map) => input_map.clone(),
+            None => return,
+        };
+
+        // the mouse moved by however much it moved, so looking around isn't scaled by time
+        let look = (-input_map.axis("look_x", &input), -input_map.axis("look_y", &input));
+
+        let moves = [
+            ("move_forward", Vector3::new(0.0, 0.0, 1.0)),
+            ("move_back", Vector3::new(0.0, 0.0, -1.0)),
+            ("move_left", Vector3::new(-1.0, 0.0, 0.0)),
+            ("move_right", Vector3::new(1.0, 0.0, 0.0)),
+            ("move_up", Vector3::new(0.0, 1.0, 0.0)),
+            ("move_down", Vector3::new(0.0, -1.0, 0.0)),
+        ];
+
+        let direction = moves
+            .iter()
+            .filter(|(action, _)| input_map.is_held(action, &input))
+            .fold(Vector3::zero(), |direction: Vector3<f32>, (_, along)| direction + along);
+
+        // diagonals shouldn't be faster than going straight
+        let movement = if direction.magnitude2() > 0.0 {
+            let delta_seconds = self.time.read().unwrap().delta_time as f32 / 1000.0;
+            direction.normalize() * CAMERA_SPEED * delta_seconds
+        } else {
+            Vector3::zero()
+        };
+
+        for (_camera, transform) in <(Read<Camera>, Write<Transform>)>::query().iter(world) {
+            transform.rotate(look.0, look.1, 0.0);
+            transform.translate(movement);
+        }
+    }
+}
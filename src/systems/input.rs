@@ -0,0 +1,48 @@
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use crate::components::config::Config;
+use crate::components::input_map::InputMap;
+use crate::components::input_state::InputState;
+use crate::events::event_handler::EventHandler;
+use crate::primitives::two_d::widget::UiRoot;
+use crate::systems::schedule::{Access, System};
+use crate::timing::Time;
+
+use legion::World;
+
+// Hands the events the window sent since last frame to the event handler, which passes them on to the
+// ui, the input state and the input map.
+pub struct InputSystem {
+    event_handler: Arc<RwLock<EventHandler>>,
+    time: Arc<RwLock<Time>>,
+}
+
+impl InputSystem {
+    pub fn new(event_handler: &Arc<RwLock<EventHandler>>, time: &Arc<RwLock<Time>>) -> Self {
+        Self {
+            event_handler: Arc::clone(event_handler),
+            time: Arc::clone(time),
+        }
+    }
+}
+
+impl System for InputSystem {
+    fn name(&self) -> &str {
+        "input"
+    }
+
+    fn access(&self) -> Access {
+        Access::new()
+            .writes::<UiRoot>()
+            .writes::<InputState>()
+            .writes::<InputMap>()
+            .writes::<Config>()
+    }
+
+    fn run(&mut self, world: &World) {
+        self.event_handler.write().unwrap().handle_events(world, &mut self.time.write().unwrap());
+    }
+}
@@ -1 +1,21 @@
-pub mod rotation;
\ No newline at end of file
+pub mod schedule;
+pub mod input;
+pub mod rotation;
+pub mod camera_controller;
+pub mod transform_propagation;
+
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use crate::systems::camera_controller::CameraController;
+use crate::systems::rotation::Rotation;
+use crate::systems::schedule::{Schedule, Stage};
+use crate::timing::Time;
+
+// Gameplay systems go here. The engine's own systems are already in the schedule, so only add to it.
+pub fn register(schedule: &mut Schedule, time: &Arc<RwLock<Time>>, seed: u64) {
+    schedule.add_system(Stage::Update, Rotation::new(time, seed));
+    schedule.add_system(Stage::Update, CameraController::new(time));
+}
@@ -3,10 +3,12 @@ use std::sync::{
     RwLock
 };
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 use crate::components::mesh::Mesh;
 use crate::components::transform::Transform;
+use crate::systems::schedule::{Access, System};
 use crate::timing::Time;
 
 use legion::World;
@@ -14,17 +16,31 @@ use legion::query::{Read, Write, IntoQuery, Query};
 
 pub struct Rotation {
     pub time: Arc<RwLock<Time>>,
+    // seeded so a replay spins everything the same way
+    rng: StdRng,
 }
 
 impl Rotation {
-    pub fn new(time: &Arc<RwLock<Time>>) -> Self {
+    pub fn new(time: &Arc<RwLock<Time>>, seed: u64) -> Self {
         Self {
             time: Arc::clone(time),
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
-    pub fn run(&self, world: &World) {
-       let mut rng = rand::thread_rng();
+}
+
+impl System for Rotation {
+    fn name(&self) -> &str {
+        "rotation"
+    }
+
+    fn access(&self) -> Access {
+        Access::new().reads::<Mesh>().writes::<Transform>()
+    }
+
+    fn run(&mut self, world: &World) {
+       let rng = &mut self.rng;
        let delta_time = self.time.read().unwrap().delta_time as f32 * 0.01;
 
        <(Write<Transform>, Read<Mesh>)>::query()
@@ -0,0 +1,145 @@
+use std::any::TypeId;
+
+use legion::World;
+
+// Stages run one after another every frame, in the order they're listed here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    // turning window events into state the rest of the frame can look at
+    Input,
+    Update,
+    // for anything that needs to see where everything ended up after update, like following a target
+    LateUpdate,
+    // last chance to change components before the drawer reads them
+    RenderExtract,
+}
+
+const STAGES: [Stage; 4] = [Stage::Input, Stage::Update, Stage::LateUpdate, Stage::RenderExtract];
+
+// The component types a system touches. Two systems can run at the same time as long as neither
+// writes something the other one reads or writes.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reads<T: 'static>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn writes<T: 'static>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        let writes_what_other_touches = self.writes
+            .iter()
+            .any(|id| other.reads.contains(id) || other.writes.contains(id));
+        let reads_what_other_writes = self.reads.iter().any(|id| other.writes.contains(id));
+
+        writes_what_other_touches || reads_what_other_writes
+    }
+}
+
+pub trait System: Send {
+    fn name(&self) -> &str;
+    // only looked at when the system is added, so it shouldn't change afterwards
+    fn access(&self) -> Access;
+    fn run(&mut self, world: &World);
+}
+
+struct ScheduledSystem {
+    system: Box<dyn System>,
+    access: Access,
+}
+
+// Systems of a stage are split into batches that run one after the other, everything in a batch runs in
+// parallel on the schedule's thread pool. A system goes into the batch after the last one holding
+// something it conflicts with, so conflicting systems always run in the order they were added.
+pub struct Schedule {
+    stages: Vec<(Stage, Vec<ScheduledSystem>, Vec<Vec<usize>>)>,
+    pool: rayon::ThreadPool,
+}
+
+// every system in a batch gets the same world on its own thread
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<World>();
+};
+
+impl Schedule {
+    pub fn new() -> Result<Self, String> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .thread_name(|index| format!("system-{}", index))
+            .build()
+            .map_err(|e| format!("can't create system thread pool: {}", e))?;
+
+        Ok(Self {
+            stages: STAGES.iter().map(|stage| (*stage, vec![], vec![])).collect(),
+            pool,
+        })
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, stage: Stage, system: S) {
+        let (_, systems, batches) = self.stages
+            .iter_mut()
+            .find(|(existing, _, _)| *existing == stage)
+            .unwrap();
+
+        let access = system.access();
+        let index = systems.len();
+
+        let first_free_batch = batches
+            .iter()
+            .rposition(|batch| batch.iter().any(|other: &usize| systems[*other].access.conflicts_with(&access)))
+            .map_or(0, |conflicting| conflicting + 1);
+
+        match batches.get_mut(first_free_batch) {
+            Some(batch) => batch.push(index),
+            None => batches.push(vec![index]),
+        }
+
+        log::debug!("scheduled system {} in {:?} batch {}", system.name(), stage, first_free_batch);
+        systems.push(ScheduledSystem { system: Box::new(system), access });
+    }
+
+    pub fn with_system<S: System + 'static>(mut self, stage: Stage, system: S) -> Self {
+        self.add_system(stage, system);
+        self
+    }
+
+    pub fn run(&mut self, world: &World) {
+        let pool = &self.pool;
+
+        for (_, systems, batches) in self.stages.iter_mut() {
+            for batch in batches.iter() {
+                let mut batch_systems = systems
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(index, _)| batch.contains(index))
+                    .map(|(_, scheduled)| &mut scheduled.system)
+                    .collect::<Vec<&mut Box<dyn System>>>();
+
+                // not worth a trip to the pool
+                if batch_systems.len() == 1 {
+                    batch_systems[0].run(world);
+                    continue;
+                }
+
+                pool.scope(|scope| {
+                    for system in batch_systems.drain(..) {
+                        scope.spawn(move |_| system.run(world));
+                    }
+                });
+            }
+        }
+    }
+}
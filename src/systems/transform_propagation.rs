@@ -0,0 +1,96 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::components::children::Children;
+use crate::components::global_transform::GlobalTransform;
+use crate::components::parent::Parent;
+use crate::components::transform::Transform;
+use crate::systems::schedule::{Access, System};
+
+use legion::{Entity, World};
+use legion::query::{Read, Write, IntoQuery, Query};
+
+// Works out every GlobalTransform from the roots down, each one is its parent's GlobalTransform times
+// its own Transform. An entity whose parent has no Transform (or is gone) counts as a root. Also
+// refreshes the Children of every entity that has them.
+pub struct TransformPropagation;
+
+impl TransformPropagation {
+    pub fn new() -> Self {
+        TransformPropagation
+    }
+}
+
+impl System for TransformPropagation {
+    fn name(&self) -> &str {
+        "transform_propagation"
+    }
+
+    fn access(&self) -> Access {
+        Access::new()
+            .reads::<Transform>()
+            .reads::<Parent>()
+            .writes::<Children>()
+            .writes::<GlobalTransform>()
+    }
+
+    fn run(&mut self, world: &World) {
+        let locals = <Read<Transform>>::query()
+            .iter_entities(world)
+            .map(|(entity, transform)| (entity, transform.matrix()))
+            .collect::<HashMap<Entity, Matrix4<f32>>>();
+
+        let parents = <Read<Parent>>::query()
+            .iter_entities(world)
+            .map(|(entity, parent)| (entity, parent.0))
+            .collect::<Vec<(Entity, Entity)>>();
+
+        let mut children = HashMap::<Entity, Vec<Entity>>::new();
+        for (child, parent) in parents.iter() {
+            children.entry(*parent).or_insert_with(Vec::new).push(*child);
+        }
+
+        for (entity, entity_children) in <Write<Children>>::query().iter_entities(world) {
+            entity_children.0 = children.get(&entity).cloned().unwrap_or_default();
+        }
+
+        let parented = parents
+            .iter()
+            .filter(|(_, parent)| locals.contains_key(parent))
+            .map(|(child, _)| *child)
+            .collect::<HashSet<Entity>>();
+
+        let mut globals = HashMap::<Entity, Matrix4<f32>>::with_capacity(locals.len());
+        let mut stack = locals
+            .keys()
+            .filter(|entity| !parented.contains(entity))
+            .map(|entity| (*entity, Matrix4::identity()))
+            .collect::<Vec<(Entity, Matrix4<f32>)>>();
+
+        while let Some((entity, parent_global)) = stack.pop() {
+            let global = parent_global * locals[&entity];
+            globals.insert(entity, global);
+
+            for child in children.get(&entity).into_iter().flatten() {
+                if locals.contains_key(child) && !globals.contains_key(child) {
+                    stack.push((*child, global));
+                }
+            }
+        }
+
+        // whatever wasn't reached from a root is parented in a loop
+        for (entity, local) in locals.iter() {
+            if !globals.contains_key(entity) {
+                log::warn!("{:?} is its own ancestor, ignoring its parent", entity);
+                globals.insert(*entity, *local);
+            }
+        }
+
+        for (entity, global) in <Write<GlobalTransform>>::query().iter_entities(world) {
+            if let Some(matrix) = globals.get(&entity) {
+                global.0 = *matrix;
+            }
+        }
+    }
+}